@@ -0,0 +1,561 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{notifier::Notifier, state::StateStore};
+
+/// Key of the tripped circuit breakers in the state file.
+const CIRCUIT_BREAKER_KEY: &str = "circuit_breaker";
+
+/// State of a funding wallet as last seen by a strategy.
+#[derive(Debug, Default, Clone, Copy)]
+struct Deployment {
+    balance: f64,
+    deployed: f64,
+}
+
+/// Drop of the funding balance of an account that tripped the circuit breaker.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Trip {
+    /// UNIX timestamp (in seconds) of the drop.
+    timestamp: u64,
+    previous_balance: f64,
+    balance: f64,
+}
+
+/// Halt the lending on the accounts whose funding balance drops unexpectedly within a cycle,
+/// which should never happen when lending and might reveal a compromised account or a margin
+/// liquidation.
+///
+/// The tripped breakers are persisted, so the lending stays halted across restarts until they
+/// are reset with `--reset-circuit-breaker` while the bot is stopped.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    /// Fraction of the balance which, lost between two cycles, trips the breaker.
+    max_balance_drop_percent: f64,
+    state: StateStore,
+    notifier: Notifier,
+    /// Tripped breakers already logged in the current cycle.
+    logged: Arc<Mutex<HashSet<String>>>,
+}
+
+impl CircuitBreaker {
+    /// Create the breaker, notifying the trips with `notifier`.
+    pub fn new(max_balance_drop_percent: f64, state: StateStore, notifier: Notifier) -> Self {
+        Self {
+            max_balance_drop_percent,
+            state,
+            notifier,
+            logged: Default::default(),
+        }
+    }
+
+    /// Log the tripped breakers again, to be called at the start of each cycle.
+    fn new_cycle(&self) {
+        self.logged.lock().unwrap().clear();
+    }
+
+    /// Reset all the tripped breakers, resuming the lending.
+    pub fn reset(state: &StateStore) -> Result<()> {
+        state.set(CIRCUIT_BREAKER_KEY, &HashMap::<String, Trip>::new())
+    }
+
+    fn trips(&self) -> HashMap<String, Trip> {
+        self.state.get(CIRCUIT_BREAKER_KEY).unwrap_or_default()
+    }
+
+    /// Trip the breaker of the account and currency if its balance dropped too much since the
+    /// previous cycle.
+    fn check(&self, keys: &str, currency: &str, previous_balance: f64, balance: f64) {
+        if previous_balance <= 0.
+            || balance >= previous_balance * (1. - self.max_balance_drop_percent)
+        {
+            return;
+        }
+
+        let key = trip_key(keys, currency);
+        let mut trips = self.trips();
+        trips.insert(
+            key.clone(),
+            Trip {
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_secs()),
+                previous_balance,
+                balance,
+            },
+        );
+
+        let message = format!(
+            "CIRCUIT BREAKER TRIPPED: the {currency} funding balance of {keys} dropped from \
+             {previous_balance:.2} to {balance:.2}, halting its new offers until reset with \
+             --reset-circuit-breaker"
+        );
+        log::error!("{message}");
+        self.notifier.notify(&message);
+        self.logged.lock().unwrap().insert(key);
+
+        if let Err(e) = self.state.set(CIRCUIT_BREAKER_KEY, &trips) {
+            log::error!("Failed to persist the circuit breaker: {e:#}");
+        }
+    }
+
+    /// Return whether the breaker of the account and currency is tripped, logging it once per
+    /// cycle if so.
+    fn is_tripped(&self, keys: &str, currency: &str) -> bool {
+        let key = trip_key(keys, currency);
+        let Some(trip) = self.trips().remove(&key) else {
+            return false;
+        };
+        if !self.logged.lock().unwrap().insert(key) {
+            return true;
+        }
+
+        log::error!(
+            "Circuit breaker tripped on the {currency} funding of {keys} (balance dropped from \
+             {:.2} to {:.2} at {}), no new offer until reset with --reset-circuit-breaker",
+            trip.previous_balance,
+            trip.balance,
+            trip.timestamp
+        );
+        true
+    }
+}
+
+fn trip_key(keys: &str, currency: &str) -> String {
+    format!("{keys}.{currency}")
+}
+
+/// Global cap on the capital deployed (lent or offered) across all the strategies.
+///
+/// The accounting is done per currency: the deployed amounts of every account lending a currency
+/// are compared against `max_total_deployed_percent` of their summed balances.
+#[derive(Debug, Clone)]
+pub struct DeploymentBudget {
+    max_total_deployed_percent: Option<f64>,
+    circuit_breaker: Option<CircuitBreaker>,
+    /// Deployments indexed by currency, then by account keys.
+    deployments: Arc<Mutex<HashMap<String, HashMap<String, Deployment>>>>,
+}
+
+impl DeploymentBudget {
+    pub fn new(
+        max_total_deployed_percent: Option<f64>,
+        circuit_breaker: Option<CircuitBreaker>,
+    ) -> Self {
+        Self {
+            max_total_deployed_percent,
+            circuit_breaker,
+            deployments: Default::default(),
+        }
+    }
+
+    /// Log the tripped circuit breakers again, to be called at the start of each cycle.
+    pub fn new_cycle(&self) {
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            circuit_breaker.new_cycle();
+        }
+    }
+
+    /// Return a handle on the budget bound to the given account and currency.
+    pub fn account(&self, keys: &str, currency: &str) -> AccountBudget {
+        AccountBudget {
+            budget: self.clone(),
+            keys: keys.to_string(),
+            currency: currency.to_string(),
+        }
+    }
+}
+
+/// Handle used by a strategy to report its deployments to the global budget.
+#[derive(Debug, Clone)]
+pub struct AccountBudget {
+    budget: DeploymentBudget,
+    keys: String,
+    currency: String,
+}
+
+impl AccountBudget {
+    /// Record the current state of the funding wallet, `deployed` being the part of the balance
+    /// already lent or offered that the strategy is not about to reallocate.
+    pub fn update(&self, balance: f64, deployed: f64) {
+        let previous = self
+            .budget
+            .deployments
+            .lock()
+            .unwrap()
+            .entry(self.currency.clone())
+            .or_default()
+            .insert(self.keys.clone(), Deployment { balance, deployed });
+
+        if let (Some(circuit_breaker), Some(previous)) = (&self.budget.circuit_breaker, previous) {
+            circuit_breaker.check(&self.keys, &self.currency, previous.balance, balance);
+        }
+    }
+
+    /// Return the amount that can still be deployed on the currency without exceeding the cap,
+    /// nothing if the circuit breaker of the account is tripped.
+    pub fn remaining(&self) -> f64 {
+        if let Some(circuit_breaker) = &self.budget.circuit_breaker {
+            if circuit_breaker.is_tripped(&self.keys, &self.currency) {
+                return 0.;
+            }
+        }
+
+        let Some(max_total_deployed_percent) = self.budget.max_total_deployed_percent else {
+            return f64::INFINITY;
+        };
+
+        let deployments = self.budget.deployments.lock().unwrap();
+        let (balance, deployed) = deployments
+            .get(&self.currency)
+            .map(|accounts| {
+                accounts
+                    .values()
+                    .fold((0., 0.), |(balance, deployed), deployment| {
+                        (balance + deployment.balance, deployed + deployment.deployed)
+                    })
+            })
+            .unwrap_or_default();
+
+        (balance * max_total_deployed_percent - deployed).max(0.)
+    }
+
+    /// Record `amount` as newly deployed.
+    pub fn deploy(&self, amount: f64) {
+        let mut deployments = self.budget.deployments.lock().unwrap();
+        deployments
+            .entry(self.currency.clone())
+            .or_default()
+            .entry(self.keys.clone())
+            .or_default()
+            .deployed += amount;
+    }
+}
+
+/// Offers of a strategy on an account, as last seen.
+#[derive(Debug, Default, Clone, Copy)]
+struct OfferCount {
+    priority: u8,
+    active: usize,
+    wanted: usize,
+}
+
+/// Cap on the number of active offers of each account across all the strategies lending with
+/// its keys, Bitfinex rejecting the offers beyond its own limit.
+///
+/// The slots are allocated by priority: a strategy can only use the slots neither held by the
+/// other strategies of the account nor wanted by the ones of higher priority, and cancels its
+/// active offers occupying the slots wanted by the ones of higher priority.
+#[derive(Debug, Clone)]
+pub struct OfferLimit {
+    max_active_offers: Option<usize>,
+    /// Offer counts indexed by account keys, then by strategy.
+    counts: Arc<Mutex<HashMap<String, HashMap<String, OfferCount>>>>,
+}
+
+impl OfferLimit {
+    pub fn new(max_active_offers: Option<usize>) -> Self {
+        Self {
+            max_active_offers,
+            counts: Default::default(),
+        }
+    }
+
+    /// Return whether the number of active offers is capped.
+    pub fn is_enabled(&self) -> bool {
+        self.max_active_offers.is_some()
+    }
+
+    /// Return a handle on the limit bound to the given account and strategy.
+    pub fn account(&self, keys: &str, strategy: &str, priority: u8) -> AccountOffers {
+        AccountOffers {
+            limit: self.clone(),
+            keys: keys.to_string(),
+            strategy: strategy.to_string(),
+            priority,
+        }
+    }
+}
+
+/// Handle used by a strategy to report its offers to the account-wide limit.
+#[derive(Debug, Clone)]
+pub struct AccountOffers {
+    limit: OfferLimit,
+    keys: String,
+    strategy: String,
+    priority: u8,
+}
+
+impl AccountOffers {
+    /// Record the number of offers the strategy has `active` on the account, and `wanted` once
+    /// reconciled for the cycle.
+    pub fn update(&self, active: usize, wanted: usize) {
+        self.limit
+            .counts
+            .lock()
+            .unwrap()
+            .entry(self.keys.clone())
+            .or_default()
+            .insert(
+                self.strategy.clone(),
+                OfferCount {
+                    priority: self.priority,
+                    active,
+                    wanted,
+                },
+            );
+    }
+
+    /// Return how many offers the strategy may have active, at most the number it wants.
+    pub fn allowed(&self) -> usize {
+        let counts = self.limit.counts.lock().unwrap();
+        let Some(account) = counts.get(&self.keys) else {
+            return 0;
+        };
+        let wanted = account
+            .get(&self.strategy)
+            .map_or(0, |offer_count| offer_count.wanted);
+
+        let Some(max_active_offers) = self.limit.max_active_offers else {
+            return wanted;
+        };
+
+        let held: usize = account
+            .iter()
+            .filter(|(strategy, _)| **strategy != self.strategy)
+            .map(|(_, offer_count)| {
+                if offer_count.priority > self.priority {
+                    offer_count.active.max(offer_count.wanted)
+                } else {
+                    offer_count.active
+                }
+            })
+            .sum();
+
+        max_active_offers.saturating_sub(held).min(wanted)
+    }
+
+    /// Return how many of its active offers the strategy must cancel to yield their slots to the
+    /// strategies of higher priority of the account, which would otherwise wait for them forever.
+    /// The ties are broken by strategy name.
+    pub fn preempted(&self) -> usize {
+        let Some(max_active_offers) = self.limit.max_active_offers else {
+            return 0;
+        };
+        let counts = self.limit.counts.lock().unwrap();
+        let Some(account) = counts.get(&self.keys) else {
+            return 0;
+        };
+        let active = account
+            .get(&self.strategy)
+            .map_or(0, |offer_count| offer_count.active);
+
+        // The strategies of lower priority yield their own slots.
+        let rank = (self.priority, self.strategy.as_str());
+        let held: usize = account
+            .iter()
+            .filter(|(strategy, offer_count)| (offer_count.priority, strategy.as_str()) > rank)
+            .map(|(_, offer_count)| offer_count.active.max(offer_count.wanted))
+            .sum();
+
+        active.saturating_sub(max_active_offers.saturating_sub(held))
+    }
+}
+
+/// New principal committed by a strategy on a UTC day.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct DailySpend {
+    /// Number of days since the UNIX epoch.
+    day: u64,
+    amount: f64,
+}
+
+/// Cap on the new principal a strategy may commit per UTC day, to contain the damage of a bad
+/// config or a runaway market.
+///
+/// The amount committed today is persisted, so restarting the bot does not reset it.
+#[derive(Debug, Clone)]
+pub struct DailyBudget {
+    /// Maximum new principal per UTC day.
+    limit: f64,
+    key: String,
+    state: StateStore,
+}
+
+impl DailyBudget {
+    pub fn new(limit: f64, strategy: &str, currency: &str, state: StateStore) -> Self {
+        Self {
+            limit,
+            key: format!("daily_budget.{strategy}.{currency}"),
+            state,
+        }
+    }
+
+    pub fn limit(&self) -> f64 {
+        self.limit
+    }
+
+    /// Return the new principal committed on the day of the UNIX timestamp `now`.
+    pub fn spent(&self, now: u64) -> f64 {
+        let spend: DailySpend = self.state.get(&self.key).unwrap_or_default();
+
+        if spend.day == now / 86400 {
+            spend.amount
+        } else {
+            0.
+        }
+    }
+
+    /// Return the new principal that can still be committed on the day of `now`.
+    pub fn remaining(&self, now: u64) -> f64 {
+        (self.limit - self.spent(now)).max(0.)
+    }
+
+    /// Record `amount` of new principal committed at `now`.
+    pub fn spend(&self, amount: f64, now: u64) -> Result<()> {
+        self.state.set(
+            &self.key,
+            &DailySpend {
+                day: now / 86400,
+                amount: self.spent(now) + amount,
+            },
+        )
+    }
+}
+
+impl fmt::Display for DailyBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+
+        write!(f, "{:.2} of {:.2} spent today", self.spent(now), self.limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[test]
+    fn circuit_breaker_trips_on_a_balance_drop() {
+        let path = env::temp_dir().join(format!("circuit_breaker_{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let circuit_breaker = CircuitBreaker::new(
+            0.2,
+            StateStore::load(path.to_str().unwrap()),
+            Notifier::default(),
+        );
+
+        circuit_breaker.check("KEYS", "USD", 1000., 900.);
+        assert!(!circuit_breaker.is_tripped("KEYS", "USD"));
+
+        circuit_breaker.check("KEYS", "USD", 1000., 700.);
+        assert!(circuit_breaker.is_tripped("KEYS", "USD"));
+        assert!(circuit_breaker.is_tripped("KEYS", "USD"));
+        assert!(!circuit_breaker.is_tripped("KEYS", "EUR"));
+        // Logged once in the cycle, then again in the next one.
+        assert_eq!(circuit_breaker.logged.lock().unwrap().len(), 1);
+        circuit_breaker.new_cycle();
+        assert!(circuit_breaker.logged.lock().unwrap().is_empty());
+        assert!(circuit_breaker.is_tripped("KEYS", "USD"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn daily_budget_rolls_over_at_midnight_utc() {
+        let path = env::temp_dir().join(format!("daily_budget_{}.json", std::process::id()));
+        let daily_budget = DailyBudget::new(
+            1000.,
+            "strategy",
+            "USD",
+            StateStore::load(path.to_str().unwrap()),
+        );
+
+        // 2023-01-01 23:00 UTC.
+        let now = 19_358 * 86400 + 23 * 3600;
+        assert_eq!(daily_budget.remaining(now), 1000.);
+
+        daily_budget.spend(300., now).unwrap();
+        daily_budget.spend(500., now + 1800).unwrap();
+        assert_eq!(daily_budget.spent(now + 1800), 800.);
+        assert_eq!(daily_budget.remaining(now + 1800), 200.);
+
+        // The next UTC day starts afresh.
+        assert_eq!(daily_budget.remaining(now + 3600), 1000.);
+        daily_budget.spend(100., now + 3600).unwrap();
+        assert_eq!(daily_budget.spent(now + 3600), 100.);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn offer_slots_are_allocated_by_priority() {
+        let offer_limit = OfferLimit::new(Some(5));
+        let high = offer_limit.account("KEYS", "high", 2);
+        let low = offer_limit.account("KEYS", "low", 1);
+        let other_account = offer_limit.account("OTHER", "other", 0);
+
+        // Nothing reported yet.
+        assert_eq!(low.allowed(), 0);
+
+        low.update(0, 4);
+        assert_eq!(low.allowed(), 4);
+        low.update(4, 4);
+
+        // The slots held by the strategies of lower priority are not available until yielded.
+        high.update(0, 3);
+        assert_eq!(high.allowed(), 1);
+
+        // The wanted slots of higher priority are reserved.
+        assert_eq!(low.allowed(), 2);
+
+        // The other accounts have their own slots.
+        other_account.update(0, 5);
+        assert_eq!(other_account.allowed(), 5);
+    }
+
+    #[test]
+    fn offer_slots_are_unlimited_when_disabled() {
+        let offer_limit = OfferLimit::new(None);
+        let strategy = offer_limit.account("KEYS", "strategy", 0);
+
+        assert!(!offer_limit.is_enabled());
+        strategy.update(10, 30);
+        assert_eq!(strategy.allowed(), 30);
+        assert_eq!(strategy.preempted(), 0);
+    }
+
+    #[test]
+    fn active_offers_are_preempted_by_higher_priority() {
+        let offer_limit = OfferLimit::new(Some(5));
+        let high = offer_limit.account("KEYS", "high", 2);
+        let peer = offer_limit.account("KEYS", "peer", 1);
+        let low = offer_limit.account("KEYS", "low", 1);
+
+        low.update(4, 4);
+        peer.update(1, 1);
+        assert_eq!(low.preempted(), 0);
+
+        // The higher priority wants 3 slots: the lower ones yield 3, the tie being broken by name.
+        high.update(0, 3);
+        assert_eq!(low.preempted(), 3);
+        assert_eq!(peer.preempted(), 0);
+        assert_eq!(high.preempted(), 0);
+
+        // Once yielded, the higher priority gets them.
+        low.update(1, 1);
+        assert_eq!(low.preempted(), 0);
+        assert_eq!(high.allowed(), 3);
+    }
+}
@@ -0,0 +1,331 @@
+use std::{env, fmt, fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
+
+/// Fields of the config holding mappings keyed by a period, an hour or a window, in numbers.
+const NUMBER_KEYED_FIELDS: [&str; 5] = [
+    "period_premium",
+    "hourly_premium",
+    "blended_windows",
+    "allocation",
+    "min_rates",
+];
+
+/// Config files deep-merged in order, the later ones overriding the earlier ones field by field.
+#[derive(Debug, Clone)]
+pub struct ConfigFiles {
+    paths: Vec<String>,
+}
+
+impl ConfigFiles {
+    pub fn new(paths: Vec<String>) -> Self {
+        Self { paths }
+    }
+}
+
+impl fmt::Display for ConfigFiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.paths.join(", "))
+    }
+}
+
+/// Format of a config file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl Format {
+    /// Detect the format of the config file at `path` from its extension.
+    fn from_path(path: &str) -> Result<Self> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+
+        match extension.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => Ok(Self::Yaml),
+            "toml" => Ok(Self::Toml),
+            "json" => Ok(Self::Json),
+            _ => bail!("Unsupported config format of {path}, expected .yaml, .yml, .toml or .json"),
+        }
+    }
+}
+
+/// Return `content` with the `${VAR}` tokens substituted with the environment variables,
+/// failing if one of them is unset.
+///
+/// The comment lines (starting with `#`) are left as is, so that a commented-out setting needs no
+/// variable, but the comments trailing a value are not recognized.
+fn interpolate(content: &str, var: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut interpolated = String::with_capacity(content.len());
+
+    for line in content.split_inclusive('\n') {
+        if line.trim_start().starts_with('#') {
+            interpolated.push_str(line);
+            continue;
+        }
+
+        let mut rest = line;
+        while let Some(start) = rest.find("${") {
+            let end = rest[start..]
+                .find('}')
+                .with_context(|| format!("Unterminated variable in {}", line.trim()))?;
+            let name = &rest[start + 2..start + end];
+            let value =
+                var(name).with_context(|| format!("Environment variable {name} is unset"))?;
+
+            interpolated.push_str(&rest[..start]);
+            interpolated.push_str(&value);
+            rest = &rest[start + end + 1..];
+        }
+        interpolated.push_str(rest);
+    }
+
+    Ok(interpolated)
+}
+
+/// Merge `overlay` into `base`: the mappings are merged key by key, recursively, any other value
+/// of `overlay` replacing the one of `base`.
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Turn the keys of the `NUMBER_KEYED_FIELDS` mappings of `value`, always strings in TOML and
+/// JSON, into numbers as in YAML, so that they deserialize into the maps keyed by period or hour.
+/// The other keys, e.g. the strategy names, are left as is.
+fn normalize_keys(value: Value) -> Value {
+    match value {
+        Value::Mapping(mapping) => Value::Mapping(
+            mapping
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = match (&key, value) {
+                        (Value::String(field), Value::Mapping(mapping))
+                            if NUMBER_KEYED_FIELDS.contains(&field.as_str()) =>
+                        {
+                            Value::Mapping(
+                                mapping
+                                    .into_iter()
+                                    .map(|(key, value)| (number_key(key), value))
+                                    .collect(),
+                            )
+                        }
+                        (_, value) => normalize_keys(value),
+                    };
+
+                    (key, value)
+                })
+                .collect(),
+        ),
+        Value::Sequence(sequence) => {
+            Value::Sequence(sequence.into_iter().map(normalize_keys).collect())
+        }
+        value => value,
+    }
+}
+
+/// Return `key` as a number if it is the string of an integer.
+fn number_key(key: Value) -> Value {
+    match key {
+        Value::String(key) => match key.parse::<u64>() {
+            Ok(number) => Value::Number(number.into()),
+            Err(_) => Value::String(key),
+        },
+        key => key,
+    }
+}
+
+/// Parse the config `content` in `format`.
+fn parse(content: &str, format: Format) -> Result<Value> {
+    let value = match format {
+        Format::Yaml => serde_yaml::from_str(content)?,
+        Format::Toml => normalize_keys(toml::from_str(content)?),
+        Format::Json => normalize_keys(serde_json::from_str(content)?),
+    };
+
+    Ok(value)
+}
+
+/// Read and parse the config file at `path`, in the format given by its extension, after
+/// substituting its `${VAR}` tokens with the environment variables.
+fn read_file(path: &str) -> Result<Value> {
+    let format = Format::from_path(path)?;
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?;
+    let content = interpolate(&content, |name| env::var(name).ok())
+        .with_context(|| format!("Failed to interpolate {path}"))?;
+
+    parse(&content, format).with_context(|| format!("Failed to parse {path}"))
+}
+
+/// Read the config `files`, deep-merge them in order and deserialize the result.
+pub fn read<T: DeserializeOwned>(files: &ConfigFiles) -> Result<T> {
+    let mut config = Value::Mapping(Mapping::new());
+    for path in &files.paths {
+        merge(&mut config, read_file(path)?);
+    }
+
+    let config =
+        serde_yaml::from_value(config).with_context(|| format!("Failed to parse {files}"))?;
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[test]
+    fn format_is_detected_from_the_extension() {
+        assert_eq!(Format::from_path("config.yaml").unwrap(), Format::Yaml);
+        assert_eq!(Format::from_path("config/prod.YML").unwrap(), Format::Yaml);
+        assert_eq!(Format::from_path("config.toml").unwrap(), Format::Toml);
+        assert_eq!(Format::from_path("config.json").unwrap(), Format::Json);
+        assert!(Format::from_path("config.ini").is_err());
+        assert!(Format::from_path("config").is_err());
+    }
+
+    fn var(name: &str) -> Option<String> {
+        (name == "API_KEY").then(|| "secret".to_string())
+    }
+
+    #[test]
+    fn variables_are_substituted() {
+        assert_eq!(
+            interpolate("keys:\n  api_key: ${API_KEY}\n", var).unwrap(),
+            "keys:\n  api_key: secret\n"
+        );
+        assert_eq!(
+            interpolate("key: ${API_KEY}-${API_KEY}", var).unwrap(),
+            "key: secret-secret"
+        );
+        assert_eq!(interpolate("key: $API_KEY", var).unwrap(), "key: $API_KEY");
+    }
+
+    #[test]
+    fn unset_or_unterminated_variables_fail() {
+        assert!(interpolate("key: ${UNSET}", var).is_err());
+        assert!(interpolate("key: ${API_KEY\nother: }", var).is_err());
+    }
+
+    #[test]
+    fn comment_lines_are_not_interpolated() {
+        let content = "# key: ${UNSET}\n  # other: ${UNSET}\nkey: ${API_KEY}\n";
+
+        assert_eq!(
+            interpolate(content, var).unwrap(),
+            "# key: ${UNSET}\n  # other: ${UNSET}\nkey: secret\n"
+        );
+    }
+
+    #[test]
+    fn mappings_are_merged_recursively() {
+        let mut base = parse("a:\n  b: 1\n  c: [1, 2]\nd: base\n", Format::Yaml).unwrap();
+        let overlay = parse("a:\n  c: [3]\n  e: 4\nf: overlay\n", Format::Yaml).unwrap();
+
+        merge(&mut base, overlay);
+
+        assert_eq!(
+            base,
+            parse(
+                "a:\n  b: 1\n  c: [3]\n  e: 4\nd: base\nf: overlay\n",
+                Format::Yaml
+            )
+            .unwrap()
+        );
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Strategy {
+        currency: String,
+        period_premium: BTreeMap<u8, f64>,
+        windows: Vec<u64>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Config {
+        strategies: BTreeMap<String, Strategy>,
+    }
+
+    fn assert_parses(content: &str, format: Format) {
+        let config: Config = serde_yaml::from_value(parse(content, format).unwrap()).unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                strategies: BTreeMap::from([(
+                    "usd".to_string(),
+                    Strategy {
+                        currency: "USD".to_string(),
+                        period_premium: BTreeMap::from([(2, 0.1), (30, 1.5)]),
+                        windows: vec![60, 3600],
+                    }
+                )]),
+            }
+        );
+    }
+
+    #[test]
+    fn yaml_config_round_trip() {
+        assert_parses(
+            "strategies:\n  usd:\n    currency: USD\n    period_premium:\n      2: 0.1\n      \
+             30: 1.5\n    windows: [60, 3600]\n",
+            Format::Yaml,
+        );
+    }
+
+    #[test]
+    fn toml_config_round_trip() {
+        assert_parses(
+            "[strategies.usd]\ncurrency = \"USD\"\nwindows = [60, 3600]\n\n\
+             [strategies.usd.period_premium]\n2 = 0.1\n30 = 1.5\n",
+            Format::Toml,
+        );
+    }
+
+    #[test]
+    fn only_the_number_keyed_fields_get_number_keys() {
+        let value = parse(
+            r#"{"strategies": {"2024": {"currency": "USD", "period_premium": {"2": 0.1, "30": 1.5},
+                "windows": [60, 3600]}}}"#,
+            Format::Json,
+        )
+        .unwrap();
+        let config: Config = serde_yaml::from_value(value).unwrap();
+
+        let strategy = &config.strategies["2024"];
+        assert_eq!(
+            strategy.period_premium,
+            BTreeMap::from([(2, 0.1), (30, 1.5)])
+        );
+    }
+
+    #[test]
+    fn json_config_round_trip() {
+        assert_parses(
+            r#"{"strategies": {"usd": {"currency": "USD", "period_premium": {"2": 0.1, "30": 1.5},
+                "windows": [60, 3600]}}}"#,
+            Format::Json,
+        );
+    }
+}
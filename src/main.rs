@@ -1,8 +1,324 @@
-use std::time::Duration;
+use std::{collections::HashMap, process, sync::Mutex, time::Duration};
 
+mod budget;
+mod config;
+mod notifier;
+mod phase;
+mod price_feed;
+mod skips;
+mod state;
 mod strategies;
+use anyhow::{ensure, Result};
+use budget::{CircuitBreaker, DeploymentBudget, OfferLimit};
+use clap::Parser;
+use config::ConfigFiles;
 use dotenv::dotenv;
-use strategies::{simple_strategy::SimpleStrategy, Strategy};
+use notifier::Notifier;
+use phase::{phase_offset, validate_phase_jitter, validate_phase_offsets};
+use price_feed::{PriceFeed, PriceFeedConfig};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+use skips::SkipStats;
+use state::StateStore;
+use strategies::{error::BitfinexError, precision::Precisions, report, DynStrategy, Shared};
+use tokio::{signal, time::Instant};
+
+const CONFIG_PATH: &str = "./config.yaml";
+
+/// Interval between two summaries of the skipped cycles.
+const SKIPS_SUMMARY_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// Config file, in YAML, TOML or JSON depending on its extension. Repeat it to deep-merge
+    /// several files in order, the later ones overriding the earlier ones field by field.
+    #[arg(long, default_value = CONFIG_PATH)]
+    config: Vec<String>,
+    /// Check the config and the env variables, then exit without connecting to Bitfinex.
+    #[arg(long)]
+    validate_config: bool,
+    /// Log the wallets, active offers and credits of the accounts of the config, then exit
+    /// without touching anything.
+    #[arg(long)]
+    report: bool,
+    /// Print the active offers of the strategies of the config, then exit without touching
+    /// anything.
+    #[arg(long)]
+    list_offers: bool,
+    /// Cancel the active offer with this ID on the markets of `--strategy`, then exit.
+    #[arg(long, requires = "strategy")]
+    cancel_offer: Option<u64>,
+    /// Strategy whose offer `--cancel-offer` cancels.
+    #[arg(long, requires = "cancel_offer")]
+    strategy: Option<String>,
+    /// Resume the lending on the accounts halted by the circuit breaker, then exit.
+    #[arg(long)]
+    reset_circuit_breaker: bool,
+}
+
+/// Settings shared by all the strategies.
+#[derive(Debug, Deserialize)]
+struct Config {
+    /// Maximum fraction of the balance of each currency that can be lent or offered at once.
+    max_total_deployed_percent: Option<f64>,
+    /// Halt the new offers of an account on a currency whose funding balance drops by more than
+    /// this fraction between two cycles.
+    max_balance_drop_percent: Option<f64>,
+    /// Maximum number of active offers of each account across its strategies.
+    max_active_offers: Option<usize>,
+    /// Cancel the offers submitted by the strategies when the bot is stopped.
+    #[serde(default)]
+    cancel_on_shutdown: bool,
+    /// Log the report of `--report` at startup, before the strategies start acting.
+    #[serde(default)]
+    startup_report: bool,
+    /// Interval, in seconds, between two cycles.
+    #[serde(default = "default_poll_interval_secs")]
+    poll_interval_secs: u64,
+    /// Run each strategy at its own phase within the poll interval instead of all of them at
+    /// the start of the cycle.
+    #[serde(default)]
+    phased_execution: bool,
+    /// Phase offset, in seconds, of the strategies by name, derived from the hash of their name
+    /// within the poll interval less the maximum jitter if not set.
+    #[serde(default)]
+    phase_offsets_secs: HashMap<String, u64>,
+    /// Maximum random delay, in seconds, before executing each strategy.
+    #[serde(default)]
+    max_jitter_secs: f64,
+    /// Seed of the random delays, to make the runs reproducible. Seeded from the entropy of the
+    /// system if not set.
+    rng_seed: Option<u64>,
+    /// Maximum time, in seconds, a strategy is given to execute before it is skipped for the
+    /// cycle.
+    #[serde(default = "default_execute_timeout_secs")]
+    execute_timeout_secs: u64,
+    /// File where the state of the strategies is persisted across restarts.
+    #[serde(default = "default_state_file")]
+    state_file: String,
+    /// Shell command run on the critical events, e.g. a rate refused by the sanity ceiling, with
+    /// the message in the `NOTIFY_MESSAGE` env variable.
+    notify_command: Option<String>,
+    /// Convert the amounts to a common quote currency in the logs.
+    price_feed: Option<PriceFeedConfig>,
+    /// Decimals of the rates and amounts accepted by the exchange for each currency, 8 if not
+    /// set.
+    #[serde(default)]
+    precision: Precisions,
+}
+
+fn default_poll_interval_secs() -> u64 {
+    60
+}
+
+fn default_execute_timeout_secs() -> u64 {
+    30
+}
+
+fn default_state_file() -> String {
+    "./state.json".to_string()
+}
+
+/// All the strategies declared in the config.
+struct Strategies {
+    all: Vec<DynStrategy>,
+    /// Phase offset of each strategy within the cycle, in their order of execution.
+    phases: Vec<Duration>,
+    poll_interval: Duration,
+    phased_execution: bool,
+    cancel_on_shutdown: bool,
+    startup_report: bool,
+    max_jitter: Duration,
+    rng: Mutex<StdRng>,
+    execute_timeout: Duration,
+    budget: DeploymentBudget,
+    price_feed: PriceFeed,
+    state: StateStore,
+    skips: SkipStats,
+}
+
+impl Strategies {
+    /// Load and validate the strategies from the config `files`.
+    fn from_config(files: &ConfigFiles) -> Result<Self> {
+        let config: Config = config::read(files)?;
+
+        if let Some(max_total_deployed_percent) = config.max_total_deployed_percent {
+            ensure!(
+                max_total_deployed_percent > 0. && max_total_deployed_percent <= 1.,
+                "max_total_deployed_percent must be within (0, 1]"
+            );
+        }
+
+        if let Some(max_balance_drop_percent) = config.max_balance_drop_percent {
+            ensure!(
+                max_balance_drop_percent > 0. && max_balance_drop_percent < 1.,
+                "max_balance_drop_percent must be within (0, 1)"
+            );
+        }
+
+        if let Some(max_active_offers) = config.max_active_offers {
+            ensure!(max_active_offers > 0, "max_active_offers must be positive");
+        }
+
+        ensure!(
+            config.execute_timeout_secs > 0,
+            "execute_timeout_secs must be positive"
+        );
+
+        ensure!(
+            config.poll_interval_secs > 0,
+            "poll_interval_secs must be positive"
+        );
+        ensure!(
+            config.max_jitter_secs >= 0.
+                && config.max_jitter_secs < config.poll_interval_secs as f64,
+            "max_jitter_secs must be within [0, poll_interval_secs)"
+        );
+        let invalid_currencies = config.precision.invalid_currencies();
+        ensure!(
+            invalid_currencies.is_empty(),
+            "precision of {} must be within [0, 12] decimals",
+            invalid_currencies.join(", ")
+        );
+
+        let state = StateStore::load(&config.state_file);
+        let notifier = Notifier::new(config.notify_command);
+        let circuit_breaker = config
+            .max_balance_drop_percent
+            .map(|max_balance_drop_percent| {
+                CircuitBreaker::new(max_balance_drop_percent, state.clone(), notifier.clone())
+            });
+
+        let shared = Shared {
+            budget: DeploymentBudget::new(config.max_total_deployed_percent, circuit_breaker),
+            offer_limit: OfferLimit::new(config.max_active_offers),
+            state,
+            price_feed: PriceFeed::new(config.price_feed),
+            notifier,
+            skips: SkipStats::default(),
+            precisions: config.precision,
+        };
+
+        let poll_interval = Duration::from_secs(config.poll_interval_secs);
+        let max_jitter = Duration::from_secs_f64(config.max_jitter_secs);
+        // Leave room for the jitter after the hashed phases.
+        let phase_interval = (poll_interval - max_jitter).max(Duration::from_millis(1));
+        let mut all = strategies::from_config(files, &shared)?;
+        let names: Vec<&str> = all.iter().map(|strategy| strategy.name()).collect();
+        validate_phase_offsets(
+            &config.phase_offsets_secs,
+            config.poll_interval_secs,
+            &names,
+        )?;
+        let mut phases = vec![Duration::ZERO; all.len()];
+        if config.phased_execution {
+            let phase =
+                |strategy: &DynStrategy| match config.phase_offsets_secs.get(strategy.name()) {
+                    Some(phase_offset_secs) => Duration::from_secs(*phase_offset_secs),
+                    None => phase_offset(strategy.name(), phase_interval),
+                };
+            all.sort_by_key(phase);
+            phases = all.iter().map(phase).collect();
+        }
+        validate_phase_jitter(&phases, max_jitter, poll_interval)?;
+
+        Ok(Self {
+            all,
+            phases,
+            poll_interval,
+            phased_execution: config.phased_execution,
+            cancel_on_shutdown: config.cancel_on_shutdown,
+            startup_report: config.startup_report,
+            max_jitter,
+            rng: Mutex::new(match config.rng_seed {
+                Some(rng_seed) => StdRng::seed_from_u64(rng_seed),
+                None => StdRng::from_entropy(),
+            }),
+            execute_timeout: Duration::from_secs(config.execute_timeout_secs),
+            budget: shared.budget,
+            price_feed: shared.price_feed,
+            state: shared.state,
+            skips: shared.skips,
+        })
+    }
+
+    /// Print a one-line summary of each strategy.
+    fn summary(&self) {
+        for strategy in &self.all {
+            println!("{strategy}");
+        }
+    }
+
+    /// Execute each strategy in turn after a random delay of up to `max_jitter`, logging their
+    /// errors. The wallets are swept first so that new funds get lent within the same cycle.
+    ///
+    /// A strategy still executing after `execute_timeout` is skipped for the cycle, so that a
+    /// hung API call does not stall the others.
+    ///
+    /// With the phased execution, each strategy is run at its phase offset from the
+    /// `cycle_start` instead, in the order of their phases.
+    async fn execute(&self, cycle_start: Instant) {
+        self.budget.new_cycle();
+        self.price_feed.new_cycle();
+
+        for (strategy, phase) in self.all.iter().zip(&self.phases) {
+            tokio::time::sleep_until(cycle_start + *phase).await;
+
+            // Spread the API calls of the strategies across the cycle.
+            if !self.max_jitter.is_zero() {
+                let jitter = self.max_jitter.mul_f64(self.rng.lock().unwrap().gen());
+                tokio::time::sleep(jitter).await;
+            }
+
+            match tokio::time::timeout(self.execute_timeout, strategy.execute()).await {
+                Ok(Err(e)) => log_error(&e, &strategy.log_target()),
+                Err(_) => log::error!(
+                    target: &strategy.log_target(),
+                    "{} timed out after {}s, skipping it for this cycle",
+                    strategy.name(),
+                    self.execute_timeout.as_secs()
+                ),
+                Ok(Ok(())) => {}
+            }
+        }
+
+        self.skips.summarize_every(SKIPS_SUMMARY_INTERVAL);
+    }
+
+    /// Reconcile the persisted state of all the strategies, the ones failing to do so starting
+    /// over without it.
+    async fn reconcile(&self) {
+        for strategy in &self.all {
+            if let Err(e) = strategy.reconcile().await {
+                log::warn!(
+                    target: &strategy.log_target(),
+                    "{}: failed to reconcile the persisted state, ignoring it: {e:#}",
+                    strategy.name()
+                );
+            }
+        }
+    }
+
+    /// Return when the cycle after the one started at `cycle_start` starts: a poll interval after
+    /// its start with the phased execution, so that the phases do not drift, or after its end
+    /// otherwise.
+    fn next_cycle(&self, cycle_start: Instant) -> Instant {
+        if self.phased_execution {
+            cycle_start + self.poll_interval
+        } else {
+            Instant::now() + self.poll_interval
+        }
+    }
+
+    /// Cancel the offers of all the strategies, logging their errors.
+    async fn cancel_offers(&self) {
+        for strategy in &self.all {
+            if let Err(e) = strategy.cancel_offers().await {
+                log_error(&e, &strategy.log_target());
+            }
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() {
@@ -10,19 +326,122 @@ async fn main() {
 
     env_logger::builder()
         .filter_level(log::LevelFilter::Info)
+        // Allow `RUST_LOG=debug` to trace the decisions of the strategies, or
+        // `RUST_LOG=strategy::<name>=debug` the ones of a single strategy.
+        .parse_default_env()
         .format_timestamp_secs()
         .init();
 
-    let strategies = SimpleStrategy::from_config("./config.yaml");
+    let args = Args::parse();
+    let config_files = ConfigFiles::new(args.config);
+
+    let strategies = match Strategies::from_config(&config_files) {
+        Ok(strategies) => strategies,
+        Err(e) => {
+            eprintln!("Invalid config: {e:#}");
+            process::exit(1);
+        }
+    };
+
+    if args.validate_config {
+        strategies.summary();
+        println!("Config is valid");
+        return;
+    }
+
+    if args.reset_circuit_breaker {
+        if let Err(e) = CircuitBreaker::reset(&strategies.state) {
+            log_error(&e, module_path!());
+            process::exit(1);
+        }
+        log::info!("Circuit breaker reset, the lending resumes at the next start");
+        return;
+    }
+
+    if let (Some(id), Some(strategy)) = (args.cancel_offer, &args.strategy) {
+        if let Err(e) = report::cancel_offer(&config_files, strategy, id).await {
+            log_error(&e, module_path!());
+            process::exit(1);
+        }
+        return;
+    }
+
+    if args.list_offers {
+        if let Err(e) = report::list_offers(&config_files).await {
+            log_error(&e, module_path!());
+            process::exit(1);
+        }
+        return;
+    }
+
+    if args.report {
+        if let Err(e) = report::report(&config_files).await {
+            log_error(&e, module_path!());
+            process::exit(1);
+        }
+        return;
+    }
+
+    if strategies.startup_report {
+        if let Err(e) = report::report(&config_files).await {
+            log_error(&e, module_path!());
+        }
+    }
+
+    strategies.reconcile().await;
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
 
     loop {
-        for strategy in &strategies {
-            let res = strategy.execute().await;
-            if let Err(e) = res {
-                log::error!("{e}")
-            }
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = async {
+                let cycle_start = Instant::now();
+                strategies.execute(cycle_start).await;
+                tokio::time::sleep_until(strategies.next_cycle(cycle_start)).await;
+            } => {}
         }
+    }
+
+    log::info!("Shutting down...");
+    strategies.skips.summarize();
+
+    if strategies.cancel_on_shutdown {
+        strategies.cancel_offers().await;
+    }
+}
+
+/// Wait for Ctrl-C or SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("Failed to install the Ctrl-C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to install the SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
 
-        tokio::time::sleep(Duration::from_secs(60)).await;
+/// Log a strategy error to its `log_target`, along with its classification if it comes from the
+/// Bitfinex API.
+fn log_error(e: &anyhow::Error, log_target: &str) {
+    match BitfinexError::classify(e) {
+        Some(error) => log::error!(target: log_target, "{error} ({e:#})"),
+        None => log::error!(target: log_target, "{e:#}"),
     }
 }
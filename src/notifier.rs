@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use tokio::process::Command;
+
+/// Run a shell command on the critical events, e.g. to page the operator when a rate is refused
+/// by the sanity ceiling.
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    command: Option<Arc<String>>,
+}
+
+impl Notifier {
+    /// Create the notifier, disabled if `command` is `None`.
+    pub fn new(command: Option<String>) -> Self {
+        Self {
+            command: command.map(Arc::new),
+        }
+    }
+
+    /// Run the command in the background with `message` in the `NOTIFY_MESSAGE` env variable,
+    /// warning if it cannot be started.
+    pub fn notify(&self, message: &str) {
+        let Some(command) = &self.command else {
+            return;
+        };
+
+        if let Err(e) = Command::new("sh")
+            .arg("-c")
+            .arg(command.as_str())
+            .env("NOTIFY_MESSAGE", message)
+            .spawn()
+        {
+            log::warn!("Failed to run the notify command: {e}");
+        }
+    }
+}
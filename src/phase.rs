@@ -0,0 +1,103 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{ensure, Result};
+
+/// Return the phase offset of the strategy `name` within the `poll_interval`, derived from the
+/// FNV-1a hash of its name so that it is stable across restarts and Rust versions.
+pub fn phase_offset(name: &str, poll_interval: Duration) -> Duration {
+    let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+
+    Duration::from_millis(hash % poll_interval.as_millis() as u64)
+}
+
+/// Ensure the `phase_offsets_secs` are below the `poll_interval_secs` and only set for the
+/// strategies `names`, catching the typos which would silently fall back to the hashed phase.
+pub fn validate_phase_offsets(
+    phase_offsets_secs: &HashMap<String, u64>,
+    poll_interval_secs: u64,
+    names: &[&str],
+) -> Result<()> {
+    for (name, phase_offset_secs) in phase_offsets_secs {
+        ensure!(
+            names.contains(&name.as_str()),
+            "phase_offsets_secs of {name} matches no enabled strategy"
+        );
+        ensure!(
+            *phase_offset_secs < poll_interval_secs,
+            "phase_offsets_secs of {name} must be below poll_interval_secs"
+        );
+    }
+
+    Ok(())
+}
+
+/// Ensure the latest of the `phases` plus the `max_jitter` stays below the `poll_interval`, so
+/// that no strategy runs past the start of the next cycle.
+pub fn validate_phase_jitter(
+    phases: &[Duration],
+    max_jitter: Duration,
+    poll_interval: Duration,
+) -> Result<()> {
+    let latest_phase = phases.iter().max().copied().unwrap_or_default();
+    ensure!(
+        latest_phase + max_jitter < poll_interval,
+        "the latest phase offset plus max_jitter_secs must be below poll_interval_secs"
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phase_offset_is_stable_and_within_the_poll_interval() {
+        let poll_interval = Duration::from_secs(60);
+
+        // FNV-1a of "a" is 0xaf63dc4c8601ec8c.
+        assert_eq!(
+            phase_offset("a", poll_interval),
+            Duration::from_millis(0xaf63_dc4c_8601_ec8c % 60_000)
+        );
+        for name in ["usd_aggressive", "btc_ladder", ""] {
+            assert_eq!(
+                phase_offset(name, poll_interval),
+                phase_offset(name, poll_interval)
+            );
+            assert!(phase_offset(name, poll_interval) < poll_interval);
+        }
+        assert_ne!(
+            phase_offset("usd_aggressive", poll_interval),
+            phase_offset("btc_ladder", poll_interval)
+        );
+    }
+
+    #[test]
+    fn phase_offsets_must_match_a_strategy() {
+        let names = ["usd_aggressive", "btc_ladder"];
+
+        let phase_offsets_secs = HashMap::from([("usd_aggressive".to_string(), 30)]);
+        assert!(validate_phase_offsets(&phase_offsets_secs, 60, &names).is_ok());
+        // Not below the poll interval.
+        assert!(validate_phase_offsets(&phase_offsets_secs, 30, &names).is_err());
+
+        let typo = HashMap::from([("usd_agressive".to_string(), 30)]);
+        assert!(validate_phase_offsets(&typo, 60, &names).is_err());
+    }
+
+    #[test]
+    fn phase_jitter_must_end_before_the_next_cycle() {
+        let poll_interval = Duration::from_secs(60);
+        let phases = [Duration::ZERO, Duration::from_secs(50)];
+
+        assert!(validate_phase_jitter(&phases, Duration::from_secs(9), poll_interval).is_ok());
+        // The latest phase plus the jitter reaches the next cycle.
+        assert!(validate_phase_jitter(&phases, Duration::from_secs(10), poll_interval).is_err());
+        // Without phases, only the jitter must be below the poll interval.
+        assert!(validate_phase_jitter(&[], Duration::from_secs(59), poll_interval).is_ok());
+        assert!(validate_phase_jitter(&[], poll_interval, poll_interval).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use bitfinex_api::{
+    api::{
+        public::ticker::{Ticker, TradingTickerResp},
+        query::AsyncQuery,
+    },
+    bitfinex::AsyncBitfinex,
+};
+
+/// Source of the prices.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    /// Last price of the Bitfinex trading ticker of the pair.
+    #[default]
+    BitfinexTicker,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PriceFeedConfig {
+    #[serde(default)]
+    source: PriceSource,
+    /// Currency the amounts are converted to.
+    #[serde(default = "default_quote_currency")]
+    quote_currency: String,
+}
+
+fn default_quote_currency() -> String {
+    "USD".to_string()
+}
+
+/// Convert the amounts of the different currencies to a common quote currency, so that the
+/// strategies lending different assets can be compared.
+///
+/// The prices are cached for the duration of a cycle.
+#[derive(Debug, Clone)]
+pub struct PriceFeed {
+    config: Option<Arc<PriceFeedConfig>>,
+    /// Prices of the cycle, by base and quote currencies.
+    prices: Arc<Mutex<HashMap<(String, String), f64>>>,
+}
+
+impl PriceFeed {
+    /// Create the price feed, disabled if `config` is `None`.
+    pub fn new(config: Option<PriceFeedConfig>) -> Self {
+        Self {
+            config: config.map(Arc::new),
+            prices: Default::default(),
+        }
+    }
+
+    /// Forget the cached prices, to be called at the start of each cycle.
+    pub fn new_cycle(&self) {
+        self.prices.lock().unwrap().clear();
+    }
+
+    /// Convert `amount` of `currency` to the quote currency, returning the converted amount and
+    /// the quote currency, or `None` if the feed is disabled or the price is unavailable.
+    pub async fn convert(
+        &self,
+        client: &AsyncBitfinex,
+        currency: &str,
+        amount: f64,
+    ) -> Option<(f64, &str)> {
+        let config = self.config.as_ref()?;
+
+        match self
+            .price(client, config.source, currency, &config.quote_currency)
+            .await
+        {
+            Ok(price) => Some((amount * price, &config.quote_currency)),
+            Err(e) => {
+                log::warn!(
+                    "Failed to fetch the {currency} price in {}: {e:#}",
+                    config.quote_currency
+                );
+                None
+            }
+        }
+    }
+
+    /// Return the price of `currency` in USD from the source of the feed, the Bitfinex ticker
+    /// if it is disabled, e.g. to convert the amounts configured in USD.
+    pub async fn usd_price(&self, client: &AsyncBitfinex, currency: &str) -> Result<f64> {
+        let source = self
+            .config
+            .as_ref()
+            .map_or_else(PriceSource::default, |config| config.source);
+
+        self.price(client, source, currency, "USD").await
+    }
+
+    /// Return the price of `currency` in `quote_currency`, fetching it from `source` if it is not
+    /// cached.
+    async fn price(
+        &self,
+        client: &AsyncBitfinex,
+        source: PriceSource,
+        currency: &str,
+        quote_currency: &str,
+    ) -> Result<f64> {
+        if currency == quote_currency {
+            return Ok(1.);
+        }
+
+        let pair = (currency.to_string(), quote_currency.to_string());
+        if let Some(price) = self.prices.lock().unwrap().get(&pair) {
+            return Ok(*price);
+        }
+
+        let price = match source {
+            PriceSource::BitfinexTicker => ticker_price(client, currency, quote_currency).await?,
+        };
+
+        self.prices.lock().unwrap().insert(pair, price);
+
+        Ok(price)
+    }
+}
+
+/// Fetch the last price of `base` in `quote` from the Bitfinex trading ticker of the pair.
+async fn ticker_price(client: &AsyncBitfinex, base: &str, quote: &str) -> Result<f64> {
+    let ticker: TradingTickerResp = Ticker::builder()
+        .symbol(&trading_symbol(base, quote))
+        .build()?
+        .query_async(client)
+        .await?;
+
+    Ok(ticker.last_price)
+}
+
+/// Return the Bitfinex trading symbol of the pair, the currencies longer than 3 letters being
+/// separated by a colon (e.g. `tBTCUSD`, `tTESTBTC:TESTUSD`).
+fn trading_symbol(base: &str, quote: &str) -> String {
+    if base.len() > 3 || quote.len() > 3 {
+        format!("t{base}:{quote}")
+    } else {
+        format!("t{base}{quote}")
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Reason why a strategy ended a cycle without submitting an offer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipReason {
+    InsufficientBalance,
+    BudgetExhausted,
+    /// The daily budget of new principal is spent.
+    DailyBudgetSpent,
+    NoRate,
+    /// The rate is not far enough above the min rate.
+    RateTooLow,
+    /// The rate failed the sanity check.
+    InsaneRate,
+    /// The borrowers bid too far below the rate.
+    UnhealthySpread,
+    /// The period of the offer is not allowed.
+    PeriodNotAllowed,
+    /// The bids are too thin for an offer.
+    ThinBook,
+    /// The account has no active offer slot left.
+    OfferLimitReached,
+    /// The projected interest of the offer is below the minimum.
+    InterestTooLow,
+    /// Too few API requests are left in the cycle to replace the offer.
+    RequestCapReached,
+    OfferGoodEnough,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Self::InsufficientBalance => "insufficient balance",
+            Self::BudgetExhausted => "deployment budget exhausted",
+            Self::DailyBudgetSpent => "daily budget spent",
+            Self::NoRate => "no rate available",
+            Self::RateTooLow => "rate too low",
+            Self::InsaneRate => "implausible rate",
+            Self::UnhealthySpread => "unhealthy borrow spread",
+            Self::PeriodNotAllowed => "period not allowed",
+            Self::ThinBook => "book too thin",
+            Self::OfferLimitReached => "account offer limit reached",
+            Self::InterestTooLow => "projected interest too low",
+            Self::RequestCapReached => "API request cap reached",
+            Self::OfferGoodEnough => "active offer good enough",
+        };
+
+        write!(f, "{reason}")
+    }
+}
+
+#[derive(Debug)]
+struct Counts {
+    /// Start of the period the counts cover.
+    since: Instant,
+    /// Skipped cycles indexed by strategy, then by reason.
+    skips: BTreeMap<String, BTreeMap<SkipReason, u64>>,
+}
+
+/// Count of the cycles skipped by the strategies for each reason, summarized periodically in the
+/// logs to help tuning the config.
+#[derive(Debug, Clone)]
+pub struct SkipStats {
+    counts: Arc<Mutex<Counts>>,
+}
+
+impl Default for SkipStats {
+    fn default() -> Self {
+        Self {
+            counts: Arc::new(Mutex::new(Counts {
+                since: Instant::now(),
+                skips: BTreeMap::new(),
+            })),
+        }
+    }
+}
+
+impl SkipStats {
+    /// Record a cycle of `strategy` skipped for `reason`.
+    pub fn record(&self, strategy: &str, reason: SkipReason) {
+        *self
+            .counts
+            .lock()
+            .unwrap()
+            .skips
+            .entry(strategy.to_string())
+            .or_default()
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    /// Log the counts if they cover at least `interval`, then start over.
+    pub fn summarize_every(&self, interval: Duration) {
+        if self.counts.lock().unwrap().since.elapsed() >= interval {
+            self.summarize();
+        }
+    }
+
+    /// Log the counts, then start over.
+    pub fn summarize(&self) {
+        let mut counts = self.counts.lock().unwrap();
+
+        log::info!(
+            "Skipped cycles over the last {}h:",
+            counts.since.elapsed().as_secs() / 3600
+        );
+        if counts.skips.is_empty() {
+            log::info!("  none");
+        }
+        for (strategy, reasons) in &counts.skips {
+            for (reason, count) in reasons {
+                log::info!("  {strategy}: {count} times due to {reason}");
+            }
+        }
+
+        counts.since = Instant::now();
+        counts.skips.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(skip_stats: &SkipStats) -> BTreeMap<String, BTreeMap<SkipReason, u64>> {
+        skip_stats.counts.lock().unwrap().skips.clone()
+    }
+
+    #[test]
+    fn skips_are_counted_by_strategy_and_reason() {
+        let skip_stats = SkipStats::default();
+
+        skip_stats.record("a", SkipReason::RateTooLow);
+        skip_stats.record("a", SkipReason::RateTooLow);
+        skip_stats.record("a", SkipReason::NoRate);
+        skip_stats.record("b", SkipReason::RateTooLow);
+
+        assert_eq!(
+            counts(&skip_stats),
+            BTreeMap::from([
+                (
+                    "a".to_string(),
+                    BTreeMap::from([(SkipReason::NoRate, 1), (SkipReason::RateTooLow, 2)])
+                ),
+                (
+                    "b".to_string(),
+                    BTreeMap::from([(SkipReason::RateTooLow, 1)])
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn skips_are_reset_once_summarized() {
+        let skip_stats = SkipStats::default();
+        skip_stats.record("a", SkipReason::RateTooLow);
+
+        // Not covering the interval yet.
+        skip_stats.summarize_every(Duration::from_secs(3600));
+        assert_eq!(counts(&skip_stats).len(), 1);
+
+        skip_stats.summarize();
+        assert!(counts(&skip_stats).is_empty());
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+/// Last offer submitted by a strategy on a currency.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Submission {
+    /// UNIX timestamp (in seconds) of the submission.
+    pub timestamp: u64,
+    pub rate: f64,
+    /// Total amount submitted, `0` for the submissions recorded before it was.
+    #[serde(default)]
+    pub amount: f64,
+    /// Period, in days, `0` for the submissions recorded before it was.
+    #[serde(default)]
+    pub period: u8,
+    /// ID of the submitted offer, `None` once it is no longer active.
+    pub offer_id: Option<u64>,
+}
+
+/// Action of a strategy on the exchange, recorded before it is taken so that a restart in the
+/// middle of it can be reconciled.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Cancel {
+        offer_id: u64,
+    },
+    /// Submission of an offer of `amount` at `rate` for `period` days, to recognize it among the
+    /// active offers.
+    Submit {
+        amount: f64,
+        rate: f64,
+        period: u8,
+    },
+}
+
+/// Action a strategy was taking, not known to have completed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PendingAction {
+    pub action: Action,
+    /// UNIX timestamp (in seconds) the action was started at.
+    pub timestamp: u64,
+}
+
+/// State persisted across restarts, stored in a JSON file as an object keyed by strategy.
+#[derive(Debug, Clone)]
+pub struct StateStore {
+    path: String,
+    entries: Arc<Mutex<HashMap<String, Value>>>,
+}
+
+impl StateStore {
+    /// Load the state from `path`, starting from an empty state if the file is missing or
+    /// corrupted.
+    pub fn load(path: &str) -> Self {
+        let entries = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                log::warn!("Ignoring the corrupted state file {path}: {e}");
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                log::warn!("Ignoring the unreadable state file {path}: {e}");
+                HashMap::new()
+            }
+        };
+
+        Self {
+            path: path.to_string(),
+            entries: Arc::new(Mutex::new(entries)),
+        }
+    }
+
+    /// Return the state stored under `key`, or `None` if there is none or it is invalid.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let entries = self.entries.lock().unwrap();
+        let value = entries.get(key)?.clone();
+
+        serde_json::from_value(value)
+            .map_err(|e| log::warn!("Ignoring the invalid {key} state: {e}"))
+            .ok()
+    }
+
+    /// Store `value` under `key` and write the state file.
+    pub fn set<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key.to_string(), serde_json::to_value(value)?);
+
+        // Write a temporary file first so that a crash never leaves a truncated state behind.
+        let tmp_path = format!("{}.tmp", self.path);
+        fs::write(&tmp_path, serde_json::to_string_pretty(&*entries)?)
+            .with_context(|| format!("Failed to write {tmp_path}"))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path))?;
+
+        Ok(())
+    }
+
+    /// Return the last submission of `strategy` on `currency`.
+    pub fn submission(&self, strategy: &str, currency: &str) -> Option<Submission> {
+        self.get(&submission_key(strategy, currency))
+    }
+
+    /// Record the last submission of `strategy` on `currency`.
+    pub fn set_submission(
+        &self,
+        strategy: &str,
+        currency: &str,
+        submission: &Submission,
+    ) -> Result<()> {
+        self.set(&submission_key(strategy, currency), submission)
+    }
+
+    /// Return the IDs of the offers owned by `strategy` on `currency`, none if unknown.
+    pub fn owned_offers(&self, strategy: &str, currency: &str) -> HashSet<u64> {
+        self.get(&owned_offers_key(strategy, currency))
+            .unwrap_or_default()
+    }
+
+    /// Record the IDs of the offers owned by `strategy` on `currency`.
+    pub fn set_owned_offers(
+        &self,
+        strategy: &str,
+        currency: &str,
+        owned_offers: &HashSet<u64>,
+    ) -> Result<()> {
+        self.set(&owned_offers_key(strategy, currency), owned_offers)
+    }
+
+    /// Return the action `strategy` was taking on `currency`, if not known to have completed.
+    pub fn pending_action(&self, strategy: &str, currency: &str) -> Option<PendingAction> {
+        self.get::<Option<PendingAction>>(&pending_action_key(strategy, currency))
+            .flatten()
+    }
+
+    /// Record the action `strategy` is taking on `currency`, or `None` once it completed.
+    pub fn set_pending_action(
+        &self,
+        strategy: &str,
+        currency: &str,
+        pending_action: Option<PendingAction>,
+    ) -> Result<()> {
+        self.set(&pending_action_key(strategy, currency), &pending_action)
+    }
+
+    /// Return the last submission of `strategy` on `currency`, forgetting its offer if it is not
+    /// among the active offers anymore (filled or canceled, possibly while the bot was down).
+    pub fn reconcile_submission(
+        &self,
+        strategy: &str,
+        currency: &str,
+        active_offer_ids: impl IntoIterator<Item = u64>,
+    ) -> Result<Option<Submission>> {
+        let Some(mut submission) = self.submission(strategy, currency) else {
+            return Ok(None);
+        };
+
+        if let Some(offer_id) = submission.offer_id {
+            if !active_offer_ids.into_iter().any(|id| id == offer_id) {
+                log::info!("Offer {offer_id} is no longer active (filled or canceled)");
+                submission.offer_id = None;
+                self.set_submission(strategy, currency, &submission)?;
+            }
+        }
+
+        Ok(Some(submission))
+    }
+}
+
+fn submission_key(strategy: &str, currency: &str) -> String {
+    format!("submissions.{strategy}.{currency}")
+}
+
+fn owned_offers_key(strategy: &str, currency: &str) -> String {
+    format!("owned_offers.{strategy}.{currency}")
+}
+
+fn pending_action_key(strategy: &str, currency: &str) -> String {
+    format!("pending_actions.{strategy}.{currency}")
+}
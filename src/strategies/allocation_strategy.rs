@@ -0,0 +1,564 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use anyhow::{ensure, Context, Ok, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use bitfinex_api::{api::authenticated::funding::types::FundingOffer, bitfinex::AsyncBitfinex};
+
+use crate::{
+    budget::AccountBudget,
+    config::{self, ConfigFiles},
+};
+
+use super::{
+    common,
+    owned_offers::OwnedOffers,
+    precision::Precision,
+    rate_display::{self, RateDisplay},
+    rate_guard::{self, RateGuard},
+    rate_selector::RateSelector,
+    timeframe::CandleTimeFrame,
+    FromConfig, Shared, Strategy,
+};
+
+/// A bucket of the allocation resolved for the current cycle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bucket {
+    period: u8,
+    rate: f64,
+    /// Amount to keep offered on the bucket.
+    amount: f64,
+}
+
+/// Resolve the amount to offer on each bucket.
+///
+/// `allocation` lists the `(period, fraction, rate)` of the buckets sorted by period, the rate
+/// being `None` when the floor of the bucket is not met. Each bucket targets its fraction of the
+/// total balance minus what is already lent on its period (`credits`), the targets of the buckets
+/// whose floor is not met being moved to the shortest bucket whose floor is met. The buckets are
+/// then funded in order from the lendable balance, and those that would be smaller than
+/// `min_amount` are left empty.
+fn allocate(
+    allocation: &[(u8, f64, Option<f64>)],
+    total_balance: f64,
+    lendable_balance: f64,
+    credits: &HashMap<u8, f64>,
+    min_amount: f64,
+) -> Vec<Bucket> {
+    let missing = |period: u8, fraction: f64| {
+        (fraction * total_balance - credits.get(&period).unwrap_or(&0.)).max(0.)
+    };
+
+    let mut buckets: Vec<Bucket> = allocation
+        .iter()
+        .filter_map(|&(period, fraction, rate)| {
+            rate.map(|rate| Bucket {
+                period,
+                rate,
+                amount: missing(period, fraction),
+            })
+        })
+        .collect();
+
+    let unmet: f64 = allocation
+        .iter()
+        .filter(|(_, _, rate)| rate.is_none())
+        .map(|&(period, fraction, _)| missing(period, fraction))
+        .sum();
+
+    if let Some(shortest) = buckets.first_mut() {
+        shortest.amount += unmet;
+    }
+
+    let mut remaining = lendable_balance;
+    for bucket in &mut buckets {
+        bucket.amount = bucket.amount.min(remaining);
+        if bucket.amount < min_amount {
+            bucket.amount = 0.;
+        }
+        remaining -= bucket.amount;
+    }
+
+    buckets
+}
+
+/// Split the balance across offers of different periods.
+pub struct AllocationStrategy {
+    name: String,
+    client: AsyncBitfinex,
+    currency: String,
+    symbol: String,
+    min_amount: f64,
+    min_rate: f64,
+    monitored_window: u64,
+    rate_selector: RateSelector,
+    /// Fraction of the total balance allocated to each period.
+    allocation: BTreeMap<u8, f64>,
+    /// Minimum rate of each period, `min_rate` being used for the others.
+    min_rates: HashMap<u8, f64>,
+    candle_timeframe: CandleTimeFrame,
+    rate_guard: RateGuard,
+    precision: Precision,
+    hidden: bool,
+    owned_offers: OwnedOffers,
+    rate_display: RateDisplay,
+    budget: AccountBudget,
+}
+
+impl AllocationStrategy {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        name: String,
+        client: AsyncBitfinex,
+        currency: String,
+        symbol: String,
+        min_amount: f64,
+        min_rate: f64,
+        monitored_window: u64,
+        rate_selector: RateSelector,
+        allocation: BTreeMap<u8, f64>,
+        min_rates: HashMap<u8, f64>,
+        candle_timeframe: CandleTimeFrame,
+        rate_guard: RateGuard,
+        precision: Precision,
+        hidden: bool,
+        owned_offers: OwnedOffers,
+        rate_display: RateDisplay,
+        budget: AccountBudget,
+    ) -> Self {
+        Self {
+            name,
+            client,
+            currency,
+            symbol,
+            min_amount,
+            min_rate,
+            monitored_window,
+            rate_selector,
+            allocation,
+            min_rates,
+            candle_timeframe,
+            rate_guard,
+            precision,
+            hidden,
+            owned_offers,
+            rate_display,
+            budget,
+        }
+    }
+
+    /// Return the minimum rate of `period`.
+    fn min_rate(&self, period: u8) -> f64 {
+        self.min_rates
+            .get(&period)
+            .copied()
+            .unwrap_or(self.min_rate)
+    }
+
+    /// Match the live offers against the buckets, keeping at most one offer per bucket.
+    ///
+    /// Return the ids of the offers to cancel and the buckets to submit.
+    fn reconcile(
+        &self,
+        buckets: &[Bucket],
+        active_offers: &[FundingOffer],
+    ) -> (Vec<u64>, Vec<Bucket>) {
+        let mut covered = vec![false; buckets.len()];
+        let mut to_cancel = vec![];
+
+        for active_offer in active_offers {
+            let bucket_index = (0..buckets.len()).find(|&i| {
+                let bucket = &buckets[i];
+                !covered[i]
+                    && bucket.period == active_offer.period
+                    && (bucket.amount - active_offer.amount).abs() <= 1.
+                    && (bucket.rate - active_offer.rate).abs() / bucket.rate <= 0.01
+            });
+
+            match bucket_index {
+                Some(i) => covered[i] = true,
+                None => to_cancel.push(active_offer.id),
+            }
+        }
+
+        let to_submit = buckets
+            .iter()
+            .zip(covered)
+            .filter(|(bucket, covered)| !covered && bucket.amount > 0.)
+            .map(|(bucket, _)| *bucket)
+            .collect();
+
+        (to_cancel, to_submit)
+    }
+}
+
+impl fmt::Display for AllocationStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let allocation: Vec<String> = self
+            .allocation
+            .iter()
+            .map(|(period, fraction)| {
+                format!(
+                    "{:.0}% on {period}d @ min {}",
+                    fraction * 100.,
+                    self.rate_display.rate(self.min_rate(*period))
+                )
+            })
+            .collect();
+
+        write!(
+            f,
+            "{} on {}: {}, {} candle over {}h",
+            self.name(),
+            self.currency,
+            allocation.join(", "),
+            self.rate_selector,
+            self.monitored_window
+        )
+    }
+}
+
+impl FromConfig for AllocationStrategy {
+    fn from_config(files: &ConfigFiles, shared: &Shared) -> Result<Vec<Self>> {
+        #[derive(Debug, Deserialize)]
+        struct Strategy {
+            keys: String,
+            currency: String,
+            /// Funding symbol, defaults to `f{currency}`.
+            #[serde(default, alias = "funding_symbol")]
+            symbol: Option<String>,
+            min_amount: f64,
+            min_rate: f64,
+            monitored_window: u64,
+            rate_selector: RateSelector,
+            allocation: BTreeMap<u8, f64>,
+            #[serde(default)]
+            min_rates: HashMap<u8, f64>,
+            #[serde(default)]
+            candle_timeframe: CandleTimeFrame,
+            #[serde(default = "rate_guard::default_max_rate")]
+            max_rate: f64,
+            #[serde(default = "rate_guard::default_sanity_max_apr")]
+            sanity_max_apr: f64,
+            #[serde(default = "common::default_enabled")]
+            enabled: bool,
+            #[serde(default = "common::default_hidden")]
+            hidden: bool,
+            #[serde(default = "rate_display::default_rate_decimals")]
+            rate_display_decimals: usize,
+            #[serde(default = "rate_display::default_apr_decimals")]
+            apr_display_decimals: usize,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[serde(default)]
+            allocation_strategies: HashMap<String, Strategy>,
+        }
+
+        let config: Config = config::read(files)?;
+
+        config
+            .allocation_strategies
+            .into_iter()
+            .filter(|(name, strategy)| common::is_enabled(name, strategy.enabled))
+            .map(|(name, strategy)| {
+                ensure!(
+                    strategy.min_amount > 0.,
+                    "{name}: min_amount must be positive"
+                );
+                ensure!(
+                    strategy.min_rate >= 0.,
+                    "{name}: min_rate must not be negative"
+                );
+                ensure!(
+                    strategy.monitored_window > 0,
+                    "{name}: monitored_window must be positive"
+                );
+                strategy
+                    .rate_selector
+                    .validate()
+                    .with_context(|| format!("{name}: invalid rate_selector"))?;
+
+                ensure!(
+                    !strategy.allocation.is_empty(),
+                    "{name}: allocation must not be empty"
+                );
+                for (period, fraction) in &strategy.allocation {
+                    common::validate_period(*period)
+                        .with_context(|| format!("{name}: invalid allocation period"))?;
+                    ensure!(
+                        *fraction > 0.,
+                        "{name}: allocation of {period} days must be positive"
+                    );
+                }
+                ensure!(
+                    strategy.allocation.values().sum::<f64>() <= 1.,
+                    "{name}: allocation fractions must sum to at most 1"
+                );
+
+                for (period, min_rate) in &strategy.min_rates {
+                    ensure!(
+                        strategy.allocation.contains_key(period),
+                        "{name}: min_rates of {period} days has no allocation"
+                    );
+                    ensure!(
+                        *min_rate >= 0.,
+                        "{name}: min_rates of {period} days must not be negative"
+                    );
+                }
+
+                let rate_display = RateDisplay::new(
+                    strategy.rate_display_decimals,
+                    strategy.apr_display_decimals,
+                );
+                rate_display
+                    .validate()
+                    .with_context(|| format!("{name}: invalid display decimals"))?;
+                let rate_guard = RateGuard::new(
+                    strategy.max_rate,
+                    strategy.sanity_max_apr,
+                    rate_display,
+                    shared.notifier.clone(),
+                );
+                rate_guard
+                    .validate()
+                    .with_context(|| format!("{name}: invalid rate ceilings"))?;
+
+                let account_budget = shared.budget.account(&strategy.keys, &strategy.currency);
+                let precision = shared.precisions.get(&strategy.currency);
+                let symbol = common::funding_symbol(&strategy.currency, strategy.symbol);
+                let owned_offers = OwnedOffers::load(
+                    &name,
+                    &strategy.currency,
+                    shared.state.clone(),
+                    rate_display,
+                );
+
+                Ok(Self::new(
+                    name,
+                    common::client_from_keys(&strategy.keys)?,
+                    strategy.currency,
+                    symbol,
+                    strategy.min_amount,
+                    strategy.min_rate,
+                    strategy.monitored_window,
+                    strategy.rate_selector,
+                    strategy.allocation,
+                    strategy.min_rates,
+                    strategy.candle_timeframe,
+                    rate_guard,
+                    precision,
+                    strategy.hidden,
+                    owned_offers,
+                    rate_display,
+                    account_budget,
+                ))
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid allocation_strategies in {files}"))
+    }
+}
+
+#[async_trait]
+impl Strategy for AllocationStrategy {
+    type Output = Result<()>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Cancel the offers submitted by the strategy.
+    async fn cancel_offers(&self) -> Self::Output {
+        self.owned_offers
+            .cancel_all(&self.client, &self.symbol, &self.log_target())
+            .await
+    }
+
+    /// Execute the strategy.
+    async fn execute(&self) -> Self::Output {
+        log::info!(target: &self.log_target(), "Executing {} on {}...", self.name(), self.currency);
+
+        let funding_wallet = common::funding_wallet(&self.client, &self.currency).await?;
+        let active_offers = self
+            .owned_offers
+            .active_offers(&self.client, &self.symbol, &self.log_target())
+            .await?;
+        let active_credits = common::active_credits(&self.client, &self.symbol).await?;
+
+        // Funds sitting in our offers can be redistributed across the buckets.
+        let offered_balance: f64 = active_offers.iter().map(|offer| offer.amount).sum();
+        let lendable_balance = funding_wallet.available_balance + offered_balance;
+        let total_balance = funding_wallet.balance;
+
+        // Only spread what the global deployment budget allows.
+        self.budget
+            .update(total_balance, total_balance - lendable_balance);
+        let lendable_balance = lendable_balance.min(self.budget.remaining());
+
+        let mut credits: HashMap<u8, f64> = HashMap::new();
+        for credit in &active_credits {
+            *credits.entry(credit.period).or_default() += credit.amount;
+        }
+
+        // Take 99% of the selected rate of each period, if it meets the floor of the period.
+        let mut allocation = vec![];
+        for (&period, &fraction) in &self.allocation {
+            let rate = common::candle_rate(
+                &self.client,
+                &self.symbol,
+                self.monitored_window,
+                &self.rate_selector,
+                period,
+                self.candle_timeframe,
+            )
+            .await?
+                * 0.99;
+
+            let rate = if rate >= self.min_rate(period) {
+                Some(rate)
+            } else {
+                log::info!(
+                    target: &self.log_target(),
+                    "{period} days rate is below its floor: {} < {} per day",
+                    self.rate_display.rate(rate),
+                    self.rate_display.rate(self.min_rate(period))
+                );
+                None
+            };
+
+            allocation.push((period, fraction, rate));
+        }
+
+        let buckets = allocate(
+            &allocation,
+            total_balance,
+            lendable_balance,
+            &credits,
+            self.min_amount,
+        );
+
+        let (to_cancel, to_submit) = self.reconcile(&buckets, &active_offers);
+        // Refuse the whole allocation before canceling any offer.
+        let Some(to_submit) = to_submit
+            .iter()
+            .map(|bucket| {
+                self.rate_guard.offer_params(
+                    bucket.amount,
+                    bucket.rate,
+                    bucket.period,
+                    &self.log_target(),
+                )
+            })
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(());
+        };
+        self.budget
+            .deploy(buckets.iter().map(|bucket| bucket.amount).sum());
+
+        if to_cancel.is_empty() && to_submit.is_empty() {
+            log::info!(
+                target: &self.log_target(),
+                "Allocation is up to date: {} offers for {offered_balance:.2} {}",
+                active_offers.len(),
+                self.currency
+            );
+            return Ok(());
+        }
+
+        for id in to_cancel {
+            self.owned_offers
+                .cancel(&self.client, id, &self.log_target())
+                .await?;
+        }
+
+        for params in to_submit {
+            let offer = common::submit_offer(
+                &self.client,
+                &self.symbol,
+                params,
+                self.precision,
+                self.hidden,
+                &self.log_target(),
+            )
+            .await?;
+            self.owned_offers.insert(offer.id)?;
+
+            log::info!(
+                target: &self.log_target(),
+                "Bucket offer {} submitted: {:.2} for {} days @ {} per day ({} APR)",
+                offer.id,
+                offer.amount,
+                offer.period,
+                self.rate_display.rate(offer.rate),
+                self.rate_display.apr(offer.rate * 365.)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALLOCATION: [(u8, f64); 3] = [(2, 0.5), (7, 0.3), (30, 0.2)];
+
+    fn allocation(rates: [Option<f64>; 3]) -> Vec<(u8, f64, Option<f64>)> {
+        ALLOCATION
+            .iter()
+            .zip(rates)
+            .map(|(&(period, fraction), rate)| (period, fraction, rate))
+            .collect()
+    }
+
+    fn amounts(buckets: &[Bucket]) -> Vec<(u8, f64)> {
+        buckets
+            .iter()
+            .map(|bucket| (bucket.period, bucket.amount))
+            .collect()
+    }
+
+    #[test]
+    fn splits_the_balance() {
+        let allocation = allocation([Some(0.0002), Some(0.00025), Some(0.0003)]);
+        let buckets = allocate(&allocation, 10_000., 10_000., &HashMap::new(), 150.);
+
+        assert_eq!(
+            amounts(&buckets),
+            vec![(2, 5_000.), (7, 3_000.), (30, 2_000.)]
+        );
+    }
+
+    #[test]
+    fn unmet_floors_fall_back_to_the_short_bucket() {
+        let allocation = allocation([Some(0.0002), Some(0.00025), None]);
+        let buckets = allocate(&allocation, 10_000., 10_000., &HashMap::new(), 150.);
+
+        assert_eq!(amounts(&buckets), vec![(2, 7_000.), (7, 3_000.)]);
+    }
+
+    #[test]
+    fn credits_are_not_reallocated() {
+        // 2 500 are already lent for 7 days and 2 000 for 30 days.
+        let credits = HashMap::from([(7, 2_500.), (30, 2_000.)]);
+        let allocation = allocation([Some(0.0002), Some(0.00025), None]);
+        let buckets = allocate(&allocation, 10_000., 5_500., &credits, 150.);
+
+        assert_eq!(amounts(&buckets), vec![(2, 5_000.), (7, 500.)]);
+    }
+
+    #[test]
+    fn small_buckets_are_left_empty() {
+        let allocation = allocation([Some(0.0002), Some(0.00025), Some(0.0003)]);
+        let buckets = allocate(&allocation, 10_000., 5_100., &HashMap::new(), 150.);
+
+        assert_eq!(amounts(&buckets), vec![(2, 5_000.), (7, 0.), (30, 0.)]);
+    }
+}
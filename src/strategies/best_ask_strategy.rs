@@ -0,0 +1,473 @@
+use std::{collections::HashMap, fmt, time::Duration};
+
+use anyhow::{ensure, Context, Ok, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use bitfinex_api::bitfinex::AsyncBitfinex;
+
+use crate::{
+    budget::AccountBudget,
+    config::{self, ConfigFiles},
+    price_feed::PriceFeed,
+    state::{StateStore, Submission},
+};
+
+use super::{
+    common,
+    owned_offers::OwnedOffers,
+    precision::Precision,
+    rate_display::{self, RateDisplay},
+    rate_guard::{self, RateGuard},
+    FromConfig, Shared, Strategy,
+};
+
+/// Return the rate `undercut` below the best ask, but never below `min_rate`.
+fn target_rate(best_ask: f64, undercut: f64, min_rate: f64) -> f64 {
+    (best_ask - undercut).max(min_rate)
+}
+
+/// Return whether an offer at `offer_rate` must be repriced to `target_rate`, given the time
+/// elapsed since the last reprice (`None` if it never happened).
+///
+/// The top of the book moves constantly: the offer is only repriced if the target moved by more
+/// than `undercut`, and at most once per `min_reprice_interval`.
+fn should_reprice(
+    offer_rate: f64,
+    target_rate: f64,
+    undercut: f64,
+    since_last_reprice: Option<Duration>,
+    min_reprice_interval: Duration,
+) -> bool {
+    (offer_rate - target_rate).abs() > undercut
+        && since_last_reprice.map_or(true, |elapsed| elapsed >= min_reprice_interval)
+}
+
+pub struct BestAskStrategy {
+    name: String,
+    client: AsyncBitfinex,
+    currency: String,
+    symbol: String,
+    min_amount: f64,
+    max_balance_percent_per_loan: f64,
+    min_rate: f64,
+    period: u8,
+    undercut: f64,
+    min_reprice_interval: Duration,
+    rate_guard: RateGuard,
+    state: StateStore,
+    price_feed: PriceFeed,
+    precision: Precision,
+    hidden: bool,
+    owned_offers: OwnedOffers,
+    rate_display: RateDisplay,
+    budget: AccountBudget,
+}
+
+impl BestAskStrategy {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        name: String,
+        client: AsyncBitfinex,
+        currency: String,
+        symbol: String,
+        min_amount: f64,
+        max_balance_percent_per_loan: f64,
+        min_rate: f64,
+        period: u8,
+        undercut: f64,
+        min_reprice_interval: Duration,
+        rate_guard: RateGuard,
+        state: StateStore,
+        price_feed: PriceFeed,
+        precision: Precision,
+        hidden: bool,
+        owned_offers: OwnedOffers,
+        rate_display: RateDisplay,
+        budget: AccountBudget,
+    ) -> Self {
+        Self {
+            name,
+            client,
+            currency,
+            symbol,
+            min_amount,
+            max_balance_percent_per_loan,
+            min_rate,
+            period,
+            undercut,
+            min_reprice_interval,
+            rate_guard,
+            state,
+            price_feed,
+            precision,
+            hidden,
+            owned_offers,
+            rate_display,
+            budget,
+        }
+    }
+}
+
+impl fmt::Display for BestAskStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {}: {} days offers {} below the best ask @ min {} per day, repriced at \
+             most every {}s",
+            self.name(),
+            self.currency,
+            self.period,
+            self.rate_display.rate(self.undercut),
+            self.rate_display.rate(self.min_rate),
+            self.min_reprice_interval.as_secs()
+        )
+    }
+}
+
+impl FromConfig for BestAskStrategy {
+    fn from_config(files: &ConfigFiles, shared: &Shared) -> Result<Vec<Self>> {
+        #[derive(Debug, Deserialize)]
+        struct Strategy {
+            keys: String,
+            currency: String,
+            /// Funding symbol, defaults to `f{currency}`.
+            #[serde(default, alias = "funding_symbol")]
+            symbol: Option<String>,
+            min_amount: f64,
+            max_balance_percent_per_loan: f64,
+            min_rate: f64,
+            period: u8,
+            undercut: f64,
+            /// Minimum number of seconds between two reprices.
+            min_reprice_interval: u64,
+            #[serde(default = "rate_guard::default_max_rate")]
+            max_rate: f64,
+            #[serde(default = "rate_guard::default_sanity_max_apr")]
+            sanity_max_apr: f64,
+            #[serde(default = "common::default_enabled")]
+            enabled: bool,
+            #[serde(default = "common::default_hidden")]
+            hidden: bool,
+            #[serde(default = "rate_display::default_rate_decimals")]
+            rate_display_decimals: usize,
+            #[serde(default = "rate_display::default_apr_decimals")]
+            apr_display_decimals: usize,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[serde(default)]
+            best_ask_strategies: HashMap<String, Strategy>,
+        }
+
+        let config: Config = config::read(files)?;
+
+        config
+            .best_ask_strategies
+            .into_iter()
+            .filter(|(name, strategy)| common::is_enabled(name, strategy.enabled))
+            .map(|(name, strategy)| {
+                ensure!(
+                    strategy.min_amount > 0.,
+                    "{name}: min_amount must be positive"
+                );
+                ensure!(
+                    strategy.max_balance_percent_per_loan > 0.
+                        && strategy.max_balance_percent_per_loan <= 1.,
+                    "{name}: max_balance_percent_per_loan must be within (0, 1]"
+                );
+                ensure!(
+                    strategy.min_rate >= 0.,
+                    "{name}: min_rate must not be negative"
+                );
+                common::validate_period(strategy.period)
+                    .with_context(|| format!("{name}: invalid period"))?;
+                ensure!(strategy.undercut > 0., "{name}: undercut must be positive");
+
+                let rate_display = RateDisplay::new(
+                    strategy.rate_display_decimals,
+                    strategy.apr_display_decimals,
+                );
+                rate_display
+                    .validate()
+                    .with_context(|| format!("{name}: invalid display decimals"))?;
+                let rate_guard = RateGuard::new(
+                    strategy.max_rate,
+                    strategy.sanity_max_apr,
+                    rate_display,
+                    shared.notifier.clone(),
+                );
+                rate_guard
+                    .validate()
+                    .with_context(|| format!("{name}: invalid rate ceilings"))?;
+
+                let account_budget = shared.budget.account(&strategy.keys, &strategy.currency);
+                let precision = shared.precisions.get(&strategy.currency);
+                let symbol = common::funding_symbol(&strategy.currency, strategy.symbol);
+                let owned_offers = OwnedOffers::load(
+                    &name,
+                    &strategy.currency,
+                    shared.state.clone(),
+                    rate_display,
+                );
+
+                Ok(Self::new(
+                    name,
+                    common::client_from_keys(&strategy.keys)?,
+                    strategy.currency,
+                    symbol,
+                    strategy.min_amount,
+                    strategy.max_balance_percent_per_loan,
+                    strategy.min_rate,
+                    strategy.period,
+                    strategy.undercut,
+                    Duration::from_secs(strategy.min_reprice_interval),
+                    rate_guard,
+                    shared.state.clone(),
+                    shared.price_feed.clone(),
+                    precision,
+                    strategy.hidden,
+                    owned_offers,
+                    rate_display,
+                    account_budget,
+                ))
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid best_ask_strategies in {files}"))
+    }
+}
+
+#[async_trait]
+impl Strategy for BestAskStrategy {
+    type Output = Result<()>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Cancel the offers submitted by the strategy.
+    async fn cancel_offers(&self) -> Self::Output {
+        self.owned_offers
+            .cancel_all(&self.client, &self.symbol, &self.log_target())
+            .await
+    }
+
+    /// Execute the strategy.
+    async fn execute(&self) -> Self::Output {
+        log::info!(target: &self.log_target(), "Executing {} on {}...", self.name(), self.currency);
+
+        let funding_wallet = common::funding_wallet(&self.client, &self.currency).await?;
+        let active_offer = self
+            .owned_offers
+            .active_offer(&self.client, &self.symbol, &self.log_target())
+            .await?;
+        let last_submission = self.state.reconcile_submission(
+            &self.name,
+            &self.currency,
+            active_offer.iter().map(|active_offer| active_offer.id),
+        )?;
+
+        let available_balance = funding_wallet.available_balance
+            + active_offer
+                .as_ref()
+                .map_or(0., |active_offer| active_offer.amount);
+        let total_balance = funding_wallet.balance;
+
+        self.budget
+            .update(total_balance, total_balance - available_balance);
+
+        // Early return if there is not enough available balance to create an offer.
+        if available_balance < self.min_amount {
+            log::info!(
+                target: &self.log_target(),
+                "Insufficient balance to submit a lend offer: {available_balance:.2} < {:.2}",
+                self.min_amount
+            );
+            return Ok(());
+        }
+
+        // Our offers are hidden, so the best ask is never our own.
+        let funding_book = common::funding_book(&self.client, &self.symbol).await?;
+        let Some(best_ask) = funding_book.asks.first() else {
+            log::info!(
+                target: &self.log_target(),
+                "No ask on the {} funding book, skipping",
+                self.symbol
+            );
+            return Ok(());
+        };
+
+        let rate = target_rate(best_ask.rate, self.undercut, self.min_rate);
+
+        log::info!(
+            target: &self.log_target(),
+            "Best ask: {} per day, target rate: {} per day",
+            self.rate_display.rate(best_ask.rate),
+            self.rate_display.rate(rate)
+        );
+
+        // Clamp the amount to loan as a fraction of the total balance and to the global budget.
+        let loan_amount = self
+            .min_amount
+            .max(available_balance.min(total_balance * self.max_balance_percent_per_loan))
+            .min(self.budget.remaining());
+
+        if loan_amount < self.min_amount {
+            log::info!(
+                target: &self.log_target(),
+                "Global deployment budget exhausted for {}: {loan_amount:.2} < {:.2}",
+                self.currency,
+                self.min_amount
+            );
+            return Ok(());
+        }
+
+        let Some(params) =
+            self.rate_guard
+                .offer_params(loan_amount, rate, self.period, &self.log_target())
+        else {
+            return Ok(());
+        };
+
+        // Check if the active offer needs to be canceled.
+        if let Some(active_offer) = active_offer {
+            // The last reprice is persisted, so that restarts do not trigger one.
+            let now = common::unix_time()?;
+            let since_last_reprice = last_submission
+                .as_ref()
+                .map(|submission| Duration::from_secs(now.saturating_sub(submission.timestamp)));
+
+            let amount_diff = (loan_amount - active_offer.amount).abs();
+
+            if active_offer.period != self.period
+                || amount_diff > 1.
+                || should_reprice(
+                    active_offer.rate,
+                    rate,
+                    self.undercut,
+                    since_last_reprice,
+                    self.min_reprice_interval,
+                )
+            {
+                self.owned_offers
+                    .cancel(&self.client, active_offer.id, &self.log_target())
+                    .await?;
+            } else {
+                log::info!(
+                    target: &self.log_target(),
+                    "Active offer is kept: {:.2} for {} days @ {} per day",
+                    active_offer.amount,
+                    active_offer.period,
+                    self.rate_display.rate(active_offer.rate)
+                );
+                self.budget.deploy(active_offer.amount);
+                return Ok(());
+            }
+        }
+
+        let offer = common::submit_offer(
+            &self.client,
+            &self.symbol,
+            params,
+            self.precision,
+            self.hidden,
+            &self.log_target(),
+        )
+        .await?;
+        self.owned_offers.insert(offer.id)?;
+        self.budget.deploy(loan_amount);
+        self.state.set_submission(
+            &self.name,
+            &self.currency,
+            &Submission {
+                timestamp: common::unix_time()?,
+                rate,
+                amount: loan_amount,
+                period: self.period,
+                offer_id: Some(offer.id),
+            },
+        )?;
+
+        log::info!(
+            target: &self.log_target(),
+            "Offer submitted: {:.2} for {} days @ {} per day ({} APR)",
+            loan_amount,
+            self.period,
+            self.rate_display.rate(rate),
+            self.rate_display.apr(rate * 365.)
+        );
+        common::log_projected_interest(
+            &self.client,
+            &self.price_feed,
+            &self.currency,
+            loan_amount,
+            rate,
+            &self.log_target(),
+        )
+        .await;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNDERCUT: f64 = 0.000_001;
+    const INTERVAL: Duration = Duration::from_secs(300);
+
+    #[test]
+    fn target_rate_never_goes_below_the_floor() {
+        assert_eq!(target_rate(0.0002, UNDERCUT, 0.0001), 0.0002 - UNDERCUT);
+        assert_eq!(target_rate(0.0001, UNDERCUT, 0.0001), 0.0001);
+    }
+
+    #[test]
+    fn small_moves_are_ignored() {
+        let offer_rate = 0.0002;
+
+        assert!(!should_reprice(
+            offer_rate,
+            offer_rate + UNDERCUT / 2.,
+            UNDERCUT,
+            None,
+            INTERVAL
+        ));
+        assert!(!should_reprice(
+            offer_rate,
+            offer_rate - UNDERCUT / 2.,
+            UNDERCUT,
+            None,
+            INTERVAL
+        ));
+    }
+
+    #[test]
+    fn large_moves_are_repriced_after_the_interval() {
+        let (offer_rate, target_rate) = (0.0002, 0.00019);
+
+        assert!(should_reprice(
+            offer_rate,
+            target_rate,
+            UNDERCUT,
+            None,
+            INTERVAL
+        ));
+        assert!(should_reprice(
+            offer_rate,
+            target_rate,
+            UNDERCUT,
+            Some(INTERVAL),
+            INTERVAL
+        ));
+        assert!(!should_reprice(
+            offer_rate,
+            target_rate,
+            UNDERCUT,
+            Some(Duration::from_secs(60)),
+            INTERVAL
+        ));
+    }
+}
@@ -0,0 +1,236 @@
+/// An aggregated level of the funding book.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BookLevel {
+    pub rate: f64,
+    pub period: u8,
+    /// Absolute amount available at this level.
+    pub amount: f64,
+}
+
+/// A snapshot of the funding book split by side.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FundingBook {
+    /// Offers (lenders side), sorted by increasing rate.
+    pub asks: Vec<BookLevel>,
+    /// Bids (borrowers side), sorted by decreasing rate.
+    pub bids: Vec<BookLevel>,
+}
+
+impl FundingBook {
+    /// Build the book from raw `(rate, period, amount)` entries as returned by the Bitfinex API,
+    /// where positive amounts are asks and negative amounts are bids.
+    pub fn from_entries(entries: impl IntoIterator<Item = (f64, u8, f64)>) -> Self {
+        let mut book = Self::default();
+
+        for (rate, period, amount) in entries {
+            let level = BookLevel {
+                rate,
+                period,
+                amount: amount.abs(),
+            };
+
+            if amount > 0. {
+                book.asks.push(level);
+            } else if amount < 0. {
+                book.bids.push(level);
+            }
+        }
+
+        book.asks.sort_by(|a, b| a.rate.total_cmp(&b.rate));
+        book.bids.sort_by(|a, b| b.rate.total_cmp(&a.rate));
+
+        book
+    }
+}
+
+/// Return the rate of the level at which `cumulative_amount` is reached when walking `levels`
+/// from the top of the book, or the rate of the last level if the book is thinner than that.
+///
+/// Return `None` if there are no levels at all.
+pub fn depth_rate(levels: &[BookLevel], cumulative_amount: f64) -> Option<f64> {
+    let mut cumulated = 0.;
+
+    for level in levels {
+        cumulated += level.amount;
+        if cumulated >= cumulative_amount {
+            return Some(level.rate);
+        }
+    }
+
+    levels.last().map(|level| level.rate)
+}
+
+/// Rate at which an amount would be absorbed by a side of the book.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Absorption {
+    pub rate: f64,
+    /// Whether the book is thinner than the amount.
+    pub thin: bool,
+}
+
+/// Return the depth-weighted rate at which `amount` would be absorbed when walking `levels` from
+/// the top of the book: the rates of the levels consumed weighted by the amount taken from each.
+///
+/// If the book is thinner than `amount`, the rate of the worst level is returned and the
+/// absorption is flagged as thin. Return `None` if there are no levels at all.
+pub fn absorption_rate(levels: &[BookLevel], amount: f64) -> Option<Absorption> {
+    let worst = levels.last()?;
+
+    let mut remaining = amount;
+    let mut weighted_rates = 0.;
+
+    for level in levels {
+        let taken = level.amount.min(remaining);
+        weighted_rates += level.rate * taken;
+        remaining -= taken;
+
+        if remaining <= 0. {
+            return Some(Absorption {
+                rate: weighted_rates / amount,
+                thin: false,
+            });
+        }
+    }
+
+    Some(Absorption {
+        rate: worst.rate,
+        thin: true,
+    })
+}
+
+/// Return the cumulative amount of the `levels` at `min_rate` or above.
+pub fn depth_above(levels: &[BookLevel], min_rate: f64) -> f64 {
+    levels
+        .iter()
+        .filter(|level| level.rate >= min_rate)
+        .map(|level| level.amount)
+        .sum()
+}
+
+/// Return the spread of `rate` over the best bid of `book`, relative to `rate`: positive when the
+/// borrowers bid below it. Return `None` if there are no bids.
+pub fn borrow_spread(book: &FundingBook, rate: f64) -> Option<f64> {
+    book.bids
+        .first()
+        .map(|best_bid| (rate - best_bid.rate) / rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// fUSD book snapshot (rate, period, count, amount) with asks and bids.
+    const USD_SNAPSHOT: [(f64, u8, u32, f64); 8] = [
+        (0.0002, 2, 3, 25_000.),
+        (0.00021, 2, 5, 100_000.),
+        (0.00019, 2, 1, 4_000.),
+        (0.00025, 30, 8, 400_000.),
+        (0.00018, 2, 2, -50_000.),
+        (0.00015, 7, 4, -120_000.),
+        (0.000185, 2, 1, -2_000.),
+        (0.0003, 120, 2, 1_000_000.),
+    ];
+
+    fn book(snapshot: &[(f64, u8, u32, f64)]) -> FundingBook {
+        FundingBook::from_entries(
+            snapshot
+                .iter()
+                .map(|&(rate, period, _, amount)| (rate, period, amount)),
+        )
+    }
+
+    #[test]
+    fn splits_and_sorts_sides() {
+        let book = book(&USD_SNAPSHOT);
+
+        let ask_rates: Vec<f64> = book.asks.iter().map(|level| level.rate).collect();
+        assert_eq!(ask_rates, vec![0.00019, 0.0002, 0.00021, 0.00025, 0.0003]);
+
+        let bid_rates: Vec<f64> = book.bids.iter().map(|level| level.rate).collect();
+        assert_eq!(bid_rates, vec![0.000185, 0.00018, 0.00015]);
+        assert!(book.bids.iter().all(|level| level.amount > 0.));
+    }
+
+    #[test]
+    fn depth_rate_walks_the_asks() {
+        let book = book(&USD_SNAPSHOT);
+
+        assert_eq!(depth_rate(&book.asks, 1_000.), Some(0.00019));
+        assert_eq!(depth_rate(&book.asks, 29_000.), Some(0.0002));
+        assert_eq!(depth_rate(&book.asks, 100_000.), Some(0.00021));
+        assert_eq!(depth_rate(&book.asks, 500_000.), Some(0.00025));
+    }
+
+    #[test]
+    fn depth_rate_on_thin_book_uses_last_level() {
+        let book = book(&USD_SNAPSHOT);
+
+        assert_eq!(depth_rate(&book.asks, 10_000_000.), Some(0.0003));
+    }
+
+    fn assert_absorption(absorption: Option<Absorption>, rate: f64, thin: bool) {
+        let absorption = absorption.unwrap();
+        assert!((absorption.rate - rate).abs() < 1e-12, "{absorption:?}");
+        assert_eq!(absorption.thin, thin);
+    }
+
+    #[test]
+    fn absorption_within_the_top_level() {
+        let book = book(&USD_SNAPSHOT);
+
+        assert_absorption(absorption_rate(&book.bids, 1_000.), 0.000185, false);
+        assert_absorption(absorption_rate(&book.bids, 2_000.), 0.000185, false);
+    }
+
+    #[test]
+    fn absorption_is_weighted_by_depth() {
+        let book = book(&USD_SNAPSHOT);
+
+        // 2k @ 0.0185% + 50k @ 0.018% + 48k @ 0.015%.
+        let expected = (2_000. * 0.000185 + 50_000. * 0.00018 + 48_000. * 0.00015) / 100_000.;
+        assert_absorption(absorption_rate(&book.bids, 100_000.), expected, false);
+    }
+
+    #[test]
+    fn absorption_on_thin_book_uses_worst_level() {
+        let book = book(&USD_SNAPSHOT);
+        assert_absorption(absorption_rate(&book.bids, 1_000_000.), 0.00015, true);
+
+        let shallow = self::book(&[(0.0002, 2, 1, -500.)]);
+        assert_absorption(absorption_rate(&shallow.bids, 1_000.), 0.0002, true);
+        assert_eq!(absorption_rate(&shallow.asks, 1_000.), None);
+    }
+
+    #[test]
+    fn empty_and_one_sided_books() {
+        let empty = book(&[]);
+        assert_eq!(depth_rate(&empty.asks, 1_000.), None);
+        assert_eq!(depth_rate(&empty.bids, 1_000.), None);
+
+        let bids_only = book(&[(0.0001, 2, 1, -1_000.)]);
+        assert_eq!(depth_rate(&bids_only.asks, 1_000.), None);
+        assert_eq!(depth_rate(&bids_only.bids, 1_000.), Some(0.0001));
+    }
+
+    #[test]
+    fn depth_above_a_rate() {
+        let book = book(&USD_SNAPSHOT);
+
+        assert_eq!(depth_above(&book.bids, 0.00018), 52_000.);
+        assert_eq!(depth_above(&book.bids, 0.0001), 172_000.);
+        assert_eq!(depth_above(&book.bids, 0.0002), 0.);
+    }
+
+    #[test]
+    fn borrow_spread_over_the_best_bid() {
+        let book = book(&USD_SNAPSHOT);
+
+        let spread = borrow_spread(&book, 0.0002).unwrap();
+        assert!((spread - 0.075).abs() < 1e-12, "{spread}");
+        assert!(borrow_spread(&book, 0.00017).unwrap() < 0.);
+        assert_eq!(
+            borrow_spread(&self::book(&[(0.0002, 2, 1, 500.)]), 0.0002),
+            None
+        );
+    }
+}
@@ -0,0 +1,381 @@
+use std::{collections::HashMap, fmt};
+
+use anyhow::{ensure, Context, Ok, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use bitfinex_api::bitfinex::AsyncBitfinex;
+
+use crate::{
+    budget::AccountBudget,
+    config::{self, ConfigFiles},
+};
+
+use super::{
+    book::{self, BookLevel},
+    common,
+    owned_offers::OwnedOffers,
+    precision::Precision,
+    rate_display::{self, RateDisplay},
+    rate_guard::{self, RateGuard},
+    FromConfig, Shared, Strategy,
+};
+
+/// Smallest rate increment used to place our offer just inside a book level.
+const RATE_TICK: f64 = 0.000_000_01;
+
+/// Return the rate just inside the ask level at which `cumulative_amount` is already offered,
+/// or `None` if there are no asks.
+fn target_rate(asks: &[BookLevel], cumulative_amount: f64) -> Option<f64> {
+    book::depth_rate(asks, cumulative_amount).map(|rate| rate - RATE_TICK)
+}
+
+pub struct BookStrategy {
+    name: String,
+    client: AsyncBitfinex,
+    currency: String,
+    symbol: String,
+    min_amount: f64,
+    max_balance_percent_per_loan: f64,
+    min_rate: f64,
+    period: u8,
+    cumulative_amount: f64,
+    rate_guard: RateGuard,
+    precision: Precision,
+    hidden: bool,
+    owned_offers: OwnedOffers,
+    rate_display: RateDisplay,
+    budget: AccountBudget,
+}
+
+impl BookStrategy {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        name: String,
+        client: AsyncBitfinex,
+        currency: String,
+        symbol: String,
+        min_amount: f64,
+        max_balance_percent_per_loan: f64,
+        min_rate: f64,
+        period: u8,
+        cumulative_amount: f64,
+        rate_guard: RateGuard,
+        precision: Precision,
+        hidden: bool,
+        owned_offers: OwnedOffers,
+        rate_display: RateDisplay,
+        budget: AccountBudget,
+    ) -> Self {
+        Self {
+            name,
+            client,
+            currency,
+            symbol,
+            min_amount,
+            max_balance_percent_per_loan,
+            min_rate,
+            period,
+            cumulative_amount,
+            rate_guard,
+            precision,
+            hidden,
+            owned_offers,
+            rate_display,
+            budget,
+        }
+    }
+}
+
+impl fmt::Display for BookStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {}: {} days offers inside the ask level reaching {:.2} @ min {} per day",
+            self.name(),
+            self.currency,
+            self.period,
+            self.cumulative_amount,
+            self.rate_display.rate(self.min_rate)
+        )
+    }
+}
+
+impl FromConfig for BookStrategy {
+    fn from_config(files: &ConfigFiles, shared: &Shared) -> Result<Vec<Self>> {
+        #[derive(Debug, Deserialize)]
+        struct Strategy {
+            keys: String,
+            currency: String,
+            /// Funding symbol, defaults to `f{currency}`.
+            #[serde(default, alias = "funding_symbol")]
+            symbol: Option<String>,
+            min_amount: f64,
+            max_balance_percent_per_loan: f64,
+            min_rate: f64,
+            period: u8,
+            cumulative_amount: f64,
+            #[serde(default = "rate_guard::default_max_rate")]
+            max_rate: f64,
+            #[serde(default = "rate_guard::default_sanity_max_apr")]
+            sanity_max_apr: f64,
+            #[serde(default = "common::default_enabled")]
+            enabled: bool,
+            #[serde(default = "common::default_hidden")]
+            hidden: bool,
+            #[serde(default = "rate_display::default_rate_decimals")]
+            rate_display_decimals: usize,
+            #[serde(default = "rate_display::default_apr_decimals")]
+            apr_display_decimals: usize,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[serde(default)]
+            book_strategies: HashMap<String, Strategy>,
+        }
+
+        let config: Config = config::read(files)?;
+
+        config
+            .book_strategies
+            .into_iter()
+            .filter(|(name, strategy)| common::is_enabled(name, strategy.enabled))
+            .map(|(name, strategy)| {
+                ensure!(
+                    strategy.min_amount > 0.,
+                    "{name}: min_amount must be positive"
+                );
+                ensure!(
+                    strategy.max_balance_percent_per_loan > 0.
+                        && strategy.max_balance_percent_per_loan <= 1.,
+                    "{name}: max_balance_percent_per_loan must be within (0, 1]"
+                );
+                ensure!(
+                    strategy.min_rate >= 0.,
+                    "{name}: min_rate must not be negative"
+                );
+                common::validate_period(strategy.period)
+                    .with_context(|| format!("{name}: invalid period"))?;
+                ensure!(
+                    strategy.cumulative_amount > 0.,
+                    "{name}: cumulative_amount must be positive"
+                );
+
+                let rate_display = RateDisplay::new(
+                    strategy.rate_display_decimals,
+                    strategy.apr_display_decimals,
+                );
+                rate_display
+                    .validate()
+                    .with_context(|| format!("{name}: invalid display decimals"))?;
+                let rate_guard = RateGuard::new(
+                    strategy.max_rate,
+                    strategy.sanity_max_apr,
+                    rate_display,
+                    shared.notifier.clone(),
+                );
+                rate_guard
+                    .validate()
+                    .with_context(|| format!("{name}: invalid rate ceilings"))?;
+
+                let account_budget = shared.budget.account(&strategy.keys, &strategy.currency);
+                let precision = shared.precisions.get(&strategy.currency);
+                let symbol = common::funding_symbol(&strategy.currency, strategy.symbol);
+                let owned_offers = OwnedOffers::load(
+                    &name,
+                    &strategy.currency,
+                    shared.state.clone(),
+                    rate_display,
+                );
+
+                Ok(Self::new(
+                    name,
+                    common::client_from_keys(&strategy.keys)?,
+                    strategy.currency,
+                    symbol,
+                    strategy.min_amount,
+                    strategy.max_balance_percent_per_loan,
+                    strategy.min_rate,
+                    strategy.period,
+                    strategy.cumulative_amount,
+                    rate_guard,
+                    precision,
+                    strategy.hidden,
+                    owned_offers,
+                    rate_display,
+                    account_budget,
+                ))
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid book_strategies in {files}"))
+    }
+}
+
+#[async_trait]
+impl Strategy for BookStrategy {
+    type Output = Result<()>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Cancel the offers submitted by the strategy.
+    async fn cancel_offers(&self) -> Self::Output {
+        self.owned_offers
+            .cancel_all(&self.client, &self.symbol, &self.log_target())
+            .await
+    }
+
+    /// Execute the strategy.
+    async fn execute(&self) -> Self::Output {
+        log::info!(target: &self.log_target(), "Executing {} on {}...", self.name(), self.currency);
+
+        let funding_wallet = common::funding_wallet(&self.client, &self.currency).await?;
+        let active_offer = self
+            .owned_offers
+            .active_offer(&self.client, &self.symbol, &self.log_target())
+            .await?;
+
+        let available_balance = funding_wallet.available_balance
+            + active_offer
+                .as_ref()
+                .map_or(0., |active_offer| active_offer.amount);
+        let total_balance = funding_wallet.balance;
+
+        self.budget
+            .update(total_balance, total_balance - available_balance);
+
+        // Early return if there is not enough available balance to create an offer.
+        if available_balance < self.min_amount {
+            log::info!(
+                target: &self.log_target(),
+                "Insufficient balance to submit a lend offer: {available_balance:.2} < {:.2}",
+                self.min_amount
+            );
+            return Ok(());
+        }
+
+        let funding_book = common::funding_book(&self.client, &self.symbol).await?;
+
+        let Some(rate) = target_rate(&funding_book.asks, self.cumulative_amount) else {
+            log::info!(
+                target: &self.log_target(),
+                "No ask on the {} funding book, skipping",
+                self.symbol
+            );
+            return Ok(());
+        };
+
+        // Never undercut below the floor.
+        let rate = if rate < self.min_rate {
+            log::info!(
+                target: &self.log_target(),
+                "Book target rate is below the floor: {} < {} per day",
+                self.rate_display.rate(rate),
+                self.rate_display.rate(self.min_rate)
+            );
+            self.min_rate
+        } else {
+            rate
+        };
+
+        // Clamp the amount to loan as a fraction of the total balance and to the global budget.
+        let loan_amount = self
+            .min_amount
+            .max(available_balance.min(total_balance * self.max_balance_percent_per_loan))
+            .min(self.budget.remaining());
+
+        if loan_amount < self.min_amount {
+            log::info!(
+                target: &self.log_target(),
+                "Global deployment budget exhausted for {}: {loan_amount:.2} < {:.2}",
+                self.currency,
+                self.min_amount
+            );
+            return Ok(());
+        }
+
+        let Some(params) =
+            self.rate_guard
+                .offer_params(loan_amount, rate, self.period, &self.log_target())
+        else {
+            return Ok(());
+        };
+
+        // Check if the active offer needs to be canceled.
+        if let Some(active_offer) = active_offer {
+            let rate_diff_percent = (active_offer.rate - rate).abs() / rate;
+            let amount_diff = (loan_amount - active_offer.amount).abs();
+
+            if active_offer.period != self.period || amount_diff > 1. || rate_diff_percent > 0.01 {
+                self.owned_offers
+                    .cancel(&self.client, active_offer.id, &self.log_target())
+                    .await?;
+            } else {
+                log::info!(
+                    target: &self.log_target(),
+                    "Active offer is good enough: {:.2} for {} days @ {} per day",
+                    active_offer.amount,
+                    active_offer.period,
+                    self.rate_display.rate(active_offer.rate)
+                );
+                self.budget.deploy(active_offer.amount);
+                return Ok(());
+            }
+        }
+
+        let offer = common::submit_offer(
+            &self.client,
+            &self.symbol,
+            params,
+            self.precision,
+            self.hidden,
+            &self.log_target(),
+        )
+        .await?;
+        self.owned_offers.insert(offer.id)?;
+        self.budget.deploy(offer.amount);
+
+        log::info!(
+            target: &self.log_target(),
+            "Offer {} submitted: {:.2} for {} days @ {} per day ({} APR)",
+            offer.id,
+            offer.amount,
+            offer.period,
+            self.rate_display.rate(offer.rate),
+            self.rate_display.apr(offer.rate * 365.)
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asks(levels: &[(f64, f64)]) -> Vec<BookLevel> {
+        levels
+            .iter()
+            .map(|&(rate, amount)| BookLevel {
+                rate,
+                period: 2,
+                amount,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn target_rate_is_just_inside_the_level() {
+        let asks = asks(&[(0.0002, 10_000.), (0.00021, 50_000.), (0.00025, 200_000.)]);
+
+        assert_eq!(target_rate(&asks, 40_000.), Some(0.00021 - RATE_TICK));
+        assert_eq!(target_rate(&asks, 5_000.), Some(0.0002 - RATE_TICK));
+    }
+
+    #[test]
+    fn target_rate_without_asks() {
+        assert_eq!(target_rate(&[], 40_000.), None);
+    }
+}
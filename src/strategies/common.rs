@@ -0,0 +1,475 @@
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, ensure, Context, Ok, Result};
+use serde_json::Value;
+
+use bitfinex_api::{
+    api::{
+        authenticated::{
+            funding::{
+                active_funding_credits::{ActiveFundingCredits, ActiveFundingCreditsResp},
+                active_funding_offers::{ActiveFundingOffers, ActiveFundingOffersResp},
+                cancel_funding_offer::CancelFundingOffer,
+                funding_auto_renew::FundingAutoRenew,
+                funding_auto_renew_status::{FundingAutoRenewStatus, FundingAutoRenewStatusResp},
+                submit_funding_offer::{SubmitFundingOffer, SubmitFundingOfferResp},
+                types::{FundingOffer, FundingOfferType},
+            },
+            wallets::{WalletResp, WalletType, Wallets, WalletsResp},
+        },
+        common::Sort,
+        ignore::ignore,
+        public::{
+            book::{Book, BookPrecision, FundingBookResp},
+            candles::{AvailableCandles, Candles, HistCandlesResp, LastCandleResp},
+            conf::Conf,
+            funding_stats::{FundingStats, FundingStatsResp},
+            ticker::{FundingTickerResp, Ticker},
+        },
+        query::AsyncQuery,
+    },
+    bitfinex::AsyncBitfinex,
+};
+
+use crate::price_feed::PriceFeed;
+
+use super::{
+    book::FundingBook,
+    error,
+    precision::Precision,
+    rate_guard::OfferParams,
+    rate_selector::RateSelector,
+    timeframe::{CandleSection, CandleTimeFrame},
+};
+
+/// Key of the Bitfinex public conf of the funding currencies.
+const FUNDING_INFO_CONF: &str = "pub:info:funding";
+
+/// Build an authenticated client from the `API_KEY_<keys>` and `SECRET_KEY_<keys>` env variables.
+pub fn client_from_keys(keys: &str) -> Result<AsyncBitfinex> {
+    let api_key_env = format!("API_KEY_{keys}");
+    let secret_key_env = format!("SECRET_KEY_{keys}");
+
+    let api_key =
+        env::var(&api_key_env).with_context(|| format!("Missing {api_key_env} env variable"))?;
+    let secret_key = env::var(&secret_key_env)
+        .with_context(|| format!("Missing {secret_key_env} env variable"))?;
+
+    Ok(AsyncBitfinex::new_auth(&api_key, &secret_key))
+}
+
+/// Default of the `enabled` flag of the strategies.
+pub fn default_enabled() -> bool {
+    true
+}
+
+/// Default of the `hidden` flag of the strategies, the offers being hidden from the public book.
+pub fn default_hidden() -> bool {
+    true
+}
+
+/// Return whether the strategy `name` is enabled, logging it once at startup otherwise.
+pub fn is_enabled(name: &str, enabled: bool) -> bool {
+    if !enabled {
+        log::info!("{name} is disabled, skipping it");
+    }
+
+    enabled
+}
+
+/// Bounds, in days, of the funding periods accepted by Bitfinex.
+pub const MIN_PERIOD: u8 = 2;
+pub const MAX_PERIOD: u8 = 120;
+
+/// Ensure that `period` is a funding period accepted by Bitfinex.
+pub fn validate_period(period: u8) -> Result<()> {
+    ensure!(
+        (MIN_PERIOD..=MAX_PERIOD).contains(&period),
+        "period must be within [{MIN_PERIOD}, {MAX_PERIOD}] days, got {period}"
+    );
+
+    Ok(())
+}
+
+/// Return `period` clamped to the funding periods accepted by Bitfinex, warning to `log_target`
+/// when it was not, as a last line of defense against an offer rejected every cycle.
+pub fn clamp_period(period: u8, log_target: &str) -> u8 {
+    let clamped = period.clamp(MIN_PERIOD, MAX_PERIOD);
+    if clamped != period {
+        log::warn!(
+            target: log_target,
+            "Period of {period} days out of bounds, clamped to {clamped} days"
+        );
+    }
+
+    clamped
+}
+
+/// Return the current UNIX timestamp, in seconds.
+pub fn unix_time() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+/// Return the hour of the day (UTC) of the unix `timestamp`, in seconds.
+pub fn utc_hour(timestamp: u64) -> u8 {
+    (timestamp / 3600 % 24) as u8
+}
+
+/// Return the funding symbol of `currency`: the `symbol` (or `funding_symbol`) override if any,
+/// used verbatim, `f{currency}` otherwise.
+///
+/// The Bitfinex funding markets are named after their currency code (`fUSD`, `fUST` for USDt,
+/// `fBTC`...), which is usually but not always the currency of the funding wallet. The override
+/// targets the markets whose symbol differs, and every call of the strategy (candles, book,
+/// offers, credits and submissions) then uses it.
+pub fn funding_symbol(currency: &str, symbol: Option<String>) -> String {
+    symbol.unwrap_or_else(|| format!("f{currency}"))
+}
+
+/// Fetch the funding wallet of the given currency from Bitfinex API.
+pub async fn funding_wallet(client: &AsyncBitfinex, currency: &str) -> Result<WalletResp> {
+    let wallets: WalletsResp = Wallets::builder().build()?.query_async(client).await?;
+
+    let funding_wallet = wallets
+        .into_iter()
+        .find(|wallet| wallet.ty == WalletType::Funding && wallet.currency == currency)
+        .ok_or(anyhow!("Funding wallet not found"))?;
+
+    Ok(funding_wallet)
+}
+
+/// Fetch all the active funding offers of the given funding symbol from Bitfinex API.
+pub async fn active_offers(
+    client: &AsyncBitfinex,
+    symbol: &str,
+) -> Result<ActiveFundingOffersResp> {
+    let active_offers: ActiveFundingOffersResp = ActiveFundingOffers::builder()
+        .symbol(symbol)
+        .build()?
+        .query_async(client)
+        .await?;
+
+    Ok(active_offers)
+}
+
+/// Fetch the active funding credits (funds currently lent) of the given funding symbol from
+/// Bitfinex API.
+///
+/// Unlike the history endpoints, the active credits endpoint is not paginated: it returns all
+/// the active credits at once, however many.
+pub async fn active_credits(
+    client: &AsyncBitfinex,
+    symbol: &str,
+) -> Result<ActiveFundingCreditsResp> {
+    let active_credits: ActiveFundingCreditsResp = ActiveFundingCredits::builder()
+        .symbol(symbol)
+        .build()?
+        .query_async(client)
+        .await?;
+
+    Ok(active_credits)
+}
+
+/// Cancel the offer `id`, succeeding if it is already gone, e.g. filled or canceled since it was
+/// fetched, which is logged to `log_target`.
+pub async fn cancel_offer(client: &AsyncBitfinex, id: u64, log_target: &str) -> Result<()> {
+    let result: Result<()> = ignore(CancelFundingOffer::builder().id(id).build()?)
+        .query_async(client)
+        .await
+        .map_err(Into::into);
+
+    if error::offer_already_gone(result)? {
+        log::info!(
+            target: log_target,
+            "Offer {id} is already gone (filled or canceled), nothing to cancel"
+        );
+    }
+
+    Ok(())
+}
+
+/// Submit a limit funding offer of `params`, `hidden` from the public book or not, on the given
+/// funding symbol and return the offer accepted by Bitfinex, the amount and the rate being rounded
+/// down to the `precision` it accepts. A rejection is an error, the corrections being logged to
+/// `log_target`.
+pub async fn submit_offer(
+    client: &AsyncBitfinex,
+    symbol: &str,
+    params: OfferParams,
+    precision: Precision,
+    hidden: bool,
+    log_target: &str,
+) -> Result<FundingOffer> {
+    let resp: SubmitFundingOfferResp = SubmitFundingOffer::builder()
+        .ty(FundingOfferType::Limit)
+        .symbol(symbol)
+        .amount(precision.amount(params.amount()))
+        .rate(precision.rate(params.rate()))
+        .period(clamp_period(params.period(), log_target))
+        .hidden(hidden)
+        .build()?
+        .query_async(client)
+        .await?;
+
+    Ok(resp.offer)
+}
+
+/// Log to `log_target` the daily interest projected for `amount` lent at `rate`, converted to the
+/// quote currency if the price feed is enabled.
+pub async fn log_projected_interest(
+    client: &AsyncBitfinex,
+    price_feed: &PriceFeed,
+    currency: &str,
+    amount: f64,
+    rate: f64,
+    log_target: &str,
+) {
+    let interest = amount * rate;
+
+    match price_feed.convert(client, currency, interest).await {
+        Some((value, quote_currency)) => log::info!(
+            target: log_target,
+            "Projected interest: {interest:.4} {currency} per day (~{value:.2} {quote_currency})"
+        ),
+        None => log::info!(
+            target: log_target,
+            "Projected interest: {interest:.4} {currency} per day"
+        ),
+    }
+}
+
+/// Fetch the funding auto-renew settings of the given currency from Bitfinex API: the rate and
+/// the period, or `None` if it is disabled.
+pub async fn auto_renew(client: &AsyncBitfinex, currency: &str) -> Result<Option<(f64, u8)>> {
+    let status: FundingAutoRenewStatusResp = FundingAutoRenewStatus::builder()
+        .currency(currency)
+        .build()?
+        .query_async(client)
+        .await?;
+
+    Ok(status.map(|settings| (settings.rate, settings.period)))
+}
+
+/// Enable the funding auto-renew of the given currency at `rate` for `period` days, on the whole
+/// balance, or disable it if `settings` is `None`.
+pub async fn set_auto_renew(
+    client: &AsyncBitfinex,
+    currency: &str,
+    settings: Option<(f64, u8)>,
+) -> Result<()> {
+    let endpoint = match settings {
+        Some((rate, period)) => FundingAutoRenew::builder()
+            .status(true)
+            .currency(currency)
+            .rate(rate)
+            .period(period)
+            .amount(0.)
+            .build()?,
+        None => FundingAutoRenew::builder()
+            .status(false)
+            .currency(currency)
+            .build()?,
+    };
+
+    ignore(endpoint).query_async(client).await?;
+
+    Ok(())
+}
+
+/// Fetch the current Flash Return Rate of the given funding symbol from the Bitfinex funding
+/// ticker.
+pub async fn frr(client: &AsyncBitfinex, symbol: &str) -> Result<f64> {
+    let ticker: FundingTickerResp = Ticker::builder()
+        .symbol(symbol)
+        .build()?
+        .query_async(client)
+        .await?;
+
+    Ok(ticker.frr)
+}
+
+/// Fetch the latest funding stats of the given funding symbol from the Bitfinex API and return
+/// the fraction of the funding in use.
+pub async fn funding_utilization(client: &AsyncBitfinex, symbol: &str) -> Result<f64> {
+    let stats: FundingStatsResp = FundingStats::builder()
+        .symbol(symbol)
+        .limit(1)
+        .build()?
+        .query_async(client)
+        .await?;
+
+    let latest = stats.first().ok_or(anyhow!("No funding stats available"))?;
+    ensure!(
+        latest.funding_amount > 0.,
+        "No funding provided on {symbol}"
+    );
+
+    Ok(latest.funding_amount_used / latest.funding_amount)
+}
+
+/// Fetch the minimum funding offer size of `currency` from the Bitfinex public conf.
+pub async fn platform_min_amount(client: &AsyncBitfinex, currency: &str) -> Result<f64> {
+    let conf: Value = Conf::builder()
+        .key(FUNDING_INFO_CONF)
+        .build()?
+        .query_async(client)
+        .await?;
+
+    min_order_size(&conf, currency)
+}
+
+/// Return the minimum funding offer size of `currency` from the funding info `conf`.
+///
+/// The conf lists `[currency, [_, _, _, min_order_size, max_order_size, ...]]` entries, the sizes
+/// being strings.
+fn min_order_size(conf: &Value, currency: &str) -> Result<f64> {
+    let entry = conf
+        .get(0)
+        .and_then(Value::as_array)
+        .and_then(|entries| {
+            entries
+                .iter()
+                .find(|entry| entry.get(0).and_then(Value::as_str) == Some(currency))
+        })
+        .with_context(|| format!("No funding info for {currency}"))?;
+
+    let min_order_size = entry
+        .get(1)
+        .and_then(|info| info.get(3))
+        .with_context(|| format!("No minimum order size for {currency}"))?;
+
+    match min_order_size {
+        Value::String(min_order_size) => Ok(min_order_size.parse()?),
+        _ => min_order_size
+            .as_f64()
+            .with_context(|| format!("Invalid minimum order size for {currency}")),
+    }
+}
+
+/// Fetch the funding book of the given funding symbol from the Bitfinex API.
+pub async fn funding_book(client: &AsyncBitfinex, symbol: &str) -> Result<FundingBook> {
+    let entries: FundingBookResp = Book::builder()
+        .symbol(symbol)
+        .precision(BookPrecision::P0)
+        .build()?
+        .query_async(client)
+        .await?;
+
+    Ok(FundingBook::from_entries(
+        entries
+            .into_iter()
+            .map(|entry| (entry.rate, entry.period, entry.amount)),
+    ))
+}
+
+/// Fetch the `time_frame` candles of the last `window` hours from the Bitfinex API, in
+/// chronological order, or only the latest one for the `Last` section.
+pub async fn candles(
+    client: &AsyncBitfinex,
+    symbol: &str,
+    window: u64,
+    period: u8,
+    time_frame: CandleTimeFrame,
+    section: CandleSection,
+) -> Result<HistCandlesResp> {
+    let candles = AvailableCandles::FundingCandles {
+        time_frame: time_frame.time_frame(),
+        currency: symbol,
+        period,
+    };
+
+    // The last section is a single candle rather than a list.
+    if section == CandleSection::Last {
+        let candle: LastCandleResp = Candles::builder()
+            .candles(candles)
+            .section(section.section())
+            .build()?
+            .query_async(client)
+            .await?;
+
+        return Ok(vec![candle]);
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+
+    let start_mts = now - (window as u128 * 3600 * 1000);
+
+    let candles: HistCandlesResp = Candles::builder()
+        .candles(candles)
+        .section(section.section())
+        .sort(Sort::Asc)
+        .start(start_mts as _)
+        .limit(10_000)
+        .build()?
+        .query_async(client)
+        .await?;
+
+    Ok(candles)
+}
+
+/// Fetch the candles of the last `monitored_window` hours from the Bitfinex API and select the
+/// rate from their highs.
+pub async fn candle_rate(
+    client: &AsyncBitfinex,
+    symbol: &str,
+    monitored_window: u64,
+    rate_selector: &RateSelector,
+    period: u8,
+    time_frame: CandleTimeFrame,
+) -> Result<f64> {
+    let candles = candles(
+        client,
+        symbol,
+        monitored_window,
+        period,
+        time_frame,
+        CandleSection::Hist,
+    )
+    .await?;
+    let highs: Vec<f64> = candles.iter().map(|candle| candle.high).collect();
+
+    rate_selector
+        .select(&highs)
+        .ok_or(anyhow!("Not enough candles fetched"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn period_bounds() {
+        assert!(validate_period(1).is_err());
+        assert!(validate_period(2).is_ok());
+        assert!(validate_period(120).is_ok());
+        assert!(validate_period(121).is_err());
+    }
+
+    #[test]
+    fn min_order_size_of_the_funding_info() {
+        let conf = serde_json::json!([[
+            ["USD", [null, null, null, "150.0", "1000000.0"]],
+            ["BTC", [null, null, null, 0.005, 100]],
+            ["ETH", [null, null, null]],
+        ]]);
+
+        assert_eq!(min_order_size(&conf, "USD").unwrap(), 150.);
+        assert_eq!(min_order_size(&conf, "BTC").unwrap(), 0.005);
+        assert!(min_order_size(&conf, "ETH").is_err());
+        assert!(min_order_size(&conf, "UST").is_err());
+        assert!(min_order_size(&serde_json::json!({}), "USD").is_err());
+    }
+
+    #[test]
+    fn periods_are_clamped_to_the_bounds() {
+        assert_eq!(clamp_period(0, "test"), 2);
+        assert_eq!(clamp_period(1, "test"), 2);
+        assert_eq!(clamp_period(2, "test"), 2);
+        assert_eq!(clamp_period(30, "test"), 30);
+        assert_eq!(clamp_period(120, "test"), 120);
+        assert_eq!(clamp_period(200, "test"), 120);
+    }
+}
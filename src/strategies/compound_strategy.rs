@@ -0,0 +1,218 @@
+use std::{collections::HashMap, fmt};
+
+use anyhow::{ensure, Context, Ok, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use bitfinex_api::{
+    api::{
+        authenticated::{
+            transfer::Transfer,
+            wallets::{WalletType, Wallets, WalletsResp},
+        },
+        ignore::ignore,
+        query::AsyncQuery,
+    },
+    bitfinex::AsyncBitfinex,
+};
+
+use crate::config::{self, ConfigFiles};
+
+use super::{common, FromConfig, Shared, Strategy};
+
+/// Wallet swept into the funding wallet.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceWallet {
+    Exchange,
+    Margin,
+}
+
+impl SourceWallet {
+    fn wallet_type(&self) -> WalletType {
+        match self {
+            SourceWallet::Exchange => WalletType::Exchange,
+            SourceWallet::Margin => WalletType::Margin,
+        }
+    }
+}
+
+impl fmt::Display for SourceWallet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceWallet::Exchange => write!(f, "exchange"),
+            SourceWallet::Margin => write!(f, "margin"),
+        }
+    }
+}
+
+/// Sweep the balances of other wallets into the funding wallet, so that they get lent.
+pub struct CompoundStrategy {
+    name: String,
+    client: AsyncBitfinex,
+    currency: String,
+    source_wallets: Vec<SourceWallet>,
+    threshold: f64,
+    dry_run: bool,
+}
+
+impl CompoundStrategy {
+    pub fn new(
+        name: String,
+        client: AsyncBitfinex,
+        currency: String,
+        source_wallets: Vec<SourceWallet>,
+        threshold: f64,
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            name,
+            client,
+            currency,
+            source_wallets,
+            threshold,
+            dry_run,
+        }
+    }
+}
+
+impl fmt::Display for CompoundStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source_wallets: Vec<String> = self
+            .source_wallets
+            .iter()
+            .map(|source_wallet| source_wallet.to_string())
+            .collect();
+
+        write!(
+            f,
+            "{} on {}: sweep the {} wallets above {:.2} into the funding wallet{}",
+            self.name(),
+            self.currency,
+            source_wallets.join(", "),
+            self.threshold,
+            if self.dry_run { " (dry run)" } else { "" }
+        )
+    }
+}
+
+impl FromConfig for CompoundStrategy {
+    fn from_config(files: &ConfigFiles, _shared: &Shared) -> Result<Vec<Self>> {
+        #[derive(Debug, Deserialize)]
+        struct Strategy {
+            keys: String,
+            currency: String,
+            source_wallets: Vec<SourceWallet>,
+            threshold: f64,
+            #[serde(default)]
+            dry_run: bool,
+            #[serde(default = "common::default_enabled")]
+            enabled: bool,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[serde(default)]
+            compound_strategies: HashMap<String, Strategy>,
+        }
+
+        let config: Config = config::read(files)?;
+
+        config
+            .compound_strategies
+            .into_iter()
+            .filter(|(name, strategy)| common::is_enabled(name, strategy.enabled))
+            .map(|(name, strategy)| {
+                ensure!(
+                    !strategy.source_wallets.is_empty(),
+                    "{name}: source_wallets must not be empty"
+                );
+                ensure!(
+                    strategy.threshold > 0.,
+                    "{name}: threshold must be positive"
+                );
+
+                Ok(Self::new(
+                    name,
+                    common::client_from_keys(&strategy.keys)?,
+                    strategy.currency,
+                    strategy.source_wallets,
+                    strategy.threshold,
+                    strategy.dry_run,
+                ))
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid compound_strategies in {files}"))
+    }
+}
+
+#[async_trait]
+impl Strategy for CompoundStrategy {
+    type Output = Result<()>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    // No offer is placed.
+    fn honors_offer_limit(&self) -> bool {
+        true
+    }
+
+    /// Nothing to cancel: the strategy does not submit offers.
+    async fn cancel_offers(&self) -> Self::Output {
+        Ok(())
+    }
+
+    /// Execute the strategy.
+    async fn execute(&self) -> Self::Output {
+        log::info!(target: &self.log_target(), "Executing {} on {}...", self.name(), self.currency);
+
+        let wallets: WalletsResp = Wallets::builder()
+            .build()?
+            .query_async(&self.client)
+            .await?;
+
+        for source_wallet in &self.source_wallets {
+            let Some(wallet) = wallets.iter().find(|wallet| {
+                wallet.ty == source_wallet.wallet_type() && wallet.currency == self.currency
+            }) else {
+                continue;
+            };
+
+            let amount = wallet.available_balance;
+            if amount < self.threshold {
+                continue;
+            }
+
+            if self.dry_run {
+                log::info!(
+                    target: &self.log_target(),
+                    "Dry run: would transfer {amount:.2} {} from the {source_wallet} wallet to \
+                     the funding wallet",
+                    self.currency
+                );
+                continue;
+            }
+
+            ignore(
+                Transfer::builder()
+                    .from(source_wallet.wallet_type())
+                    .to(WalletType::Funding)
+                    .currency(&self.currency)
+                    .amount(amount)
+                    .build()?,
+            )
+            .query_async(&self.client)
+            .await?;
+
+            log::info!(
+                target: &self.log_target(),
+                "Transferred {amount:.2} {} from the {source_wallet} wallet to the funding wallet",
+                self.currency
+            );
+        }
+
+        Ok(())
+    }
+}
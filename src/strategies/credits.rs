@@ -0,0 +1,388 @@
+use std::collections::VecDeque;
+
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+/// Milliseconds in a day.
+const DAY_MS: f64 = 86_400_000.;
+
+/// An active funding credit, i.e. funds currently lent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Credit {
+    pub id: u64,
+    pub amount: f64,
+    /// Rate, per day.
+    pub rate: f64,
+    /// Period, in days.
+    pub period: u8,
+    /// UNIX timestamp (in milliseconds) the credit was opened at.
+    pub mts_opening: u64,
+}
+
+impl Credit {
+    /// Return the number of days since the credit was opened at `now`, in milliseconds.
+    fn age_days(&self, now: u64) -> f64 {
+        now.saturating_sub(self.mts_opening) as f64 / DAY_MS
+    }
+
+    /// Return the number of days left before the credit is returned at `now`, in milliseconds.
+    fn remaining_days(&self, now: u64) -> f64 {
+        (self.period as f64 - self.age_days(now)).max(0.)
+    }
+}
+
+/// Summary of the funds lent in the active credits of a currency.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CreditsSummary {
+    pub count: usize,
+    /// Total principal lent.
+    pub principal: f64,
+    /// Average rate, per day, weighted by the principal.
+    pub average_rate: f64,
+    /// Average number of days left before the credits are returned, weighted by the principal.
+    pub average_remaining_days: f64,
+}
+
+impl CreditsSummary {
+    /// Summarize the `credits` at `now`, in milliseconds.
+    pub fn new(credits: &[Credit], now: u64) -> Self {
+        let principal: f64 = credits.iter().map(|credit| credit.amount).sum();
+        if principal <= 0. {
+            return Self::default();
+        }
+
+        let weighted_average = |value: &dyn Fn(&Credit) -> f64| {
+            credits
+                .iter()
+                .map(|credit| credit.amount * value(credit))
+                .sum::<f64>()
+                / principal
+        };
+
+        Self {
+            count: credits.len(),
+            principal,
+            average_rate: weighted_average(&|credit| credit.rate),
+            average_remaining_days: weighted_average(&|credit| credit.remaining_days(now)),
+        }
+    }
+
+    /// Return the fractions of the `balance` lent, offered in the `offered` amount and idle.
+    pub fn deployment(&self, balance: f64, offered: f64) -> (f64, f64, f64) {
+        if balance <= 0. {
+            return (0., 0., 0.);
+        }
+
+        let lent = (self.principal / balance).min(1.);
+        let offered = (offered / balance).min(1. - lent);
+        (lent, offered, 1. - lent - offered)
+    }
+}
+
+/// Minimum interval, in seconds, between two logs of the capital usage.
+const CAPITAL_USAGE_LOG_INTERVAL_SECS: u64 = 3600;
+
+/// Capital of a strategy at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct CapitalSample {
+    /// UNIX timestamp, in seconds.
+    timestamp: u64,
+    offered: f64,
+    lent: f64,
+}
+
+/// Fraction of the capital of a strategy actually lent rather than sitting in unfilled offers,
+/// over a sliding window, weighted by the time each amount was offered or lent for.
+///
+/// Persisted across restarts, but for the window which is configured.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapitalUsage {
+    #[serde(skip)]
+    window_secs: u64,
+    samples: VecDeque<CapitalSample>,
+    /// UNIX timestamp (in seconds) of the last log.
+    logged_at: Option<u64>,
+}
+
+impl CapitalUsage {
+    pub fn new(window_secs: u64) -> Self {
+        Self {
+            window_secs,
+            samples: VecDeque::new(),
+            logged_at: None,
+        }
+    }
+
+    /// Return the persisted `capital_usage` over a window of `window_secs`, or a new one.
+    pub fn restore(capital_usage: Option<Self>, window_secs: u64) -> Self {
+        Self {
+            window_secs,
+            ..capital_usage.unwrap_or_else(|| Self::new(window_secs))
+        }
+    }
+
+    /// Record the `offered` and `lent` amounts at `now`, in seconds, forgetting the samples out
+    /// of the window.
+    ///
+    /// The unchanged amounts extend the last sample rather than adding one each cycle, to keep
+    /// the persisted samples few.
+    pub fn record(&mut self, now: u64, offered: f64, lent: f64) {
+        let len = self.samples.len();
+        if len >= 2
+            && self
+                .samples
+                .range(len - 2..)
+                .all(|sample| sample.offered == offered && sample.lent == lent)
+        {
+            self.samples[len - 1].timestamp = now;
+        } else {
+            self.samples.push_back(CapitalSample {
+                timestamp: now,
+                offered,
+                lent,
+            });
+        }
+
+        // The first sample kept holds from the start of the window.
+        let since = now.saturating_sub(self.window_secs);
+        while self
+            .samples
+            .get(1)
+            .is_some_and(|sample| sample.timestamp <= since)
+        {
+            self.samples.pop_front();
+        }
+        if let Some(sample) = self.samples.front_mut() {
+            sample.timestamp = sample.timestamp.max(since);
+        }
+    }
+
+    /// Return the fraction of the amount-seconds offered or lent over the window that were lent,
+    /// each sample holding until the next one, or `None` without any capital over the window.
+    pub fn utilization(&self) -> Option<f64> {
+        let (offered, lent) = self.samples.iter().zip(self.samples.iter().skip(1)).fold(
+            (0., 0.),
+            |(offered, lent), (sample, next)| {
+                let secs = next.timestamp.saturating_sub(sample.timestamp) as f64;
+                (offered + sample.offered * secs, lent + sample.lent * secs)
+            },
+        );
+
+        (offered + lent > 0.).then(|| lent / (offered + lent))
+    }
+
+    /// Return whether the usage is due for a log at `now`, in seconds, i.e. not logged within
+    /// the last hour, and remember it was logged if so.
+    pub fn log_due(&mut self, now: u64) -> bool {
+        let due = self.logged_at.map_or(true, |logged_at| {
+            now.saturating_sub(logged_at) >= CAPITAL_USAGE_LOG_INTERVAL_SECS
+        });
+        if due {
+            self.logged_at = Some(now);
+        }
+
+        due
+    }
+}
+
+/// Close the credits lent at a rate well below the achievable one shortly before they are
+/// returned anyway, to lend the funds again at the better rate.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CloseCredits {
+    /// Number of hours before their return within which the credits can be closed.
+    pub expiry_window_hours: f64,
+    /// Relative margin by which the achievable rate must exceed the rate of the credit.
+    pub min_rate_margin: f64,
+    /// Number of days a credit must have been open for before it can be closed.
+    pub min_age_days: f64,
+}
+
+impl CloseCredits {
+    /// Ensure the window and the age are positive and the margin is not negative.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.expiry_window_hours > 0.,
+            "expiry_window_hours must be positive"
+        );
+        ensure!(
+            self.min_rate_margin >= 0.,
+            "min_rate_margin must not be negative"
+        );
+        ensure!(self.min_age_days > 0., "min_age_days must be positive");
+
+        Ok(())
+    }
+
+    /// Return whether to close `credit` at `now`, in milliseconds, given the achievable `rate`.
+    pub fn should_close(&self, credit: &Credit, rate: f64, now: u64) -> bool {
+        credit.age_days(now) >= self.min_age_days
+            && credit.remaining_days(now) * 24. <= self.expiry_window_hours
+            && rate > credit.rate * (1. + self.min_rate_margin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn credit(amount: f64, rate: f64, period: u8, age_days: f64) -> Credit {
+        Credit {
+            id: 1,
+            amount,
+            rate,
+            period,
+            mts_opening: NOW - (age_days * DAY_MS) as u64,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn averages_are_weighted_by_the_principal() {
+        let summary = CreditsSummary::new(
+            &[credit(300., 0.0002, 2, 1.), credit(100., 0.0006, 30, 10.)],
+            NOW,
+        );
+
+        assert_eq!(summary.count, 2);
+        assert_close(summary.principal, 400.);
+        assert_close(summary.average_rate, 0.0003);
+        assert_close(
+            summary.average_remaining_days,
+            (300. * 1. + 100. * 20.) / 400.,
+        );
+    }
+
+    #[test]
+    fn overdue_credits_have_no_remaining_days() {
+        let summary = CreditsSummary::new(&[credit(100., 0.0002, 2, 3.)], NOW);
+
+        assert_eq!(summary.average_remaining_days, 0.);
+    }
+
+    #[test]
+    fn no_credits() {
+        assert_eq!(CreditsSummary::new(&[], NOW), CreditsSummary::default());
+    }
+
+    #[test]
+    fn deployment_fractions() {
+        let summary = CreditsSummary::new(&[credit(600., 0.0002, 2, 0.)], NOW);
+
+        let (lent, offered, idle) = summary.deployment(1000., 300.);
+        assert_close(lent, 0.6);
+        assert_close(offered, 0.3);
+        assert_close(idle, 0.1);
+        assert_eq!(summary.deployment(0., 0.), (0., 0., 0.));
+    }
+
+    #[test]
+    fn capital_usage_is_weighted_by_time() {
+        let mut capital_usage = CapitalUsage::new(86400);
+        assert_eq!(capital_usage.utilization(), None);
+
+        // 1000 offered for an hour, then 750 lent and 250 offered for three hours.
+        capital_usage.record(0, 1000., 0.);
+        capital_usage.record(3600, 250., 750.);
+        capital_usage.record(4 * 3600, 250., 750.);
+
+        assert_close(capital_usage.utilization().unwrap(), 0.5625);
+    }
+
+    #[test]
+    fn capital_usage_forgets_the_samples_out_of_the_window() {
+        let mut capital_usage = CapitalUsage::new(3600);
+
+        capital_usage.record(0, 1000., 0.);
+        capital_usage.record(1800, 0., 1000.);
+        capital_usage.record(5400, 0., 1000.);
+
+        assert_eq!(capital_usage.utilization(), Some(1.));
+    }
+
+    #[test]
+    fn unchanged_capital_extends_the_last_sample() {
+        let mut capital_usage = CapitalUsage::new(86400);
+
+        capital_usage.record(0, 1000., 0.);
+        for hour in 1..=4 {
+            capital_usage.record(hour * 3600, 250., 750.);
+        }
+
+        assert_eq!(capital_usage.samples.len(), 3);
+        assert_close(
+            capital_usage.utilization().unwrap(),
+            750. * 3. / (1000. + (250. + 750.) * 3.),
+        );
+    }
+
+    #[test]
+    fn capital_usage_holds_the_first_sample_from_the_start_of_the_window() {
+        let mut capital_usage = CapitalUsage::new(3600);
+
+        capital_usage.record(0, 0., 1000.);
+        capital_usage.record(1800, 0., 1000.);
+        capital_usage.record(3600, 1000., 0.);
+        capital_usage.record(5400, 1000., 0.);
+        capital_usage.record(7200, 1000., 0.);
+
+        // Offered for the whole window, the lent amount ending at 3600.
+        assert_eq!(capital_usage.utilization(), Some(0.));
+    }
+
+    #[test]
+    fn capital_usage_survives_a_restart() {
+        let mut capital_usage = CapitalUsage::new(86400);
+        capital_usage.record(0, 1000., 0.);
+        capital_usage.record(3600, 0., 1000.);
+        capital_usage.record(7200, 0., 1000.);
+        assert!(capital_usage.log_due(7200));
+
+        let value = serde_json::to_value(&capital_usage).unwrap();
+        let restored = CapitalUsage::restore(serde_json::from_value(value).ok(), 86400);
+
+        assert_eq!(restored.samples, capital_usage.samples);
+        assert_eq!(restored.logged_at, Some(7200));
+        assert_close(restored.utilization().unwrap(), 0.5);
+        assert_eq!(CapitalUsage::restore(None, 3600).window_secs, 3600);
+    }
+
+    #[test]
+    fn capital_usage_is_logged_hourly() {
+        let mut capital_usage = CapitalUsage::new(3600);
+
+        assert!(capital_usage.log_due(0));
+        assert!(!capital_usage.log_due(3599));
+        assert!(capital_usage.log_due(3600));
+    }
+
+    fn close_credits() -> CloseCredits {
+        CloseCredits {
+            expiry_window_hours: 48.,
+            min_rate_margin: 0.5,
+            min_age_days: 7.,
+        }
+    }
+
+    #[test]
+    fn close_the_credits_near_their_return_below_the_market() {
+        let credit = credit(100., 0.0002, 30, 29.);
+
+        assert!(close_credits().should_close(&credit, 0.0004, NOW));
+        assert!(!close_credits().should_close(&credit, 0.000_29, NOW));
+    }
+
+    #[test]
+    fn keep_the_credits_far_from_their_return() {
+        assert!(!close_credits().should_close(&credit(100., 0.0002, 30, 20.), 0.0004, NOW));
+    }
+
+    #[test]
+    fn keep_the_recent_credits() {
+        assert!(!close_credits().should_close(&credit(100., 0.0002, 7, 6.), 0.0004, NOW));
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+
+/// First of the Bitfinex error codes returned when the API key, signature or nonce is rejected.
+const ERR_AUTH_FAILED: i64 = 10100;
+/// Bitfinex error codes returned when the request payload is invalid.
+const ERR_PARAMS: [i64; 2] = [10020, 10050];
+/// Bitfinex error code returned when too many requests are sent.
+const ERR_RATE_LIMIT: i64 = 11010;
+
+/// Classified error returned by the Bitfinex API as an `["error", code, message]` array.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BitfinexError {
+    RateLimited,
+    InvalidCredentials,
+    InsufficientFunds(String),
+    OfferExists(String),
+    BelowMinimum(String),
+    OfferNotFound(String),
+    InvalidOffer(String),
+    Other { code: i64, message: String },
+}
+
+impl BitfinexError {
+    /// Classify an `["error", code, message]` response body.
+    pub fn from_response(code: i64, message: &str) -> Self {
+        let lowercase = message.to_lowercase();
+
+        if code == ERR_RATE_LIMIT || lowercase.contains("ratelimit") {
+            BitfinexError::RateLimited
+        } else if (ERR_AUTH_FAILED..ERR_AUTH_FAILED + 100).contains(&code)
+            || lowercase.contains("apikey")
+        {
+            BitfinexError::InvalidCredentials
+        } else if lowercase.contains("not enough") || lowercase.contains("insufficient") {
+            BitfinexError::InsufficientFunds(message.to_string())
+        } else if lowercase.contains("already exist") || lowercase.contains("duplicate") {
+            BitfinexError::OfferExists(message.to_string())
+        } else if lowercase.contains("minimum") {
+            BitfinexError::BelowMinimum(message.to_string())
+        } else if lowercase.contains("not found") || lowercase.contains("already closed") {
+            BitfinexError::OfferNotFound(message.to_string())
+        } else if ERR_PARAMS.contains(&code) || lowercase.contains("invalid") {
+            BitfinexError::InvalidOffer(message.to_string())
+        } else {
+            BitfinexError::Other {
+                code,
+                message: message.to_string(),
+            }
+        }
+    }
+
+    /// Look for an `["error", code, message]` array in the chain of `error` and classify it, or
+    /// return `None` if the error does not come from the Bitfinex API.
+    pub fn classify(error: &anyhow::Error) -> Option<Self> {
+        error
+            .chain()
+            .find_map(|cause| Self::parse(&cause.to_string()))
+    }
+
+    /// Parse the first `["error", code, message]` array found in `text`.
+    fn parse(text: &str) -> Option<Self> {
+        let start = text.find("[\"error\"")?;
+
+        let (_, code, message) = serde_json::Deserializer::from_str(&text[start..])
+            .into_iter::<(String, i64, String)>()
+            .next()?
+            .ok()?;
+
+        Some(Self::from_response(code, &message))
+    }
+}
+
+impl fmt::Display for BitfinexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BitfinexError::RateLimited => write!(f, "Rate limited by Bitfinex"),
+            BitfinexError::InvalidCredentials => write!(f, "Invalid Bitfinex API credentials"),
+            BitfinexError::InsufficientFunds(message) => write!(f, "Insufficient funds: {message}"),
+            BitfinexError::OfferExists(message) => write!(f, "Offer already exists: {message}"),
+            BitfinexError::BelowMinimum(message) => write!(f, "Offer below minimum: {message}"),
+            BitfinexError::OfferNotFound(message) => write!(f, "Offer not found: {message}"),
+            BitfinexError::InvalidOffer(message) => write!(f, "Invalid offer: {message}"),
+            BitfinexError::Other { code, message } => {
+                write!(f, "Bitfinex error {code}: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BitfinexError {}
+
+/// Turn the failed cancellation of an offer already gone, e.g. filled or canceled since it was
+/// fetched, into a success. Return whether the offer was already gone.
+pub fn offer_already_gone(result: anyhow::Result<()>) -> anyhow::Result<bool> {
+    match result {
+        Ok(()) => Ok(false),
+        Err(e) => match BitfinexError::classify(&e) {
+            Some(BitfinexError::OfferNotFound(_)) => Ok(true),
+            _ => Err(e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[test]
+    fn classifies_error_codes() {
+        assert_eq!(
+            BitfinexError::from_response(ERR_RATE_LIMIT, "ratelimit: error"),
+            BitfinexError::RateLimited
+        );
+        assert_eq!(
+            BitfinexError::from_response(ERR_AUTH_FAILED, "apikey: invalid"),
+            BitfinexError::InvalidCredentials
+        );
+        assert_eq!(
+            BitfinexError::from_response(10001, "Invalid offer: not enough balance"),
+            BitfinexError::InsufficientFunds("Invalid offer: not enough balance".to_string())
+        );
+        assert_eq!(
+            BitfinexError::from_response(10020, "amount: invalid"),
+            BitfinexError::InvalidOffer("amount: invalid".to_string())
+        );
+        assert_eq!(
+            BitfinexError::from_response(10001, "Invalid offer: offer already exists"),
+            BitfinexError::OfferExists("Invalid offer: offer already exists".to_string())
+        );
+        assert_eq!(
+            BitfinexError::from_response(
+                10001,
+                "Invalid offer: incorrect amount, minimum is 150 dollar or equivalent in USD"
+            ),
+            BitfinexError::BelowMinimum(
+                "Invalid offer: incorrect amount, minimum is 150 dollar or equivalent in USD"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            BitfinexError::from_response(20060, "maintenance"),
+            BitfinexError::Other {
+                code: 20060,
+                message: "maintenance".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn classifies_error_chains() {
+        let error = anyhow!("HTTP 500: [\"error\",11010,\"ratelimit: error\"]")
+            .context("Failed to submit the offer");
+
+        assert_eq!(
+            BitfinexError::classify(&error),
+            Some(BitfinexError::RateLimited)
+        );
+        assert_eq!(BitfinexError::classify(&anyhow!("timed out")), None);
+    }
+
+    #[test]
+    fn canceling_an_offer_already_gone_succeeds() {
+        let not_found = anyhow!("HTTP 500: [\"error\",10001,\"Offer not found\"]")
+            .context("Failed to cancel the offer");
+        let closed = anyhow!("HTTP 500: [\"error\",10001,\"Offer already closed\"]");
+
+        assert!(offer_already_gone(Err(not_found)).unwrap());
+        assert!(offer_already_gone(Err(closed)).unwrap());
+        assert!(!offer_already_gone(Ok(())).unwrap());
+    }
+
+    #[test]
+    fn other_cancel_errors_abort() {
+        let rate_limited = anyhow!("HTTP 500: [\"error\",11010,\"ratelimit: error\"]");
+
+        assert!(offer_already_gone(Err(rate_limited)).is_err());
+        assert!(offer_already_gone(Err(anyhow!("timed out"))).is_err());
+    }
+}
@@ -0,0 +1,336 @@
+use std::{collections::HashMap, fmt};
+
+use anyhow::{ensure, Context, Ok, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use bitfinex_api::bitfinex::AsyncBitfinex;
+
+use crate::{
+    budget::AccountBudget,
+    config::{self, ConfigFiles},
+    price_feed::PriceFeed,
+};
+
+use super::{
+    common,
+    owned_offers::OwnedOffers,
+    precision::Precision,
+    rate_display::{self, RateDisplay},
+    rate_guard::{self, RateGuard},
+    FromConfig, Shared, Strategy,
+};
+
+/// Amount to keep offered.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OfferAmount {
+    /// A fixed amount of the currency.
+    Absolute(f64),
+    /// A fraction, within (0, 1], of the total balance.
+    BalancePercent(f64),
+}
+
+impl OfferAmount {
+    /// Return the amount to offer out of `total_balance`.
+    fn resolve(&self, total_balance: f64) -> f64 {
+        match self {
+            OfferAmount::Absolute(amount) => *amount,
+            OfferAmount::BalancePercent(percent) => total_balance * percent,
+        }
+    }
+}
+
+impl fmt::Display for OfferAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OfferAmount::Absolute(amount) => write!(f, "{amount:.2}"),
+            OfferAmount::BalancePercent(percent) => {
+                write!(f, "{:.0}% of the balance", percent * 100.)
+            }
+        }
+    }
+}
+
+pub struct FixedRateStrategy {
+    name: String,
+    client: AsyncBitfinex,
+    currency: String,
+    symbol: String,
+    rate: f64,
+    amount: OfferAmount,
+    period: u8,
+    rate_guard: RateGuard,
+    price_feed: PriceFeed,
+    precision: Precision,
+    hidden: bool,
+    owned_offers: OwnedOffers,
+    rate_display: RateDisplay,
+    budget: AccountBudget,
+}
+
+impl FixedRateStrategy {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        name: String,
+        client: AsyncBitfinex,
+        currency: String,
+        symbol: String,
+        rate: f64,
+        amount: OfferAmount,
+        period: u8,
+        rate_guard: RateGuard,
+        price_feed: PriceFeed,
+        precision: Precision,
+        hidden: bool,
+        owned_offers: OwnedOffers,
+        rate_display: RateDisplay,
+        budget: AccountBudget,
+    ) -> Self {
+        Self {
+            name,
+            client,
+            currency,
+            symbol,
+            rate,
+            amount,
+            period,
+            rate_guard,
+            price_feed,
+            precision,
+            hidden,
+            owned_offers,
+            rate_display,
+            budget,
+        }
+    }
+}
+
+impl fmt::Display for FixedRateStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {}: {} for {} days @ {} per day",
+            self.name(),
+            self.currency,
+            self.amount,
+            self.period,
+            self.rate_display.rate(self.rate)
+        )
+    }
+}
+
+impl FromConfig for FixedRateStrategy {
+    fn from_config(files: &ConfigFiles, shared: &Shared) -> Result<Vec<Self>> {
+        #[derive(Debug, Deserialize)]
+        struct Strategy {
+            keys: String,
+            currency: String,
+            /// Funding symbol, defaults to `f{currency}`.
+            #[serde(default, alias = "funding_symbol")]
+            symbol: Option<String>,
+            rate: f64,
+            amount: OfferAmount,
+            period: u8,
+            #[serde(default = "rate_guard::default_max_rate")]
+            max_rate: f64,
+            #[serde(default = "rate_guard::default_sanity_max_apr")]
+            sanity_max_apr: f64,
+            #[serde(default = "common::default_enabled")]
+            enabled: bool,
+            #[serde(default = "common::default_hidden")]
+            hidden: bool,
+            #[serde(default = "rate_display::default_rate_decimals")]
+            rate_display_decimals: usize,
+            #[serde(default = "rate_display::default_apr_decimals")]
+            apr_display_decimals: usize,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[serde(default)]
+            fixed_rate_strategies: HashMap<String, Strategy>,
+        }
+
+        let config: Config = config::read(files)?;
+
+        config
+            .fixed_rate_strategies
+            .into_iter()
+            .filter(|(name, strategy)| common::is_enabled(name, strategy.enabled))
+            .map(|(name, strategy)| {
+                ensure!(strategy.rate > 0., "{name}: rate must be positive");
+                match strategy.amount {
+                    OfferAmount::Absolute(amount) => {
+                        ensure!(amount > 0., "{name}: amount must be positive")
+                    }
+                    OfferAmount::BalancePercent(percent) => ensure!(
+                        percent > 0. && percent <= 1.,
+                        "{name}: balance_percent amount must be within (0, 1]"
+                    ),
+                }
+                common::validate_period(strategy.period)
+                    .with_context(|| format!("{name}: invalid period"))?;
+
+                let rate_display = RateDisplay::new(
+                    strategy.rate_display_decimals,
+                    strategy.apr_display_decimals,
+                );
+                rate_display
+                    .validate()
+                    .with_context(|| format!("{name}: invalid display decimals"))?;
+                let rate_guard = RateGuard::new(
+                    strategy.max_rate,
+                    strategy.sanity_max_apr,
+                    rate_display,
+                    shared.notifier.clone(),
+                );
+                rate_guard
+                    .validate()
+                    .with_context(|| format!("{name}: invalid rate ceilings"))?;
+
+                let account_budget = shared.budget.account(&strategy.keys, &strategy.currency);
+                let precision = shared.precisions.get(&strategy.currency);
+                let symbol = common::funding_symbol(&strategy.currency, strategy.symbol);
+                let owned_offers = OwnedOffers::load(
+                    &name,
+                    &strategy.currency,
+                    shared.state.clone(),
+                    rate_display,
+                );
+
+                Ok(Self::new(
+                    name,
+                    common::client_from_keys(&strategy.keys)?,
+                    strategy.currency,
+                    symbol,
+                    strategy.rate,
+                    strategy.amount,
+                    strategy.period,
+                    rate_guard,
+                    shared.price_feed.clone(),
+                    precision,
+                    strategy.hidden,
+                    owned_offers,
+                    rate_display,
+                    account_budget,
+                ))
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid fixed_rate_strategies in {files}"))
+    }
+}
+
+#[async_trait]
+impl Strategy for FixedRateStrategy {
+    type Output = Result<()>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Cancel the offers submitted by the strategy.
+    async fn cancel_offers(&self) -> Self::Output {
+        self.owned_offers
+            .cancel_all(&self.client, &self.symbol, &self.log_target())
+            .await
+    }
+
+    /// Execute the strategy.
+    async fn execute(&self) -> Self::Output {
+        log::info!(target: &self.log_target(), "Executing {} on {}...", self.name(), self.currency);
+
+        let funding_wallet = common::funding_wallet(&self.client, &self.currency).await?;
+        let active_offer = self
+            .owned_offers
+            .active_offer(&self.client, &self.symbol, &self.log_target())
+            .await?;
+
+        let available_balance = funding_wallet.available_balance
+            + active_offer
+                .as_ref()
+                .map_or(0., |active_offer| active_offer.amount);
+        let total_balance = funding_wallet.balance;
+
+        self.budget
+            .update(total_balance, total_balance - available_balance);
+
+        // Offer what is available, up to the configured amount and the global budget.
+        let loan_amount = self
+            .amount
+            .resolve(total_balance)
+            .min(available_balance)
+            .min(self.budget.remaining());
+
+        let Some(params) =
+            self.rate_guard
+                .offer_params(loan_amount, self.rate, self.period, &self.log_target())
+        else {
+            return Ok(());
+        };
+
+        // Keep the active offer if it already matches the pinned one.
+        if let Some(active_offer) = active_offer {
+            if active_offer.period == self.period
+                && (active_offer.rate - self.rate).abs() < 1e-8
+                && (active_offer.amount - loan_amount).abs() <= 1.
+            {
+                log::info!(
+                    target: &self.log_target(),
+                    "Active offer is up to date: {:.2} for {} days @ {} per day",
+                    active_offer.amount,
+                    active_offer.period,
+                    self.rate_display.rate(active_offer.rate)
+                );
+                self.budget.deploy(active_offer.amount);
+                return Ok(());
+            }
+
+            self.owned_offers
+                .cancel(&self.client, active_offer.id, &self.log_target())
+                .await?;
+        }
+
+        if loan_amount <= 0. {
+            log::info!(
+                target: &self.log_target(),
+                "Nothing to offer on {}, skipping",
+                self.currency
+            );
+            return Ok(());
+        }
+
+        let offer = common::submit_offer(
+            &self.client,
+            &self.symbol,
+            params,
+            self.precision,
+            self.hidden,
+            &self.log_target(),
+        )
+        .await?;
+        self.owned_offers.insert(offer.id)?;
+        self.budget.deploy(offer.amount);
+
+        log::info!(
+            target: &self.log_target(),
+            "Offer {} submitted: {:.2} for {} days @ {} per day ({} APR)",
+            offer.id,
+            offer.amount,
+            offer.period,
+            self.rate_display.rate(offer.rate),
+            self.rate_display.apr(offer.rate * 365.)
+        );
+        common::log_projected_interest(
+            &self.client,
+            &self.price_feed,
+            &self.currency,
+            offer.amount,
+            offer.rate,
+            &self.log_target(),
+        )
+        .await;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,508 @@
+use std::{collections::HashMap, fmt};
+
+use anyhow::{ensure, Context, Ok, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use bitfinex_api::{
+    api::{
+        authenticated::funding::{
+            submit_funding_offer::{SubmitFundingOffer, SubmitFundingOfferResp},
+            types::{FundingOffer, FundingOfferType},
+        },
+        query::AsyncQuery,
+    },
+    bitfinex::AsyncBitfinex,
+};
+
+use crate::{
+    budget::AccountBudget,
+    config::{self, ConfigFiles},
+};
+
+use super::{
+    common,
+    owned_offers::OwnedOffers,
+    precision::Precision,
+    rate_display::{self, RateDisplay},
+    rate_guard::{self, RateGuard},
+    FromConfig, Shared, Strategy,
+};
+
+/// Flavor of FRR-relative offer.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrrDelta {
+    /// The offer rate keeps tracking the FRR (`FRRDELTAVAR`).
+    Variable,
+    /// The offer rate is fixed to the FRR at the time of the fill (`FRRDELTAFIX`).
+    Fixed,
+}
+
+impl FrrDelta {
+    fn offer_type(&self) -> FundingOfferType {
+        match self {
+            FrrDelta::Variable => FundingOfferType::FrrDeltaVar,
+            FrrDelta::Fixed => FundingOfferType::FrrDeltaFix,
+        }
+    }
+
+    fn from_offer_type(ty: &FundingOfferType) -> Option<Self> {
+        match ty {
+            FundingOfferType::FrrDeltaVar => Some(FrrDelta::Variable),
+            FundingOfferType::FrrDeltaFix => Some(FrrDelta::Fixed),
+            _ => None,
+        }
+    }
+}
+
+/// The characteristics of an FRR-relative offer compared during the reconciliation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FrrOffer {
+    /// `None` for offers that are not FRR-relative.
+    delta_type: Option<FrrDelta>,
+    delta: f64,
+    period: u8,
+    amount: f64,
+}
+
+impl From<&FundingOffer> for FrrOffer {
+    fn from(offer: &FundingOffer) -> Self {
+        Self {
+            delta_type: FrrDelta::from_offer_type(&offer.ty),
+            delta: offer.rate,
+            period: offer.period,
+            amount: offer.amount,
+        }
+    }
+}
+
+/// What to do with the active offer to reach the desired one.
+#[derive(Debug, PartialEq)]
+enum Reconciliation {
+    /// Nothing to lend and no active offer.
+    Idle,
+    /// The active offer matches the desired one.
+    Keep,
+    /// No active offer, submit the desired one.
+    Submit,
+    /// The active offer differs from the desired one.
+    Replace,
+    /// The active offer must be canceled and nothing submitted.
+    Cancel,
+}
+
+/// Compare the active offer (if any) against the desired one (`None` when we should not lend).
+fn reconcile(active_offer: Option<FrrOffer>, desired_offer: Option<FrrOffer>) -> Reconciliation {
+    match (active_offer, desired_offer) {
+        (None, None) => Reconciliation::Idle,
+        (Some(_), None) => Reconciliation::Cancel,
+        (None, Some(_)) => Reconciliation::Submit,
+        (Some(active_offer), Some(desired_offer)) => {
+            if active_offer.delta_type == desired_offer.delta_type
+                && active_offer.period == desired_offer.period
+                && (active_offer.amount - desired_offer.amount).abs() <= 1.
+                && (active_offer.delta - desired_offer.delta).abs() < 1e-8
+            {
+                Reconciliation::Keep
+            } else {
+                Reconciliation::Replace
+            }
+        }
+    }
+}
+
+pub struct FrrStrategy {
+    name: String,
+    client: AsyncBitfinex,
+    currency: String,
+    symbol: String,
+    min_amount: f64,
+    max_balance_percent_per_loan: f64,
+    min_rate: f64,
+    delta_type: FrrDelta,
+    delta: f64,
+    period: u8,
+    rate_guard: RateGuard,
+    precision: Precision,
+    hidden: bool,
+    owned_offers: OwnedOffers,
+    rate_display: RateDisplay,
+    budget: AccountBudget,
+}
+
+impl FrrStrategy {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        name: String,
+        client: AsyncBitfinex,
+        currency: String,
+        symbol: String,
+        min_amount: f64,
+        max_balance_percent_per_loan: f64,
+        min_rate: f64,
+        delta_type: FrrDelta,
+        delta: f64,
+        period: u8,
+        rate_guard: RateGuard,
+        precision: Precision,
+        hidden: bool,
+        owned_offers: OwnedOffers,
+        rate_display: RateDisplay,
+        budget: AccountBudget,
+    ) -> Self {
+        Self {
+            name,
+            client,
+            currency,
+            symbol,
+            min_amount,
+            max_balance_percent_per_loan,
+            min_rate,
+            delta_type,
+            delta,
+            period,
+            rate_guard,
+            precision,
+            hidden,
+            owned_offers,
+            rate_display,
+            budget,
+        }
+    }
+
+    /// Return the offer we want on the book, or `None` if we should not lend.
+    fn desired_offer(
+        &self,
+        frr: f64,
+        available_balance: f64,
+        total_balance: f64,
+    ) -> Option<FrrOffer> {
+        // Don't lend when the FRR-relative rate doesn't clear the floor.
+        let rate = frr + self.delta;
+        if rate < self.min_rate {
+            log::info!(
+                target: &self.log_target(),
+                "FRR-relative rate is below the floor: {} < {} per day",
+                self.rate_display.rate(rate),
+                self.rate_display.rate(self.min_rate)
+            );
+            return None;
+        }
+
+        // Clamp the amount to loan as a fraction of the total balance and to the global budget.
+        let amount = self
+            .min_amount
+            .max(available_balance.min(total_balance * self.max_balance_percent_per_loan))
+            .min(available_balance)
+            .min(self.budget.remaining());
+
+        if amount < self.min_amount {
+            log::info!(
+                target: &self.log_target(),
+                "Insufficient balance to submit a lend offer: {amount:.2} < {:.2}",
+                self.min_amount
+            );
+            return None;
+        }
+
+        // Round as submitted, so that the comparison with the active offer is exact.
+        Some(FrrOffer {
+            delta_type: Some(self.delta_type),
+            delta: self.precision.rate(self.delta),
+            period: self.period,
+            amount: self.precision.amount(amount),
+        })
+    }
+
+    async fn cancel(&self, active_offer: &FundingOffer) -> Result<()> {
+        self.owned_offers
+            .cancel(&self.client, active_offer.id, &self.log_target())
+            .await?;
+
+        Ok(())
+    }
+
+    async fn submit(&self, offer: &FrrOffer) -> Result<()> {
+        let resp: SubmitFundingOfferResp = SubmitFundingOffer::builder()
+            .ty(self.delta_type.offer_type())
+            .symbol(&self.symbol)
+            .amount(offer.amount)
+            .rate(offer.delta)
+            .period(offer.period)
+            .hidden(self.hidden)
+            .build()?
+            .query_async(&self.client)
+            .await?;
+        self.owned_offers.insert(resp.offer.id)?;
+        self.budget.deploy(resp.offer.amount);
+
+        log::info!(
+            target: &self.log_target(),
+            "FRR offer {} submitted: {:.2} for {} days @ FRR {} per day",
+            resp.offer.id,
+            resp.offer.amount,
+            offer.period,
+            self.rate_display.offset(offer.delta)
+        );
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for FrrStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {}: {} days {:?} offers @ FRR {} per day, min {} per day",
+            self.name(),
+            self.currency,
+            self.period,
+            self.delta_type,
+            self.rate_display.offset(self.delta),
+            self.rate_display.rate(self.min_rate)
+        )
+    }
+}
+
+impl FromConfig for FrrStrategy {
+    fn from_config(files: &ConfigFiles, shared: &Shared) -> Result<Vec<Self>> {
+        #[derive(Debug, Deserialize)]
+        struct Strategy {
+            keys: String,
+            currency: String,
+            /// Funding symbol, defaults to `f{currency}`.
+            #[serde(default, alias = "funding_symbol")]
+            symbol: Option<String>,
+            min_amount: f64,
+            max_balance_percent_per_loan: f64,
+            min_rate: f64,
+            delta_type: FrrDelta,
+            delta: f64,
+            period: u8,
+            #[serde(default = "rate_guard::default_max_rate")]
+            max_rate: f64,
+            #[serde(default = "rate_guard::default_sanity_max_apr")]
+            sanity_max_apr: f64,
+            #[serde(default = "common::default_enabled")]
+            enabled: bool,
+            #[serde(default = "common::default_hidden")]
+            hidden: bool,
+            #[serde(default = "rate_display::default_rate_decimals")]
+            rate_display_decimals: usize,
+            #[serde(default = "rate_display::default_apr_decimals")]
+            apr_display_decimals: usize,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[serde(default)]
+            frr_strategies: HashMap<String, Strategy>,
+        }
+
+        let config: Config = config::read(files)?;
+
+        config
+            .frr_strategies
+            .into_iter()
+            .filter(|(name, strategy)| common::is_enabled(name, strategy.enabled))
+            .map(|(name, strategy)| {
+                ensure!(
+                    strategy.min_amount > 0.,
+                    "{name}: min_amount must be positive"
+                );
+                ensure!(
+                    strategy.max_balance_percent_per_loan > 0.
+                        && strategy.max_balance_percent_per_loan <= 1.,
+                    "{name}: max_balance_percent_per_loan must be within (0, 1]"
+                );
+                ensure!(
+                    strategy.min_rate >= 0.,
+                    "{name}: min_rate must not be negative"
+                );
+                common::validate_period(strategy.period)
+                    .with_context(|| format!("{name}: invalid period"))?;
+
+                let rate_display = RateDisplay::new(
+                    strategy.rate_display_decimals,
+                    strategy.apr_display_decimals,
+                );
+                rate_display
+                    .validate()
+                    .with_context(|| format!("{name}: invalid display decimals"))?;
+                let rate_guard = RateGuard::new(
+                    strategy.max_rate,
+                    strategy.sanity_max_apr,
+                    rate_display,
+                    shared.notifier.clone(),
+                );
+                rate_guard
+                    .validate()
+                    .with_context(|| format!("{name}: invalid rate ceilings"))?;
+
+                let account_budget = shared.budget.account(&strategy.keys, &strategy.currency);
+                let precision = shared.precisions.get(&strategy.currency);
+                let symbol = common::funding_symbol(&strategy.currency, strategy.symbol);
+                let owned_offers = OwnedOffers::load(
+                    &name,
+                    &strategy.currency,
+                    shared.state.clone(),
+                    rate_display,
+                );
+
+                Ok(Self::new(
+                    name,
+                    common::client_from_keys(&strategy.keys)?,
+                    strategy.currency,
+                    symbol,
+                    strategy.min_amount,
+                    strategy.max_balance_percent_per_loan,
+                    strategy.min_rate,
+                    strategy.delta_type,
+                    strategy.delta,
+                    strategy.period,
+                    rate_guard,
+                    precision,
+                    strategy.hidden,
+                    owned_offers,
+                    rate_display,
+                    account_budget,
+                ))
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid frr_strategies in {files}"))
+    }
+}
+
+#[async_trait]
+impl Strategy for FrrStrategy {
+    type Output = Result<()>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Cancel the offers submitted by the strategy.
+    async fn cancel_offers(&self) -> Self::Output {
+        self.owned_offers
+            .cancel_all(&self.client, &self.symbol, &self.log_target())
+            .await
+    }
+
+    /// Execute the strategy.
+    async fn execute(&self) -> Self::Output {
+        log::info!(target: &self.log_target(), "Executing {} on {}...", self.name(), self.currency);
+
+        let funding_wallet = common::funding_wallet(&self.client, &self.currency).await?;
+        let active_offer = self
+            .owned_offers
+            .active_offer(&self.client, &self.symbol, &self.log_target())
+            .await?;
+
+        let available_balance = funding_wallet.available_balance
+            + active_offer
+                .as_ref()
+                .map_or(0., |active_offer| active_offer.amount);
+        let total_balance = funding_wallet.balance;
+
+        self.budget
+            .update(total_balance, total_balance - available_balance);
+
+        let frr = common::frr(&self.client, &self.symbol).await?;
+
+        if !self.rate_guard.check(frr + self.delta, &self.log_target()) {
+            return Ok(());
+        }
+
+        let desired_offer = self.desired_offer(frr, available_balance, total_balance);
+
+        match (
+            reconcile(active_offer.as_ref().map(FrrOffer::from), desired_offer),
+            active_offer,
+        ) {
+            (Reconciliation::Keep, Some(active_offer)) => {
+                log::info!(
+                    target: &self.log_target(),
+                    "Active FRR offer is up to date: {:.2} for {} days @ FRR {} per day",
+                    active_offer.amount,
+                    active_offer.period,
+                    self.rate_display.offset(active_offer.rate)
+                );
+                self.budget.deploy(active_offer.amount);
+            }
+            (Reconciliation::Cancel, Some(active_offer)) => self.cancel(&active_offer).await?,
+            (Reconciliation::Replace, Some(active_offer)) => {
+                self.cancel(&active_offer).await?;
+                if let Some(desired_offer) = desired_offer {
+                    self.submit(&desired_offer).await?;
+                }
+            }
+            (Reconciliation::Submit, _) => {
+                if let Some(desired_offer) = desired_offer {
+                    self.submit(&desired_offer).await?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer(delta_type: Option<FrrDelta>, delta: f64, period: u8, amount: f64) -> FrrOffer {
+        FrrOffer {
+            delta_type,
+            delta,
+            period,
+            amount,
+        }
+    }
+
+    #[test]
+    fn nothing_to_do_without_offers() {
+        assert_eq!(reconcile(None, None), Reconciliation::Idle);
+    }
+
+    #[test]
+    fn submit_when_no_active_offer() {
+        let desired = offer(Some(FrrDelta::Variable), 0.00001, 2, 500.);
+        assert_eq!(reconcile(None, Some(desired)), Reconciliation::Submit);
+    }
+
+    #[test]
+    fn cancel_when_frr_below_floor() {
+        let active = offer(Some(FrrDelta::Variable), 0.00001, 2, 500.);
+        assert_eq!(reconcile(Some(active), None), Reconciliation::Cancel);
+    }
+
+    #[test]
+    fn keep_matching_offer() {
+        let desired = offer(Some(FrrDelta::Variable), 0.00001, 2, 500.);
+        let active = offer(Some(FrrDelta::Variable), 0.00001, 2, 500.5);
+        assert_eq!(reconcile(Some(active), Some(desired)), Reconciliation::Keep);
+    }
+
+    #[test]
+    fn replace_mismatching_offer() {
+        let desired = offer(Some(FrrDelta::Variable), 0.00001, 2, 500.);
+
+        let cases = [
+            offer(Some(FrrDelta::Fixed), 0.00001, 2, 500.),
+            offer(None, 0.00001, 2, 500.),
+            offer(Some(FrrDelta::Variable), -0.00001, 2, 500.),
+            offer(Some(FrrDelta::Variable), 0.00001, 30, 500.),
+            offer(Some(FrrDelta::Variable), 0.00001, 2, 300.),
+        ];
+
+        for active in cases {
+            assert_eq!(
+                reconcile(Some(active), Some(desired)),
+                Reconciliation::Replace
+            );
+        }
+    }
+}
@@ -0,0 +1,186 @@
+/// Return the `percentile` (within (0, 100]) of `values`, linearly interpolated between the
+/// closest ranks, or `None` if there are no values.
+pub fn percentile(values: &[f64], percentile: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let rank = percentile / 100. * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64))
+}
+
+/// Return the exponential moving average of `values` over `period` values, seeded with the simple
+/// average of the first `period` values, or `None` if there are not enough values to seed it.
+pub fn ema(values: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || values.len() < period {
+        return None;
+    }
+
+    let alpha = 2. / (period as f64 + 1.);
+    let seed = values[..period].iter().sum::<f64>() / period as f64;
+
+    Some(
+        values[period..]
+            .iter()
+            .fold(seed, |ema, value| alpha * value + (1. - alpha) * ema),
+    )
+}
+
+/// Return the population standard deviation of `values`, or `None` if there are no values.
+pub fn std_dev(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let variance = values
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / values.len() as f64;
+
+    Some(variance.sqrt())
+}
+
+/// Return the difference between the highest and the lowest of `values`, or `None` if there are
+/// no values.
+pub fn range(values: &[f64]) -> Option<f64> {
+    let max = values.iter().copied().reduce(f64::max)?;
+    let min = values.iter().copied().reduce(f64::min)?;
+
+    Some(max - min)
+}
+
+/// Return the momentum of `values`: the relative change of the average of the last `k` values
+/// from the average of the `k` values before, or `None` if there are less than `2 * k` values.
+pub fn momentum(values: &[f64], k: usize) -> Option<f64> {
+    if k == 0 || values.len() < 2 * k {
+        return None;
+    }
+
+    let average = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    let recent = average(&values[values.len() - k..]);
+    let prior = average(&values[values.len() - 2 * k..values.len() - k]);
+
+    if prior == 0. {
+        return None;
+    }
+
+    Some(recent / prior - 1.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentile_interpolates_between_ranks() {
+        let values = [0.0004, 0.0001, 0.0003, 0.0002, 0.0005];
+
+        assert_eq!(percentile(&values, 100.), Some(0.0005));
+        assert_eq!(percentile(&values, 50.), Some(0.0003));
+        assert!((percentile(&values, 25.).unwrap() - 0.0002).abs() < 1e-12);
+        assert!((percentile(&values, 10.).unwrap() - 0.00014).abs() < 1e-12);
+    }
+
+    #[test]
+    fn percentile_of_nothing() {
+        assert_eq!(percentile(&[], 50.), None);
+        assert_eq!(percentile(&[0.0002], 50.), Some(0.0002));
+    }
+
+    #[test]
+    fn ema_is_seeded_with_the_average() {
+        assert_eq!(ema(&[0.0001, 0.0002, 0.0003], 3), Some(0.0002));
+        assert_eq!(ema(&[0.0001, 0.0002], 3), None);
+        assert_eq!(ema(&[0.0001], 0), None);
+    }
+
+    #[test]
+    fn ema_smooths_a_spike() {
+        // Seeded at 0.0002 with alpha = 0.5, the spike only moves the average half-way.
+        let smoothed = ema(&[0.0001, 0.0002, 0.0003, 0.0007], 3).unwrap();
+        assert!((smoothed - 0.00045).abs() < 1e-12);
+
+        // A flat series stays flat.
+        let flat = ema(&[0.0002; 10], 4).unwrap();
+        assert!((flat - 0.0002).abs() < 1e-12);
+    }
+
+    /// Rates steadily climbing from 0.0001 to 0.00019.
+    fn trending() -> Vec<f64> {
+        (0..10).map(|i| 0.0001 + i as f64 * 0.00001).collect()
+    }
+
+    /// Flat rates with a single spike.
+    fn spiky() -> Vec<f64> {
+        let mut values = vec![0.0001; 10];
+        values[5] = 0.0011;
+        values
+    }
+
+    #[test]
+    fn flat_series_has_no_volatility() {
+        let flat = [0.0002; 10];
+
+        assert!(std_dev(&flat).unwrap() < 1e-12);
+        assert_eq!(range(&flat), Some(0.));
+    }
+
+    #[test]
+    fn trending_series_volatility() {
+        let trending = trending();
+
+        assert!((range(&trending).unwrap() - 0.00009).abs() < 1e-12);
+        // Population standard deviation of 0..10 is sqrt(8.25).
+        assert!((std_dev(&trending).unwrap() - 8.25_f64.sqrt() * 0.00001).abs() < 1e-12);
+    }
+
+    #[test]
+    fn spiky_series_volatility() {
+        let spiky = spiky();
+
+        assert!((range(&spiky).unwrap() - 0.001).abs() < 1e-12);
+        // A single spike of 0.001 over 10 values: sqrt(0.1 * 0.9) * 0.001.
+        assert!((std_dev(&spiky).unwrap() - 0.09_f64.sqrt() * 0.001).abs() < 1e-12);
+        // The range reacts more than the standard deviation to a lone spike.
+        assert!(range(&spiky) > std_dev(&spiky));
+    }
+
+    #[test]
+    fn volatility_of_nothing() {
+        assert_eq!(std_dev(&[]), None);
+        assert_eq!(range(&[]), None);
+    }
+
+    #[test]
+    fn momentum_of_trends() {
+        // Rising: the last 3 values average 0.00018, the 3 before 0.00015.
+        assert!((momentum(&trending(), 3).unwrap() - 0.2).abs() < 1e-9);
+
+        // Falling: the last 3 values average 0.00011, the 3 before 0.00014.
+        let falling: Vec<f64> = trending().into_iter().rev().collect();
+        assert!((momentum(&falling, 3).unwrap() + 3. / 14.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn momentum_of_an_oscillating_series() {
+        let oscillating: Vec<f64> = (0..10)
+            .map(|i| if i % 2 == 0 { 0.0001 } else { 0.0003 })
+            .collect();
+
+        assert!(momentum(&oscillating, 2).unwrap().abs() < 1e-12);
+    }
+
+    #[test]
+    fn momentum_without_enough_values() {
+        assert_eq!(momentum(&trending(), 6), None);
+        assert_eq!(momentum(&trending(), 0), None);
+    }
+}
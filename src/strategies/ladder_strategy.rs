@@ -0,0 +1,496 @@
+use std::{collections::HashMap, fmt};
+
+use anyhow::{ensure, Context, Ok, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use bitfinex_api::{api::authenticated::funding::types::FundingOffer, bitfinex::AsyncBitfinex};
+
+use crate::{
+    budget::{AccountBudget, AccountOffers},
+    config::{self, ConfigFiles},
+};
+
+use super::{
+    common,
+    owned_offers::OwnedOffers,
+    precision::Precision,
+    rate_display::{self, RateDisplay},
+    rate_guard::{self, RateGuard},
+    rate_selector::RateSelector,
+    timeframe::CandleTimeFrame,
+    FromConfig, Shared, Strategy,
+};
+
+/// A single rung of the ladder as described in the config.
+#[derive(Debug, Deserialize)]
+pub struct RungConfig {
+    /// Fraction of the total balance allocated to this rung.
+    fraction: f64,
+    /// Period (in days) of the offers placed on this rung.
+    period: u8,
+}
+
+/// A rung of the ladder resolved for the current cycle.
+#[derive(Debug, Clone, Copy)]
+struct Rung {
+    rate: f64,
+    period: u8,
+    amount: f64,
+}
+
+pub struct LadderStrategy {
+    name: String,
+    client: AsyncBitfinex,
+    currency: String,
+    symbol: String,
+    min_amount: f64,
+    min_rate: f64,
+    base_period: u8,
+    monitored_window: u64,
+    nth_highest_candle: usize,
+    rate_step: f64,
+    rungs: Vec<RungConfig>,
+    rate_guard: RateGuard,
+    candle_timeframe: CandleTimeFrame,
+    precision: Precision,
+    hidden: bool,
+    offer_slots: AccountOffers,
+    owned_offers: OwnedOffers,
+    rate_display: RateDisplay,
+    budget: AccountBudget,
+}
+
+impl LadderStrategy {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        name: String,
+        client: AsyncBitfinex,
+        currency: String,
+        symbol: String,
+        min_amount: f64,
+        min_rate: f64,
+        base_period: u8,
+        monitored_window: u64,
+        nth_highest_candle: usize,
+        rate_step: f64,
+        rungs: Vec<RungConfig>,
+        rate_guard: RateGuard,
+        candle_timeframe: CandleTimeFrame,
+        precision: Precision,
+        hidden: bool,
+        offer_slots: AccountOffers,
+        owned_offers: OwnedOffers,
+        rate_display: RateDisplay,
+        budget: AccountBudget,
+    ) -> Self {
+        Self {
+            name,
+            client,
+            currency,
+            symbol,
+            min_amount,
+            min_rate,
+            base_period,
+            monitored_window,
+            nth_highest_candle,
+            rate_step,
+            rungs,
+            rate_guard,
+            candle_timeframe,
+            precision,
+            hidden,
+            offer_slots,
+            owned_offers,
+            rate_display,
+            budget,
+        }
+    }
+
+    /// Resolve the desired ladder from the base rate and the balances.
+    ///
+    /// Each rung targets its fraction of the total balance, but rungs are funded in order from
+    /// the lendable balance (available + currently offered) so that the sum of the rungs never
+    /// exceeds what can actually be lent. Rungs that would be smaller than `min_amount` are left
+    /// empty.
+    fn desired_ladder(
+        &self,
+        base_rate: f64,
+        total_balance: f64,
+        lendable_balance: f64,
+    ) -> Vec<Rung> {
+        let mut remaining = lendable_balance;
+
+        self.rungs
+            .iter()
+            .enumerate()
+            .map(|(i, rung)| {
+                let rate = (base_rate + i as f64 * self.rate_step).max(self.min_rate);
+
+                let mut amount = (rung.fraction * total_balance).min(remaining);
+                if amount < self.min_amount {
+                    amount = 0.;
+                }
+                remaining -= amount;
+
+                Rung {
+                    rate,
+                    period: rung.period,
+                    amount,
+                }
+            })
+            .collect()
+    }
+
+    /// Match the live offers against the desired ladder.
+    ///
+    /// Return the ids of the offers to cancel (drifted, wrong period or exceeding their rung) and
+    /// the offers to submit to top up the rungs that are not fully covered (filled or partially
+    /// filled ones).
+    fn reconcile(&self, ladder: &[Rung], active_offers: &[FundingOffer]) -> (Vec<u64>, Vec<Rung>) {
+        let mut offered = vec![0.; ladder.len()];
+        let mut to_cancel = vec![];
+
+        for active_offer in active_offers {
+            let rung_index = (0..ladder.len()).find(|&i| {
+                let rung = &ladder[i];
+                rung.period == active_offer.period
+                    && (active_offer.rate - rung.rate).abs() / rung.rate <= 0.01
+                    && offered[i] + active_offer.amount <= rung.amount + 1.
+            });
+
+            match rung_index {
+                Some(i) => offered[i] += active_offer.amount,
+                None => to_cancel.push(active_offer.id),
+            }
+        }
+
+        let to_submit = ladder
+            .iter()
+            .zip(offered)
+            .filter_map(|(rung, offered)| {
+                let missing = rung.amount - offered;
+                (missing >= self.min_amount).then_some(Rung {
+                    amount: missing,
+                    ..*rung
+                })
+            })
+            .collect();
+
+        (to_cancel, to_submit)
+    }
+}
+
+impl fmt::Display for LadderStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {}: {} rungs stepped by {} from the {}th highest {} days candle over {}h",
+            self.name(),
+            self.currency,
+            self.rungs.len(),
+            self.rate_display.rate(self.rate_step),
+            self.nth_highest_candle,
+            self.base_period,
+            self.monitored_window
+        )
+    }
+}
+
+impl FromConfig for LadderStrategy {
+    fn from_config(files: &ConfigFiles, shared: &Shared) -> Result<Vec<Self>> {
+        #[derive(Debug, Deserialize)]
+        struct Strategy {
+            keys: String,
+            currency: String,
+            /// Funding symbol, defaults to `f{currency}`.
+            #[serde(default, alias = "funding_symbol")]
+            symbol: Option<String>,
+            min_amount: f64,
+            min_rate: f64,
+            base_period: u8,
+            monitored_window: u64,
+            nth_highest_candle: usize,
+            rate_step: f64,
+            rungs: Vec<RungConfig>,
+            #[serde(default = "rate_guard::default_max_rate")]
+            max_rate: f64,
+            #[serde(default = "rate_guard::default_sanity_max_apr")]
+            sanity_max_apr: f64,
+            #[serde(default)]
+            candle_timeframe: CandleTimeFrame,
+            #[serde(default = "common::default_enabled")]
+            enabled: bool,
+            #[serde(default = "common::default_hidden")]
+            hidden: bool,
+            /// Priority of the strategy for the active offer slots of the account.
+            #[serde(default)]
+            offer_priority: u8,
+            #[serde(default = "rate_display::default_rate_decimals")]
+            rate_display_decimals: usize,
+            #[serde(default = "rate_display::default_apr_decimals")]
+            apr_display_decimals: usize,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[serde(default)]
+            ladder_strategies: HashMap<String, Strategy>,
+        }
+
+        let config: Config = config::read(files)?;
+
+        config
+            .ladder_strategies
+            .into_iter()
+            .filter(|(name, strategy)| common::is_enabled(name, strategy.enabled))
+            .map(|(name, strategy)| {
+                ensure!(
+                    strategy.min_amount > 0.,
+                    "{name}: min_amount must be positive"
+                );
+                ensure!(
+                    strategy.min_rate >= 0.,
+                    "{name}: min_rate must not be negative"
+                );
+                common::validate_period(strategy.base_period)
+                    .with_context(|| format!("{name}: invalid base_period"))?;
+                ensure!(
+                    strategy.monitored_window > 0,
+                    "{name}: monitored_window must be positive"
+                );
+                ensure!(
+                    strategy.nth_highest_candle > 0,
+                    "{name}: nth_highest_candle must be positive"
+                );
+                ensure!(
+                    strategy.rate_step >= 0.,
+                    "{name}: rate_step must not be negative"
+                );
+                ensure!(
+                    !strategy.rungs.is_empty(),
+                    "{name}: at least one rung is required"
+                );
+
+                for rung in &strategy.rungs {
+                    ensure!(
+                        rung.fraction > 0.,
+                        "{name}: rung fractions must be positive"
+                    );
+                    common::validate_period(rung.period)
+                        .with_context(|| format!("{name}: invalid rung period"))?;
+                }
+
+                let total_fraction: f64 = strategy.rungs.iter().map(|rung| rung.fraction).sum();
+                ensure!(
+                    total_fraction <= 1.,
+                    "{name}: rungs sum up to {total_fraction}, which is above 1"
+                );
+
+                let rate_display = RateDisplay::new(
+                    strategy.rate_display_decimals,
+                    strategy.apr_display_decimals,
+                );
+                rate_display
+                    .validate()
+                    .with_context(|| format!("{name}: invalid display decimals"))?;
+                let rate_guard = RateGuard::new(
+                    strategy.max_rate,
+                    strategy.sanity_max_apr,
+                    rate_display,
+                    shared.notifier.clone(),
+                );
+                rate_guard
+                    .validate()
+                    .with_context(|| format!("{name}: invalid rate ceilings"))?;
+
+                let account_budget = shared.budget.account(&strategy.keys, &strategy.currency);
+                let offer_slots =
+                    shared
+                        .offer_limit
+                        .account(&strategy.keys, &name, strategy.offer_priority);
+                let precision = shared.precisions.get(&strategy.currency);
+                let symbol = common::funding_symbol(&strategy.currency, strategy.symbol);
+                let owned_offers = OwnedOffers::load(
+                    &name,
+                    &strategy.currency,
+                    shared.state.clone(),
+                    rate_display,
+                );
+
+                Ok(Self::new(
+                    name,
+                    common::client_from_keys(&strategy.keys)?,
+                    strategy.currency,
+                    symbol,
+                    strategy.min_amount,
+                    strategy.min_rate,
+                    strategy.base_period,
+                    strategy.monitored_window,
+                    strategy.nth_highest_candle,
+                    strategy.rate_step,
+                    strategy.rungs,
+                    rate_guard,
+                    strategy.candle_timeframe,
+                    precision,
+                    strategy.hidden,
+                    offer_slots,
+                    owned_offers,
+                    rate_display,
+                    account_budget,
+                ))
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid ladder_strategies in {files}"))
+    }
+}
+
+#[async_trait]
+impl Strategy for LadderStrategy {
+    type Output = Result<()>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn honors_offer_limit(&self) -> bool {
+        true
+    }
+
+    /// Cancel the offers submitted by the strategy.
+    async fn cancel_offers(&self) -> Self::Output {
+        self.owned_offers
+            .cancel_all(&self.client, &self.symbol, &self.log_target())
+            .await
+    }
+
+    /// Execute the strategy.
+    async fn execute(&self) -> Self::Output {
+        log::info!(target: &self.log_target(), "Executing {} on {}...", self.name(), self.currency);
+
+        let funding_wallet = common::funding_wallet(&self.client, &self.currency).await?;
+        let active_offers = self
+            .owned_offers
+            .active_offers(&self.client, &self.symbol, &self.log_target())
+            .await?;
+
+        // Funds sitting in our offers can be redistributed across the rungs.
+        let offered_balance: f64 = active_offers.iter().map(|offer| offer.amount).sum();
+        let lendable_balance = funding_wallet.available_balance + offered_balance;
+        let total_balance = funding_wallet.balance;
+
+        // Only spread what the global deployment budget allows.
+        self.budget
+            .update(total_balance, total_balance - lendable_balance);
+        let lendable_balance = lendable_balance.min(self.budget.remaining());
+
+        // Take 99% of the highest rate as the base of the ladder.
+        let base_rate = common::candle_rate(
+            &self.client,
+            &self.symbol,
+            self.monitored_window,
+            &RateSelector::NthHighest(self.nth_highest_candle),
+            self.base_period,
+            self.candle_timeframe,
+        )
+        .await?
+            * 0.99;
+
+        let ladder = self.desired_ladder(base_rate, total_balance, lendable_balance);
+
+        let (mut to_cancel, mut to_submit) = self.reconcile(&ladder, &active_offers);
+
+        // Only submit the rungs the active offer slots of the account allow.
+        let kept = active_offers.len() - to_cancel.len();
+        self.offer_slots
+            .update(active_offers.len(), kept + to_submit.len());
+
+        // Yield the slots wanted by the strategies of higher priority on the account, with the
+        // kept rungs of the highest rates, the least likely to be filled.
+        let preempted = self.offer_slots.preempted().saturating_sub(to_cancel.len());
+        if preempted > 0 {
+            let mut kept_offers: Vec<&FundingOffer> = active_offers
+                .iter()
+                .filter(|active_offer| !to_cancel.contains(&active_offer.id))
+                .collect();
+            kept_offers.sort_by(|a, b| b.rate.total_cmp(&a.rate));
+            log::info!(
+                target: &self.log_target(),
+                "Canceling {preempted} rungs: their slots are wanted by the strategies of higher \
+                 priority on the account"
+            );
+            to_cancel.extend(kept_offers.iter().take(preempted).map(|offer| offer.id));
+        }
+        let kept = active_offers.len() - to_cancel.len();
+        let free = self.offer_slots.allowed().saturating_sub(kept);
+        if free < to_submit.len() {
+            log::warn!(
+                target: &self.log_target(),
+                "Only {free} of the {} rungs to submit fit within the max active offers of the \
+                 account, the others being taken by its other strategies",
+                to_submit.len()
+            );
+            to_submit.truncate(free);
+        }
+        // Refuse the whole ladder before canceling any rung.
+        let Some(to_submit) = to_submit
+            .iter()
+            .map(|rung| {
+                self.rate_guard.offer_params(
+                    rung.amount,
+                    rung.rate,
+                    rung.period,
+                    &self.log_target(),
+                )
+            })
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(());
+        };
+        self.budget
+            .deploy(ladder.iter().map(|rung| rung.amount).sum());
+
+        if to_cancel.is_empty() && to_submit.is_empty() {
+            log::info!(
+                target: &self.log_target(),
+                "Ladder is up to date: {} offers for {offered_balance:.2} {}",
+                active_offers.len(),
+                self.currency
+            );
+            return Ok(());
+        }
+
+        for id in to_cancel {
+            self.owned_offers
+                .cancel(&self.client, id, &self.log_target())
+                .await?;
+        }
+
+        for params in &to_submit {
+            let offer = common::submit_offer(
+                &self.client,
+                &self.symbol,
+                *params,
+                self.precision,
+                self.hidden,
+                &self.log_target(),
+            )
+            .await?;
+            self.owned_offers.insert(offer.id)?;
+
+            log::info!(
+                target: &self.log_target(),
+                "Rung submitted as offer {}: {:.2} for {} days @ {} per day ({} APR)",
+                offer.id,
+                offer.amount,
+                offer.period,
+                self.rate_display.rate(offer.rate),
+                self.rate_display.apr(offer.rate * 365.)
+            );
+        }
+        self.offer_slots
+            .update(kept + to_submit.len(), kept + to_submit.len());
+
+        Ok(())
+    }
+}
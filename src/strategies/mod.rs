@@ -1,14 +1,179 @@
+use std::fmt;
+
+use anyhow::{ensure, Result};
 use async_trait::async_trait;
+use serde_yaml::Value;
+
+use crate::{
+    budget::{DeploymentBudget, OfferLimit},
+    config::{self, ConfigFiles},
+    notifier::Notifier,
+    price_feed::PriceFeed,
+    skips::SkipStats,
+    state::StateStore,
+};
+
+use self::{
+    allocation_strategy::AllocationStrategy, best_ask_strategy::BestAskStrategy,
+    book_strategy::BookStrategy, compound_strategy::CompoundStrategy,
+    fixed_rate_strategy::FixedRateStrategy, frr_strategy::FrrStrategy,
+    ladder_strategy::LadderStrategy, precision::Precisions, rotation_strategy::RotationStrategy,
+    simple_strategy::SimpleStrategy, spike_strategy::SpikeStrategy,
+    tranche_strategy::TrancheStrategy,
+};
 
+pub mod allocation_strategy;
+pub mod best_ask_strategy;
+mod book;
+pub mod book_strategy;
+mod common;
+pub mod compound_strategy;
+mod credits;
+pub mod error;
+pub mod fixed_rate_strategy;
+pub mod frr_strategy;
+mod indicators;
+pub mod ladder_strategy;
+mod owned_offers;
+mod period_optimizer;
+pub mod precision;
+mod rate_display;
+mod rate_guard;
+mod rate_selector;
+pub mod report;
+pub mod rotation_strategy;
+mod seasonality;
 pub mod simple_strategy;
+pub mod spike_strategy;
+mod timeframe;
+pub mod tranche_strategy;
+mod utilization;
+
+/// Resources shared by all the strategies.
+pub struct Shared {
+    pub budget: DeploymentBudget,
+    pub offer_limit: OfferLimit,
+    pub state: StateStore,
+    pub price_feed: PriceFeed,
+    pub notifier: Notifier,
+    pub skips: SkipStats,
+    pub precisions: Precisions,
+}
+
+/// Strategy of any type, as run by the bot.
+pub type DynStrategy = Box<dyn Strategy<Output = Result<()>>>;
+
+/// Load the strategies of a type from its section of the config.
+pub trait FromConfig: Sized {
+    fn from_config(files: &ConfigFiles, shared: &Shared) -> Result<Vec<Self>>;
+}
 
 #[async_trait]
-pub trait Strategy {
+pub trait Strategy: fmt::Display + Send + Sync {
     type Output;
 
-    fn from_config(path: &str) -> Vec<Self>
-    where
-        Self: std::marker::Sized;
+    /// Name of the strategy in the config.
+    fn name(&self) -> &str;
+
+    /// Target of the logs of the strategy, e.g. `strategy::usd_aggressive`, so that a single
+    /// strategy can be traced with `RUST_LOG=strategy::usd_aggressive=debug`.
+    fn log_target(&self) -> String {
+        format!("strategy::{}", self.name())
+    }
+
+    /// Reconcile the state persisted across restarts against the exchange, before the first
+    /// cycle.
+    async fn reconcile(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether the offers of the strategy are reported to the `max_active_offers` of its
+    /// account, the strategies placing offers without it not being able to run along the limit.
+    fn honors_offer_limit(&self) -> bool {
+        false
+    }
 
     async fn execute(&self) -> Self::Output;
+
+    async fn cancel_offers(&self) -> Self::Output;
+}
+
+/// Load the strategies of type `S`, boxed.
+fn load<S>(files: &ConfigFiles, shared: &Shared) -> Result<Vec<DynStrategy>>
+where
+    S: FromConfig + Strategy<Output = Result<()>> + 'static,
+{
+    Ok(S::from_config(files, shared)?
+        .into_iter()
+        .map(|strategy| Box::new(strategy) as DynStrategy)
+        .collect())
+}
+
+/// Config section of each strategy type, along with its loader, in their order of execution.
+/// The wallets are swept first so that new funds get lent within the same cycle.
+#[allow(clippy::type_complexity)]
+const SECTIONS: [(&str, fn(&ConfigFiles, &Shared) -> Result<Vec<DynStrategy>>); 11] = [
+    ("compound_strategies", load::<CompoundStrategy>),
+    ("simple_strategies", load::<SimpleStrategy>),
+    ("ladder_strategies", load::<LadderStrategy>),
+    ("frr_strategies", load::<FrrStrategy>),
+    ("book_strategies", load::<BookStrategy>),
+    ("fixed_rate_strategies", load::<FixedRateStrategy>),
+    ("best_ask_strategies", load::<BestAskStrategy>),
+    ("allocation_strategies", load::<AllocationStrategy>),
+    ("tranche_strategies", load::<TrancheStrategy>),
+    ("rotation_strategies", load::<RotationStrategy>),
+    ("spike_strategies", load::<SpikeStrategy>),
+];
+
+/// Load the strategies of all the sections of the config `files`, whatever their type.
+pub fn from_config(files: &ConfigFiles, shared: &Shared) -> Result<Vec<DynStrategy>> {
+    let config: Value = config::read(files)?;
+
+    // Catch the typos in the section names, which would otherwise silently disable strategies.
+    if let Some(sections) = config.as_mapping() {
+        for section in sections.keys().filter_map(Value::as_str) {
+            ensure!(
+                !section.ends_with("_strategies")
+                    || SECTIONS.iter().any(|(known, _)| *known == section),
+                "Unknown strategy section {section} in {files}"
+            );
+        }
+    }
+
+    // The request cap is only enforced by the simple strategies, the others would silently ignore
+    // it.
+    for (section, _) in SECTIONS
+        .iter()
+        .filter(|(section, _)| *section != "simple_strategies")
+    {
+        let Some(strategies) = config.get(*section).and_then(Value::as_mapping) else {
+            continue;
+        };
+        for (name, strategy) in strategies {
+            ensure!(
+                strategy.get("max_requests_per_cycle").is_none(),
+                "{}: max_requests_per_cycle is only supported by simple_strategies",
+                name.as_str().unwrap_or_default()
+            );
+        }
+    }
+
+    let mut strategies = vec![];
+    for (_, load) in SECTIONS {
+        strategies.extend(load(files, shared)?);
+    }
+
+    // The offers of the other strategies would take the slots of the account unaccounted for.
+    if shared.offer_limit.is_enabled() {
+        for strategy in &strategies {
+            ensure!(
+                strategy.honors_offer_limit(),
+                "{} does not support max_active_offers, only the simple and ladder strategies do",
+                strategy.name()
+            );
+        }
+    }
+
+    Ok(strategies)
 }
@@ -0,0 +1,217 @@
+use std::{collections::HashSet, sync::Mutex};
+
+use anyhow::{bail, Ok, Result};
+
+use bitfinex_api::{api::authenticated::funding::types::FundingOffer, bitfinex::AsyncBitfinex};
+
+use crate::state::StateStore;
+
+use super::{common, rate_display::RateDisplay};
+
+/// Forget the `owned_offers` that are no longer among the `active_offer_ids` (filled or
+/// canceled), and adopt the `tracked_offer_id` of the last submission if it is still active, e.g.
+/// after a restart.
+pub fn reconcile_owned_offers(
+    owned_offers: &mut HashSet<u64>,
+    active_offer_ids: &[u64],
+    tracked_offer_id: Option<u64>,
+) {
+    owned_offers.retain(|id| active_offer_ids.contains(id));
+
+    if let Some(tracked_offer_id) = tracked_offer_id {
+        if active_offer_ids.contains(&tracked_offer_id) {
+            owned_offers.insert(tracked_offer_id);
+        }
+    }
+}
+
+/// Offers submitted by a strategy on a currency, the only ones it may cancel or replace: the
+/// offers placed manually or by other strategies are left alone, as reserved balance.
+///
+/// The IDs are persisted in the state store, so that the offers are still managed after a
+/// restart.
+pub struct OwnedOffers {
+    strategy: String,
+    currency: String,
+    state: StateStore,
+    ids: Mutex<HashSet<u64>>,
+    rate_display: RateDisplay,
+}
+
+impl OwnedOffers {
+    /// Load the offers owned by `strategy` on `currency` from the `state`, their rates being
+    /// logged with `rate_display`.
+    pub fn load(
+        strategy: &str,
+        currency: &str,
+        state: StateStore,
+        rate_display: RateDisplay,
+    ) -> Self {
+        let ids = state.owned_offers(strategy, currency);
+
+        Self {
+            strategy: strategy.to_string(),
+            currency: currency.to_string(),
+            state,
+            ids: Mutex::new(ids),
+            rate_display,
+        }
+    }
+
+    /// Fetch the active offers of the funding `symbol` owned by the strategy, forgetting the
+    /// owned offers no longer active (filled or canceled) and logging the foreign ones.
+    pub async fn active_offers(
+        &self,
+        client: &AsyncBitfinex,
+        symbol: &str,
+        log_target: &str,
+    ) -> Result<Vec<FundingOffer>> {
+        let active_offers = common::active_offers(client, symbol).await?;
+        let (owned_offers, _) = self.partition(active_offers, None, log_target)?;
+
+        Ok(owned_offers)
+    }
+
+    /// Split the `active_offers` into the ones owned by the strategy and the foreign ones,
+    /// forgetting the owned offers no longer active (filled or canceled), adopting the
+    /// `tracked_offer_id` of the last submission if it is still active, and logging the foreign
+    /// offers to `log_target`.
+    pub fn partition(
+        &self,
+        active_offers: Vec<FundingOffer>,
+        tracked_offer_id: Option<u64>,
+        log_target: &str,
+    ) -> Result<(Vec<FundingOffer>, Vec<FundingOffer>)> {
+        let active_offer_ids: Vec<u64> = active_offers
+            .iter()
+            .map(|active_offer| active_offer.id)
+            .collect();
+
+        let (owned_offers, foreign_offers): (Vec<FundingOffer>, Vec<FundingOffer>) = {
+            let mut ids = self.ids.lock().unwrap();
+            reconcile_owned_offers(&mut ids, &active_offer_ids, tracked_offer_id);
+
+            active_offers
+                .into_iter()
+                .partition(|active_offer| ids.contains(&active_offer.id))
+        };
+        self.save()?;
+
+        for foreign_offer in &foreign_offers {
+            log::debug!(
+                target: log_target,
+                "Ignoring the foreign offer {}: {:.2} for {} days @ {} per day",
+                foreign_offer.id,
+                foreign_offer.amount,
+                foreign_offer.period,
+                self.rate_display.rate(foreign_offer.rate)
+            );
+        }
+
+        Ok((owned_offers, foreign_offers))
+    }
+
+    /// Fetch the single active offer of the funding `symbol` owned by the strategy.
+    ///
+    /// Simultaneous owned offers are not expected: they are all canceled and an error is
+    /// returned.
+    pub async fn active_offer(
+        &self,
+        client: &AsyncBitfinex,
+        symbol: &str,
+        log_target: &str,
+    ) -> Result<Option<FundingOffer>> {
+        let mut active_offers = self.active_offers(client, symbol, log_target).await?;
+
+        if active_offers.len() > 1 {
+            for active_offer in &active_offers {
+                self.cancel(client, active_offer.id, log_target).await?;
+            }
+
+            bail!(
+                "Detected {} owned active offers on {}, which have all been canceled",
+                active_offers.len(),
+                self.currency
+            );
+        }
+
+        Ok(active_offers.pop())
+    }
+
+    /// Return the IDs of the owned offers.
+    pub fn ids(&self) -> HashSet<u64> {
+        self.ids.lock().unwrap().clone()
+    }
+
+    /// Record the offer `id` just submitted by the strategy.
+    pub fn insert(&self, id: u64) -> Result<()> {
+        self.ids.lock().unwrap().insert(id);
+        self.save()
+    }
+
+    /// Forget the offer `id`, canceled by the strategy.
+    pub fn remove(&self, id: u64) -> Result<()> {
+        self.ids.lock().unwrap().remove(&id);
+        self.save()
+    }
+
+    /// Replace the owned offers with `ids`, e.g. once reconciled after a restart.
+    pub fn set(&self, ids: HashSet<u64>) -> Result<()> {
+        *self.ids.lock().unwrap() = ids;
+        self.save()
+    }
+
+    /// Cancel the owned offer `id` and forget it, logging to `log_target`.
+    pub async fn cancel(&self, client: &AsyncBitfinex, id: u64, log_target: &str) -> Result<()> {
+        common::cancel_offer(client, id, log_target).await?;
+        self.ids.lock().unwrap().remove(&id);
+        self.save()
+    }
+
+    /// Cancel all the active offers of the funding `symbol` owned by the strategy.
+    pub async fn cancel_all(
+        &self,
+        client: &AsyncBitfinex,
+        symbol: &str,
+        log_target: &str,
+    ) -> Result<()> {
+        for active_offer in self.active_offers(client, symbol, log_target).await? {
+            self.cancel(client, active_offer.id, log_target).await?;
+            log::info!(target: log_target, "Offer {} canceled", active_offer.id);
+        }
+
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        let ids = self.ids.lock().unwrap().clone();
+        self.state
+            .set_owned_offers(&self.strategy, &self.currency, &ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owned_offers_are_forgotten_once_inactive() {
+        let mut owned_offers = HashSet::from([1, 2]);
+
+        reconcile_owned_offers(&mut owned_offers, &[2, 3], None);
+
+        assert_eq!(owned_offers, HashSet::from([2]));
+    }
+
+    #[test]
+    fn tracked_offer_is_adopted_if_active() {
+        let mut owned_offers = HashSet::new();
+
+        reconcile_owned_offers(&mut owned_offers, &[1, 2], Some(2));
+        assert_eq!(owned_offers, HashSet::from([2]));
+
+        // A foreign offer is never adopted, nor a filled tracked one.
+        reconcile_owned_offers(&mut owned_offers, &[1], Some(2));
+        assert!(owned_offers.is_empty());
+    }
+}
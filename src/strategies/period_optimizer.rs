@@ -0,0 +1,199 @@
+use std::fmt;
+
+use anyhow::{ensure, Result};
+use serde::Deserialize;
+
+use super::common;
+
+/// A period evaluated by the optimizer.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct PeriodCandidate {
+    pub period: u8,
+    /// Relative premium the rate of this period must offer to be preferred over a period without
+    /// premium (e.g. 0.15 for 15%).
+    #[serde(default)]
+    pub premium: f64,
+}
+
+/// How the quotes of the candidate periods are compared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeriodCriterion {
+    /// The APR discounted by the premium of the period.
+    #[default]
+    PremiumAdjusted,
+    /// The raw rate, ignoring the premiums.
+    HighestRate,
+    /// The rate divided by the period, favoring the shortest lockups.
+    RatePerPeriod,
+}
+
+impl fmt::Display for PeriodCriterion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeriodCriterion::PremiumAdjusted => write!(f, "premium-adjusted APR"),
+            PeriodCriterion::HighestRate => write!(f, "highest rate"),
+            PeriodCriterion::RatePerPeriod => write!(f, "rate per day of lockup"),
+        }
+    }
+}
+
+/// Pick the period with the best expected APR among the candidates.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PeriodOptimizer {
+    pub candidates: Vec<PeriodCandidate>,
+    #[serde(default)]
+    pub criterion: PeriodCriterion,
+}
+
+impl PeriodOptimizer {
+    pub fn validate(&self) -> Result<()> {
+        ensure!(!self.candidates.is_empty(), "candidates must not be empty");
+
+        for candidate in &self.candidates {
+            common::validate_period(candidate.period)?;
+            ensure!(
+                candidate.premium >= 0.,
+                "premium of the {} days period must not be negative",
+                candidate.period
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for PeriodOptimizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let periods: Vec<String> = self
+            .candidates
+            .iter()
+            .map(|candidate| format!("{}d (+{:.0}%)", candidate.period, candidate.premium * 100.))
+            .collect();
+
+        write!(f, "best {} of {}", self.criterion, periods.join(", "))
+    }
+}
+
+/// The rate quoted for a candidate period.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeriodQuote {
+    pub period: u8,
+    pub rate: f64,
+    pub premium: f64,
+}
+
+impl PeriodQuote {
+    /// Return the APR of the quoted rate.
+    pub fn apr(&self) -> f64 {
+        self.rate * 365.
+    }
+
+    /// Return the APR discounted by the premium required for the period.
+    pub fn adjusted_apr(&self) -> f64 {
+        self.apr() / (1. + self.premium)
+    }
+
+    /// Return the score of the quote according to `criterion`, the higher the better.
+    pub fn score(&self, criterion: PeriodCriterion) -> f64 {
+        match criterion {
+            PeriodCriterion::PremiumAdjusted => self.adjusted_apr(),
+            PeriodCriterion::HighestRate => self.rate,
+            PeriodCriterion::RatePerPeriod => self.rate / self.period as f64,
+        }
+    }
+}
+
+/// Return the quote with the best score according to `criterion`, preferring the shortest period
+/// on ties, or `None` if there are no quotes.
+pub fn best_period(quotes: &[PeriodQuote], criterion: PeriodCriterion) -> Option<PeriodQuote> {
+    quotes.iter().copied().reduce(|best, quote| {
+        let (best_score, score) = (best.score(criterion), quote.score(criterion));
+
+        if score > best_score || (score == best_score && quote.period < best.period) {
+            quote
+        } else {
+            best
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(period: u8, rate: f64, premium: f64) -> PeriodQuote {
+        PeriodQuote {
+            period,
+            rate,
+            premium,
+        }
+    }
+
+    #[test]
+    fn longer_period_must_beat_the_premium() {
+        // The 30 days rate is 10% above the 2 days one, short of the 15% premium.
+        let quotes = [quote(2, 0.0002, 0.), quote(30, 0.00022, 0.15)];
+        assert_eq!(
+            best_period(&quotes, PeriodCriterion::PremiumAdjusted)
+                .unwrap()
+                .period,
+            2
+        );
+
+        // The 30 days rate is 20% above the 2 days one.
+        let quotes = [quote(2, 0.0002, 0.), quote(30, 0.00024, 0.15)];
+        assert_eq!(
+            best_period(&quotes, PeriodCriterion::PremiumAdjusted)
+                .unwrap()
+                .period,
+            30
+        );
+    }
+
+    #[test]
+    fn picks_the_best_of_many() {
+        let quotes = [
+            quote(2, 0.0002, 0.),
+            quote(7, 0.00025, 0.05),
+            quote(30, 0.00026, 0.15),
+            quote(120, 0.0003, 0.5),
+        ];
+
+        assert_eq!(
+            best_period(&quotes, PeriodCriterion::PremiumAdjusted),
+            Some(quote(7, 0.00025, 0.05))
+        );
+    }
+
+    #[test]
+    fn ties_go_to_the_shortest_period() {
+        let quotes = [quote(30, 0.0002, 0.), quote(2, 0.0002, 0.)];
+
+        assert_eq!(
+            best_period(&quotes, PeriodCriterion::PremiumAdjusted)
+                .unwrap()
+                .period,
+            2
+        );
+    }
+
+    #[test]
+    fn no_quotes() {
+        assert_eq!(best_period(&[], PeriodCriterion::PremiumAdjusted), None);
+    }
+
+    #[test]
+    fn criteria() {
+        let quotes = [
+            quote(2, 0.0002, 0.),
+            quote(30, 0.00026, 0.15),
+            quote(120, 0.0003, 0.6),
+        ];
+
+        let best = |criterion| best_period(&quotes, criterion).unwrap().period;
+        assert_eq!(best(PeriodCriterion::PremiumAdjusted), 30);
+        assert_eq!(best(PeriodCriterion::HighestRate), 120);
+        assert_eq!(best(PeriodCriterion::RatePerPeriod), 2);
+    }
+}
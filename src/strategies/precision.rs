@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+/// Tolerance, in units of the last decimal, below which a value is considered already rounded
+/// despite the floating point errors.
+const EPSILON: f64 = 1e-6;
+
+/// Return `value` rounded down to `decimals` decimals, the values already rounded but off by a
+/// floating point error being kept as is.
+pub fn floor(value: f64, decimals: i32) -> f64 {
+    let scale = 10_f64.powi(decimals);
+    let scaled = value * scale;
+
+    if (scaled - scaled.round()).abs() <= EPSILON {
+        scaled.round() / scale
+    } else {
+        scaled.floor() / scale
+    }
+}
+
+/// Return `value` rounded to the nearest value of `decimals` decimals.
+pub fn round(value: f64, decimals: i32) -> f64 {
+    let scale = 10_f64.powi(decimals);
+    (value * scale).round() / scale
+}
+
+fn default_rate_decimals() -> i32 {
+    8
+}
+
+fn default_amount_decimals() -> i32 {
+    8
+}
+
+/// How the rates are quantized to `rate_decimals` decimals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateRounding {
+    /// Round down, never pushing the rate above the computed target.
+    #[default]
+    Floor,
+    /// Round to the nearest.
+    Round,
+}
+
+/// Precision accepted by Bitfinex for the offers of a currency.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Precision {
+    /// Decimals of the rates, per day.
+    #[serde(default = "default_rate_decimals")]
+    pub rate_decimals: i32,
+    #[serde(default)]
+    pub rate_rounding: RateRounding,
+    /// Decimals of the amounts.
+    #[serde(default = "default_amount_decimals")]
+    pub amount_decimals: i32,
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Self {
+            rate_decimals: default_rate_decimals(),
+            rate_rounding: RateRounding::default(),
+            amount_decimals: default_amount_decimals(),
+        }
+    }
+}
+
+impl Precision {
+    /// Return `rate` quantized, rounded down by default to never price above the intent.
+    pub fn rate(&self, rate: f64) -> f64 {
+        match self.rate_rounding {
+            RateRounding::Floor => floor(rate, self.rate_decimals),
+            RateRounding::Round => round(rate, self.rate_decimals),
+        }
+    }
+
+    /// Return `amount` rounded down, never exceeding the available balance.
+    pub fn amount(&self, amount: f64) -> f64 {
+        floor(amount, self.amount_decimals)
+    }
+}
+
+/// Precision overrides indexed by currency, the other currencies using the Bitfinex defaults.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Precisions {
+    currencies: HashMap<String, Precision>,
+}
+
+impl Precisions {
+    /// Return the precision of the offers of `currency`.
+    pub fn get(&self, currency: &str) -> Precision {
+        self.currencies.get(currency).copied().unwrap_or_default()
+    }
+
+    /// Return the currencies whose decimals are outside [0, 12].
+    pub fn invalid_currencies(&self) -> Vec<&str> {
+        self.currencies
+            .iter()
+            .filter(|(_, precision)| {
+                !(0..=12).contains(&precision.rate_decimals)
+                    || !(0..=12).contains(&precision.amount_decimals)
+            })
+            .map(|(currency, _)| currency.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floor_rounds_down() {
+        assert_eq!(floor(0.000_123_456_789, 8), 0.000_123_45);
+        assert_eq!(floor(1.999_999, 2), 1.99);
+        assert_eq!(floor(152.123_456_789_9, 8), 152.123_456_78);
+    }
+
+    #[test]
+    fn floor_keeps_rounded_values() {
+        assert_eq!(floor(0.000_25, 8), 0.000_25);
+        assert_eq!(floor(150., 8), 150.);
+        assert_eq!(floor(0., 8), 0.);
+    }
+
+    #[test]
+    fn floor_ignores_negative_float_errors() {
+        // 0.1 + 0.2 is 0.30000000000000004, 0.7 - 0.4 is 0.29999999999999993.
+        assert_eq!(floor(0.7 - 0.4, 2), 0.3);
+        assert_eq!(floor(0.1 + 0.2, 2), 0.3);
+        assert_eq!(floor(1.1 * 3., 1), 3.3);
+    }
+
+    #[test]
+    fn floor_very_small_amounts() {
+        // Sub-satoshi amounts.
+        assert_eq!(floor(0.000_000_009, 8), 0.);
+        assert_eq!(floor(0.000_000_019, 8), 0.000_000_01);
+        assert_eq!(floor(1e-12, 8), 0.);
+    }
+
+    #[test]
+    fn floor_negative_values() {
+        // Rounding down a negative FRR delta lowers the rate further.
+        assert_eq!(floor(-0.000_012_345, 6), -0.000_013);
+        assert_eq!(floor(-0.000_01, 6), -0.000_01);
+    }
+
+    #[test]
+    fn round_ties_away_from_zero() {
+        assert_eq!(round(0.125, 2), 0.13);
+        assert_eq!(round(0.124, 2), 0.12);
+        assert_eq!(round(2.5, 0), 3.);
+        assert_eq!(round(-2.5, 0), -3.);
+    }
+
+    #[test]
+    fn floor_ties_round_down() {
+        assert_eq!(floor(0.125, 2), 0.12);
+        assert_eq!(floor(2.5, 0), 2.);
+    }
+
+    #[test]
+    fn precision_defaults_and_overrides() {
+        let precisions = Precisions {
+            currencies: HashMap::from([(
+                "JPY".to_string(),
+                Precision {
+                    rate_decimals: 6,
+                    rate_rounding: RateRounding::Floor,
+                    amount_decimals: 0,
+                },
+            )]),
+        };
+
+        assert_eq!(precisions.get("USD"), Precision::default());
+        assert_eq!(precisions.get("USD").rate(0.000_123_456_789), 0.000_123_45);
+        assert_eq!(precisions.get("JPY").rate(0.000_123_456_789), 0.000_123);
+        assert_eq!(precisions.get("JPY").amount(20_000.9), 20_000.);
+        assert!(precisions.invalid_currencies().is_empty());
+    }
+
+    #[test]
+    fn rates_are_floored_or_rounded() {
+        let precision = |rate_decimals, rate_rounding| Precision {
+            rate_decimals,
+            rate_rounding,
+            ..Precision::default()
+        };
+
+        assert_eq!(
+            precision(8, RateRounding::Floor).rate(0.000_123_456_789),
+            0.000_123_45
+        );
+        assert_eq!(
+            precision(8, RateRounding::Round).rate(0.000_123_456_789),
+            0.000_123_46
+        );
+        assert_eq!(
+            precision(5, RateRounding::Floor).rate(0.000_123_456_789),
+            0.000_12
+        );
+        // 0.0003 * 1e8 is 29999.999999999996 in floating point.
+        assert_eq!(precision(8, RateRounding::Floor).rate(0.0003), 0.0003);
+    }
+}
@@ -0,0 +1,95 @@
+use anyhow::{ensure, Result};
+
+/// Maximum number of decimals displayed.
+const MAX_DECIMALS: usize = 12;
+
+/// Decimals of the rates displayed in the logs, for the currencies lent at very different rate
+/// scales.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateDisplay {
+    /// Decimals of the percentages of the daily rates.
+    rate_decimals: usize,
+    /// Decimals of the percentages of the APRs.
+    apr_decimals: usize,
+}
+
+pub fn default_rate_decimals() -> usize {
+    4
+}
+
+pub fn default_apr_decimals() -> usize {
+    2
+}
+
+impl Default for RateDisplay {
+    fn default() -> Self {
+        Self {
+            rate_decimals: default_rate_decimals(),
+            apr_decimals: default_apr_decimals(),
+        }
+    }
+}
+
+impl RateDisplay {
+    pub fn new(rate_decimals: usize, apr_decimals: usize) -> Self {
+        Self {
+            rate_decimals,
+            apr_decimals,
+        }
+    }
+
+    /// Ensure the decimals are at most 12.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.rate_decimals <= MAX_DECIMALS,
+            "rate_display_decimals must be at most {MAX_DECIMALS}"
+        );
+        ensure!(
+            self.apr_decimals <= MAX_DECIMALS,
+            "apr_display_decimals must be at most {MAX_DECIMALS}"
+        );
+
+        Ok(())
+    }
+
+    /// Format the daily `rate` as a percentage, e.g. `0.0250%`.
+    pub fn rate(&self, rate: f64) -> String {
+        format!("{:.*}%", self.rate_decimals, rate * 100.)
+    }
+
+    /// Format the daily `offset` as a signed percentage, e.g. `+0.0010%`.
+    pub fn offset(&self, offset: f64) -> String {
+        format!("{:+.*}%", self.rate_decimals, offset * 100.)
+    }
+
+    /// Format the annual `apr` as a percentage, e.g. `10.95%`.
+    pub fn apr(&self, apr: f64) -> String {
+        format!("{:.*}%", self.apr_decimals, apr * 100.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_the_historical_precision() {
+        let display = RateDisplay::default();
+
+        assert_eq!(display.rate(0.000_25), "0.0250%");
+        assert_eq!(display.offset(0.000_01), "+0.0010%");
+        assert_eq!(display.offset(-0.000_01), "-0.0010%");
+        assert_eq!(display.apr(0.0003 * 365.), "10.95%");
+    }
+
+    #[test]
+    fn decimals_are_configurable() {
+        let display = RateDisplay::new(6, 0);
+
+        assert_eq!(display.rate(0.000_000_5), "0.000050%");
+        assert_eq!(display.apr(0.1095), "11%");
+        assert!(display.validate().is_ok());
+        assert!(RateDisplay::new(13, 2).validate().is_err());
+        assert!(RateDisplay::new(4, 13).validate().is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use anyhow::{ensure, Result};
+
+use crate::notifier::Notifier;
+
+use super::rate_display::RateDisplay;
+
+/// Rate, per day, below which an offer is too cheap to be anything but a bug.
+pub const MIN_SANE_RATE: f64 = 1e-8;
+
+/// Default ceiling on the offered rates, per day (365% APR).
+pub fn default_max_rate() -> f64 {
+    0.01
+}
+
+/// Default ceiling on the APR implied by the offered rates (200%).
+pub fn default_sanity_max_apr() -> f64 {
+    2.
+}
+
+/// Parameters of a funding offer, only built by `RateGuard::offer_params` so that no offer is
+/// submitted at a rate it did not check.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OfferParams {
+    amount: f64,
+    rate: f64,
+    period: u8,
+}
+
+impl OfferParams {
+    pub fn amount(&self) -> f64 {
+        self.amount
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn period(&self) -> u8 {
+        self.period
+    }
+
+    /// Return the same offer for `amount`, e.g. a chunk of it, the rate being already checked.
+    pub fn with_amount(self, amount: f64) -> Self {
+        Self { amount, ..self }
+    }
+}
+
+/// Guard refusing the implausible rates: not above `MIN_SANE_RATE`, above `max_rate` per day or
+/// implying an APR above `sanity_max_apr`.
+///
+/// Never submit an implausible rate, whatever the config or the market data say: this is a
+/// guardrail against a catastrophic misconfiguration or a market data glitch.
+#[derive(Debug, Clone)]
+pub struct RateGuard {
+    max_rate: f64,
+    sanity_max_apr: f64,
+    display: RateDisplay,
+    notifier: Notifier,
+}
+
+impl RateGuard {
+    /// Create the guard, formatting the refused rates with `display` and notifying the refusals
+    /// with `notifier`.
+    pub fn new(
+        max_rate: f64,
+        sanity_max_apr: f64,
+        display: RateDisplay,
+        notifier: Notifier,
+    ) -> Self {
+        Self {
+            max_rate,
+            sanity_max_apr,
+            display,
+            notifier,
+        }
+    }
+
+    /// Ensure the ceilings are positive.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.max_rate > MIN_SANE_RATE,
+            "max_rate must be above {MIN_SANE_RATE}"
+        );
+        ensure!(self.sanity_max_apr > 0., "sanity_max_apr must be positive");
+
+        Ok(())
+    }
+
+    /// Return why `rate` must not be offered, or `None` if it is plausible.
+    fn refusal(&self, rate: f64) -> Option<String> {
+        if rate.is_nan() || rate <= MIN_SANE_RATE {
+            Some(format!(
+                "Refusing to submit an offer @ {rate} per day, not a positive rate"
+            ))
+        } else if rate > self.max_rate {
+            Some(format!(
+                "Refusing to submit an offer @ {} per day, above the {} per day max rate",
+                self.display.rate(rate),
+                self.display.rate(self.max_rate)
+            ))
+        } else if rate * 365. > self.sanity_max_apr {
+            Some(format!(
+                "Refusing to submit an offer @ {} per day ({} APR), above the {} APR sanity \
+                 ceiling",
+                self.display.rate(rate),
+                self.display.apr(rate * 365.),
+                self.display.apr(self.sanity_max_apr)
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Return whether `rate` is plausible, logging an error to `log_target` and notifying it
+    /// otherwise.
+    pub fn check(&self, rate: f64, log_target: &str) -> bool {
+        let Some(refusal) = self.refusal(rate) else {
+            return true;
+        };
+
+        log::error!(target: log_target, "{refusal}");
+        self.notifier.notify(&refusal);
+
+        false
+    }
+
+    /// Build the parameters of an offer of `amount` for `period` days @ `rate` per day, or `None`
+    /// if the rate is refused, as checked by `check`.
+    pub fn offer_params(
+        &self,
+        amount: f64,
+        rate: f64,
+        period: u8,
+        log_target: &str,
+    ) -> Option<OfferParams> {
+        self.check(rate, log_target).then_some(OfferParams {
+            amount,
+            rate,
+            period,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard() -> RateGuard {
+        RateGuard::new(0.01, 2., RateDisplay::default(), Notifier::default())
+    }
+
+    #[test]
+    fn rates_must_be_positive_and_below_the_ceilings() {
+        let guard = guard();
+
+        assert!(guard.check(0.0003, "test"));
+        assert!(!guard.check(0., "test"));
+        assert!(!guard.check(-0.0003, "test"));
+        assert!(!guard.check(1e-9, "test"));
+        assert!(!guard.check(f64::NAN, "test"));
+        // A flash print of 9% per day.
+        assert!(!guard.check(0.09, "test"));
+        // Below the max rate, but above 200% APR.
+        assert!(!guard.check(0.006, "test"));
+
+        let guard = RateGuard::new(0.001, 10., RateDisplay::default(), Notifier::default());
+        assert!(guard.check(0.001, "test"));
+        assert!(!guard.check(0.0011, "test"));
+    }
+
+    #[test]
+    fn offer_params_are_built_for_the_plausible_rates() {
+        let guard = guard();
+
+        let params = guard.offer_params(500., 0.0003, 30, "test").unwrap();
+        assert_eq!(params.amount(), 500.);
+        assert_eq!(params.rate(), 0.0003);
+        assert_eq!(params.period(), 30);
+        assert_eq!(params.with_amount(150.).amount(), 150.);
+        assert_eq!(params.with_amount(150.).rate(), 0.0003);
+
+        assert_eq!(guard.offer_params(500., 0.09, 30, "test"), None);
+        assert_eq!(guard.offer_params(500., f64::NAN, 30, "test"), None);
+    }
+
+    #[test]
+    fn ceilings_must_be_positive() {
+        assert!(guard().validate().is_ok());
+        assert!(
+            RateGuard::new(0., 2., RateDisplay::default(), Notifier::default())
+                .validate()
+                .is_err()
+        );
+        assert!(
+            RateGuard::new(0.01, 0., RateDisplay::default(), Notifier::default())
+                .validate()
+                .is_err()
+        );
+    }
+}
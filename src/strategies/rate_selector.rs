@@ -0,0 +1,124 @@
+use std::fmt;
+
+use anyhow::{bail, ensure, Result};
+use serde::Deserialize;
+
+use super::indicators;
+
+/// How the rate is selected, from the candle highs of the monitored window or from the book.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateSelector {
+    /// The nth highest candle high.
+    NthHighest(usize),
+    /// The given percentile, within (0, 100], of the candle highs.
+    Percentile(f64),
+    /// The exponential moving average of the candle highs over `period` candles, scaled by
+    /// `multiplier`.
+    Ema { period: usize, multiplier: f64 },
+    /// The depth-weighted rate at which the amount to loan would be absorbed by the bids of the
+    /// funding book, only supported by the simple strategies.
+    BookDepth,
+}
+
+impl RateSelector {
+    /// Ensure the selector parameter is within its range, and that it selects from the candles:
+    /// the strategies supporting `BookDepth` check it beforehand.
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            RateSelector::NthHighest(n) => ensure!(n > 0, "nth highest candle must be positive"),
+            RateSelector::Percentile(p) => {
+                ensure!(p > 0. && p <= 100., "percentile must be within (0, 100]")
+            }
+            RateSelector::Ema { period, multiplier } => {
+                ensure!(period > 0, "EMA period must be positive");
+                ensure!(multiplier > 0., "EMA multiplier must be positive");
+            }
+            RateSelector::BookDepth => bail!("book_depth is only supported by simple_strategies"),
+        }
+
+        Ok(())
+    }
+
+    /// Select the rate from the candle highs, or `None` if there are not enough candles or the
+    /// selector does not use the candles.
+    pub fn select(&self, highs: &[f64]) -> Option<f64> {
+        match *self {
+            RateSelector::NthHighest(n) => {
+                if n == 0 || highs.len() < n {
+                    return None;
+                }
+
+                let mut sorted = highs.to_vec();
+                sorted.sort_by(|a, b| b.partial_cmp(a).unwrap());
+
+                Some(sorted[n - 1])
+            }
+            RateSelector::Percentile(p) => indicators::percentile(highs, p),
+            RateSelector::Ema { period, multiplier } => {
+                indicators::ema(highs, period).map(|ema| ema * multiplier)
+            }
+            RateSelector::BookDepth => None,
+        }
+    }
+}
+
+impl fmt::Display for RateSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateSelector::NthHighest(n) => write!(f, "{n}th highest"),
+            RateSelector::Percentile(p) => write!(f, "{p}th percentile"),
+            RateSelector::Ema { period, multiplier } => write!(f, "EMA({period}) x {multiplier}"),
+            RateSelector::BookDepth => write!(f, "book depth"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HIGHS: [f64; 5] = [0.0002, 0.0005, 0.0001, 0.0004, 0.0003];
+
+    #[test]
+    fn nth_highest() {
+        assert_eq!(RateSelector::NthHighest(1).select(&HIGHS), Some(0.0005));
+        assert_eq!(RateSelector::NthHighest(3).select(&HIGHS), Some(0.0003));
+        assert_eq!(RateSelector::NthHighest(6).select(&HIGHS), None);
+    }
+
+    #[test]
+    fn percentile() {
+        assert_eq!(RateSelector::Percentile(100.).select(&HIGHS), Some(0.0005));
+        assert_eq!(RateSelector::Percentile(50.).select(&HIGHS), Some(0.0003));
+        assert_eq!(RateSelector::Percentile(50.).select(&[]), None);
+    }
+
+    #[test]
+    fn ema() {
+        let selector = RateSelector::Ema {
+            period: 5,
+            multiplier: 1.1,
+        };
+        let ema = selector.select(&HIGHS).unwrap();
+        assert!((ema - 0.0003 * 1.1).abs() < 1e-12);
+
+        assert_eq!(selector.select(&HIGHS[..4]), None);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(RateSelector::NthHighest(0).validate().is_err());
+        assert!(RateSelector::NthHighest(3).validate().is_ok());
+        assert!(RateSelector::Percentile(0.).validate().is_err());
+        assert!(RateSelector::Percentile(100.).validate().is_ok());
+        assert!(RateSelector::Percentile(100.5).validate().is_err());
+        assert!(RateSelector::Ema {
+            period: 0,
+            multiplier: 1.
+        }
+        .validate()
+        .is_err());
+        assert!(RateSelector::BookDepth.validate().is_err());
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Ok, Result};
+use serde_yaml::Value;
+
+use bitfinex_api::{
+    api::{
+        authenticated::wallets::{WalletType, Wallets, WalletsResp},
+        query::AsyncQuery,
+    },
+    bitfinex::AsyncBitfinex,
+};
+
+use crate::config::{self, ConfigFiles};
+
+use super::common;
+
+/// Return the distinct account keys of all the strategies declared in the config `files`.
+fn account_keys(files: &ConfigFiles) -> Result<BTreeSet<String>> {
+    let config: Value = config::read(files)?;
+
+    let Some(sections) = config.as_mapping() else {
+        return Ok(BTreeSet::new());
+    };
+
+    let keys = sections
+        .iter()
+        .filter(|(section, _)| section.as_str().is_some_and(|s| s.ends_with("_strategies")))
+        .filter_map(|(_, strategies)| strategies.as_mapping())
+        .flat_map(|strategies| strategies.values())
+        .filter_map(|strategy| strategy.get("keys")?.as_str())
+        .map(str::to_string)
+        .collect();
+
+    Ok(keys)
+}
+
+/// Market watched by a strategy of the config.
+struct Market {
+    strategy: String,
+    keys: String,
+    currency: String,
+    symbol: String,
+}
+
+/// Return the markets of all the strategies declared in the config `files`, sorted by
+/// strategy, one per currency for the strategies lending several.
+fn markets(files: &ConfigFiles) -> Result<Vec<Market>> {
+    let config: Value = config::read(files)?;
+
+    let Some(sections) = config.as_mapping() else {
+        return Ok(vec![]);
+    };
+
+    let mut markets = vec![];
+    for (name, strategy) in sections
+        .iter()
+        .filter(|(section, _)| section.as_str().is_some_and(|s| s.ends_with("_strategies")))
+        .filter_map(|(_, strategies)| strategies.as_mapping())
+        .flat_map(|strategies| strategies.iter())
+    {
+        let (Some(name), Some(keys)) =
+            (name.as_str(), strategy.get("keys").and_then(Value::as_str))
+        else {
+            continue;
+        };
+
+        let currencies: Vec<&str> = match strategy.get("currencies").and_then(Value::as_sequence) {
+            Some(currencies) => currencies.iter().filter_map(Value::as_str).collect(),
+            None => strategy
+                .get("currency")
+                .and_then(Value::as_str)
+                .into_iter()
+                .collect(),
+        };
+        let symbol = strategy
+            .get("symbol")
+            .or(strategy.get("funding_symbol"))
+            .and_then(Value::as_str);
+
+        for currency in currencies {
+            markets.push(Market {
+                strategy: name.to_string(),
+                keys: keys.to_string(),
+                currency: currency.to_string(),
+                symbol: common::funding_symbol(currency, symbol.map(str::to_string)),
+            });
+        }
+    }
+    markets.sort_by(|a, b| a.strategy.cmp(&b.strategy));
+
+    Ok(markets)
+}
+
+/// Log the lending state of the account: its wallets, and the active offers and credits of its
+/// funding wallets, warning about the `currencies` of its strategies without a funded wallet.
+async fn report_account(
+    keys: &str,
+    client: &AsyncBitfinex,
+    currencies: &BTreeSet<String>,
+) -> Result<()> {
+    let wallets: WalletsResp = Wallets::builder().build()?.query_async(client).await?;
+
+    log::info!("Account {keys}:");
+
+    for currency in currencies {
+        let funded = wallets.iter().any(|wallet| {
+            wallet.ty == WalletType::Funding && wallet.currency == *currency && wallet.balance > 0.
+        });
+        if !funded {
+            log::warn!("  No funds in the {currency} funding wallet lent by the strategies");
+        }
+    }
+
+    for wallet in &wallets {
+        log::info!(
+            "  {:?} {} wallet: {:.2} ({:.2} available)",
+            wallet.ty,
+            wallet.currency,
+            wallet.balance,
+            wallet.available_balance
+        );
+    }
+
+    for wallet in wallets
+        .iter()
+        .filter(|wallet| wallet.ty == WalletType::Funding)
+    {
+        let symbol = common::funding_symbol(&wallet.currency, None);
+        let active_offers = common::active_offers(client, &symbol).await?;
+        let active_credits = common::active_credits(client, &symbol).await?;
+
+        let offered: f64 = active_offers.iter().map(|offer| offer.amount).sum();
+        let lent: f64 = active_credits.iter().map(|credit| credit.amount).sum();
+
+        log::info!(
+            "  {symbol}: {:.2} offered in {} offers, {:.2} lent in {} credits ({:.2}% of the \
+             balance)",
+            offered,
+            active_offers.len(),
+            lent,
+            active_credits.len(),
+            if wallet.balance > 0. {
+                lent * 100. / wallet.balance
+            } else {
+                0.
+            }
+        );
+
+        for offer in &active_offers {
+            log::info!(
+                "    Offer {}: {:.2} for {} days @ {:.4}% per day ({:.2}% APR)",
+                offer.id,
+                offer.amount,
+                offer.period,
+                offer.rate * 100.,
+                offer.rate * 100. * 365.
+            );
+        }
+
+        for credit in &active_credits {
+            log::info!(
+                "    Credit {}: {:.2} for {} days @ {:.4}% per day ({:.2}% APR)",
+                credit.id,
+                credit.amount,
+                credit.period,
+                credit.rate * 100.,
+                credit.rate * 100. * 365.
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Print a table of the active offers on the market of each strategy of the config `files`,
+/// without touching anything.
+pub async fn list_offers(files: &ConfigFiles) -> Result<()> {
+    let now = common::unix_time()? * 1000;
+
+    println!(
+        "{:<20} {:<8} {:>14} {:>12} {:>9} {:>6} {:>10} {:>12}",
+        "STRATEGY", "CURRENCY", "AMOUNT", "RATE (%/DAY)", "APR (%)", "PERIOD", "AGE", "ID"
+    );
+
+    for market in markets(files)? {
+        let client = common::client_from_keys(&market.keys)?;
+        let active_offers = common::active_offers(&client, &market.symbol).await?;
+
+        for offer in &active_offers {
+            let age_secs = now.saturating_sub(offer.mts_create) / 1000;
+
+            println!(
+                "{:<20} {:<8} {:>14.2} {:>12.4} {:>9.2} {:>6} {:>10} {:>12}",
+                market.strategy,
+                market.currency,
+                offer.amount,
+                offer.rate * 100.,
+                offer.rate * 100. * 365.,
+                offer.period,
+                format!("{}h{:02}m", age_secs / 3600, age_secs % 3600 / 60),
+                offer.id
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Cancel the active offer `id` on the markets of `strategy` in the config `files`, with the
+/// keys of the strategy.
+pub async fn cancel_offer(files: &ConfigFiles, strategy: &str, id: u64) -> Result<()> {
+    let markets: Vec<Market> = markets(files)?
+        .into_iter()
+        .filter(|market| market.strategy == strategy)
+        .collect();
+    if markets.is_empty() {
+        bail!("Unknown strategy {strategy} in {files}");
+    }
+
+    for market in markets {
+        let client = common::client_from_keys(&market.keys)?;
+        let active_offers = common::active_offers(&client, &market.symbol).await?;
+
+        if let Some(offer) = active_offers.iter().find(|offer| offer.id == id) {
+            common::cancel_offer(&client, id, module_path!()).await?;
+
+            log::info!(
+                "Offer {id} of {strategy} canceled: {:.2} {} for {} days @ {:.4}% per day",
+                offer.amount,
+                market.currency,
+                offer.period,
+                offer.rate * 100.
+            );
+            return Ok(());
+        }
+    }
+
+    bail!("Offer {id} is not an active offer on the markets of {strategy}")
+}
+
+/// Log a read-only report of the lending state of every account used in the config `files`.
+pub async fn report(files: &ConfigFiles) -> Result<()> {
+    let markets = markets(files)?;
+
+    for keys in account_keys(files)? {
+        let currencies: BTreeSet<String> = markets
+            .iter()
+            .filter(|market| market.keys == keys)
+            .map(|market| market.currency.clone())
+            .collect();
+
+        let client = common::client_from_keys(&keys)?;
+        report_account(&keys, &client, &currencies).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,571 @@
+use std::{collections::HashMap, fmt, sync::Mutex};
+
+use anyhow::{ensure, Context, Ok, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use bitfinex_api::bitfinex::AsyncBitfinex;
+
+use crate::{
+    budget::AccountBudget,
+    config::{self, ConfigFiles},
+    state::StateStore,
+};
+
+use super::{
+    common,
+    owned_offers::OwnedOffers,
+    precision::Precisions,
+    rate_display::{self, RateDisplay},
+    rate_guard::{self, RateGuard},
+    rate_selector::RateSelector,
+    timeframe::CandleTimeFrame,
+    FromConfig, Shared, Strategy,
+};
+
+/// The currency the newly available funds are lent in, and the challenger currently beating it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Rotation {
+    preferred: Option<String>,
+    /// Challenger currency and the number of consecutive cycles it beat the preferred one.
+    challenger: Option<(String, usize)>,
+}
+
+impl Rotation {
+    /// Update the rotation with the `rates` of this cycle and return the preferred currency.
+    ///
+    /// The preferred currency is replaced once another one beats its rate by more than `margin`
+    /// for `confirm_cycles` consecutive cycles, or right away if its rate is unavailable.
+    fn update(
+        &mut self,
+        rates: &[(String, f64)],
+        margin: f64,
+        confirm_cycles: usize,
+    ) -> Option<&str> {
+        let (best, best_rate) = rates.iter().max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+        let preferred_rate = self.preferred.as_ref().and_then(|preferred| {
+            rates
+                .iter()
+                .find(|(currency, _)| currency == preferred)
+                .map(|(_, rate)| *rate)
+        });
+
+        match preferred_rate {
+            None => {
+                self.preferred = Some(best.clone());
+                self.challenger = None;
+            }
+            Some(preferred_rate) if *best_rate > preferred_rate * (1. + margin) => {
+                let streak = match &self.challenger {
+                    Some((challenger, streak)) if challenger == best => streak + 1,
+                    _ => 1,
+                };
+
+                if streak >= confirm_cycles {
+                    self.preferred = Some(best.clone());
+                    self.challenger = None;
+                } else {
+                    self.challenger = Some((best.clone(), streak));
+                }
+            }
+            Some(_) => self.challenger = None,
+        }
+
+        self.preferred.as_deref()
+    }
+}
+
+/// Lend the newly available funds in whichever of the configured currencies pays best.
+///
+/// No conversion happens: the funds of the other currencies are held back, and the funds locked
+/// in their offers and credits are left untouched.
+pub struct RotationStrategy {
+    name: String,
+    client: AsyncBitfinex,
+    currencies: Vec<String>,
+    min_amount: f64,
+    min_rate: f64,
+    period: u8,
+    monitored_window: u64,
+    rate_selector: RateSelector,
+    candle_timeframe: CandleTimeFrame,
+    margin: f64,
+    confirm_cycles: usize,
+    rate_guard: RateGuard,
+    /// Rotation, persisted across restarts not to start over the confirmation of a challenger.
+    rotation: Mutex<Rotation>,
+    state: StateStore,
+    precisions: Precisions,
+    hidden: bool,
+    owned_offers: HashMap<String, OwnedOffers>,
+    rate_display: RateDisplay,
+    budgets: HashMap<String, AccountBudget>,
+}
+
+impl RotationStrategy {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        name: String,
+        client: AsyncBitfinex,
+        currencies: Vec<String>,
+        min_amount: f64,
+        min_rate: f64,
+        period: u8,
+        monitored_window: u64,
+        rate_selector: RateSelector,
+        candle_timeframe: CandleTimeFrame,
+        margin: f64,
+        confirm_cycles: usize,
+        rate_guard: RateGuard,
+        state: StateStore,
+        precisions: Precisions,
+        hidden: bool,
+        owned_offers: HashMap<String, OwnedOffers>,
+        rate_display: RateDisplay,
+        budgets: HashMap<String, AccountBudget>,
+    ) -> Self {
+        Self {
+            name,
+            client,
+            currencies,
+            min_amount,
+            min_rate,
+            period,
+            monitored_window,
+            rate_selector,
+            candle_timeframe,
+            margin,
+            confirm_cycles,
+            rate_guard,
+            rotation: Mutex::new(Rotation::default()),
+            state,
+            precisions,
+            hidden,
+            owned_offers,
+            rate_display,
+            budgets,
+        }
+    }
+
+    /// Key of the rotation in the state file.
+    fn state_key(&self) -> String {
+        format!("rotation_strategies.{}", self.name)
+    }
+
+    /// Return the achievable rate of each currency, skipping those without enough candles.
+    async fn rates(&self) -> Vec<(String, f64)> {
+        let mut rates = Vec::new();
+
+        for currency in &self.currencies {
+            match common::candle_rate(
+                &self.client,
+                &common::funding_symbol(currency, None),
+                self.monitored_window,
+                &self.rate_selector,
+                self.period,
+                self.candle_timeframe,
+            )
+            .await
+            {
+                Result::Ok(rate) => rates.push((currency.clone(), rate * 0.99)),
+                Err(e) => log::warn!(
+                    target: &self.log_target(),
+                    "No {currency} rate available: {e:#}"
+                ),
+            }
+        }
+
+        rates
+    }
+
+    /// Lend the available balance of `currency` at `rate`.
+    async fn lend(&self, currency: &str, rate: f64) -> Result<()> {
+        let symbol = common::funding_symbol(currency, None);
+        let budget = &self.budgets[currency];
+        let owned_offers = &self.owned_offers[currency];
+
+        let funding_wallet = common::funding_wallet(&self.client, currency).await?;
+        let active_offer = owned_offers
+            .active_offer(&self.client, &symbol, &self.log_target())
+            .await?;
+
+        let available_balance = funding_wallet.available_balance
+            + active_offer
+                .as_ref()
+                .map_or(0., |active_offer| active_offer.amount);
+        let total_balance = funding_wallet.balance;
+
+        budget.update(total_balance, total_balance - available_balance);
+
+        let loan_amount = available_balance.min(budget.remaining());
+        if loan_amount < self.min_amount {
+            log::info!(
+                target: &self.log_target(),
+                "Insufficient {currency} balance to submit a lend offer: {loan_amount:.2} < {:.2}",
+                self.min_amount
+            );
+            return Ok(());
+        }
+
+        if rate < self.min_rate {
+            log::info!(
+                target: &self.log_target(),
+                "{currency} rate {} is below the min rate {}, skipping",
+                self.rate_display.rate(rate),
+                self.rate_display.rate(self.min_rate)
+            );
+            return Ok(());
+        }
+
+        let Some(params) =
+            self.rate_guard
+                .offer_params(loan_amount, rate, self.period, &self.log_target())
+        else {
+            return Ok(());
+        };
+
+        if let Some(active_offer) = active_offer {
+            if active_offer.period == self.period
+                && (active_offer.amount - loan_amount).abs() <= 1.
+                && (active_offer.rate - rate).abs() / rate <= 0.01
+            {
+                log::info!(
+                    target: &self.log_target(),
+                    "Active {currency} offer is good enough: {:.2} for {} days @ {} per day",
+                    active_offer.amount,
+                    active_offer.period,
+                    self.rate_display.rate(active_offer.rate)
+                );
+                budget.deploy(active_offer.amount);
+                return Ok(());
+            }
+
+            owned_offers
+                .cancel(&self.client, active_offer.id, &self.log_target())
+                .await?;
+        }
+
+        let offer = common::submit_offer(
+            &self.client,
+            &symbol,
+            params,
+            self.precisions.get(currency),
+            self.hidden,
+            &self.log_target(),
+        )
+        .await?;
+        owned_offers.insert(offer.id)?;
+        budget.deploy(loan_amount);
+
+        log::info!(
+            target: &self.log_target(),
+            "{currency} offer submitted: {:.2} for {} days @ {} per day ({} APR)",
+            loan_amount,
+            self.period,
+            self.rate_display.rate(rate),
+            self.rate_display.apr(rate * 365.)
+        );
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for RotationStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {}: {} days offers in the best paying currency (switching after {} cycles \
+             {:.0}% above) @ min {} per day, {} candle over {}h",
+            self.name(),
+            self.currencies.join(", "),
+            self.period,
+            self.confirm_cycles,
+            self.margin * 100.,
+            self.rate_display.rate(self.min_rate),
+            self.rate_selector,
+            self.monitored_window
+        )
+    }
+}
+
+impl FromConfig for RotationStrategy {
+    fn from_config(files: &ConfigFiles, shared: &Shared) -> Result<Vec<Self>> {
+        #[derive(Debug, Deserialize)]
+        struct Strategy {
+            keys: String,
+            currencies: Vec<String>,
+            min_amount: f64,
+            min_rate: f64,
+            period: u8,
+            monitored_window: u64,
+            rate_selector: RateSelector,
+            #[serde(default)]
+            candle_timeframe: CandleTimeFrame,
+            margin: f64,
+            confirm_cycles: usize,
+            #[serde(default = "rate_guard::default_max_rate")]
+            max_rate: f64,
+            #[serde(default = "rate_guard::default_sanity_max_apr")]
+            sanity_max_apr: f64,
+            #[serde(default = "common::default_hidden")]
+            hidden: bool,
+            #[serde(default = "common::default_enabled")]
+            enabled: bool,
+            #[serde(default = "rate_display::default_rate_decimals")]
+            rate_display_decimals: usize,
+            #[serde(default = "rate_display::default_apr_decimals")]
+            apr_display_decimals: usize,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[serde(default)]
+            rotation_strategies: HashMap<String, Strategy>,
+        }
+
+        let config: Config = config::read(files)?;
+
+        config
+            .rotation_strategies
+            .into_iter()
+            .filter(|(name, strategy)| common::is_enabled(name, strategy.enabled))
+            .map(|(name, strategy)| {
+                ensure!(
+                    strategy.currencies.len() >= 2,
+                    "{name}: at least two currencies are required"
+                );
+                ensure!(
+                    strategy.min_amount > 0.,
+                    "{name}: min_amount must be positive"
+                );
+                ensure!(
+                    strategy.min_rate >= 0.,
+                    "{name}: min_rate must not be negative"
+                );
+                ensure!(
+                    strategy.monitored_window > 0,
+                    "{name}: monitored_window must be positive"
+                );
+                ensure!(strategy.margin >= 0., "{name}: margin must not be negative");
+                ensure!(
+                    strategy.confirm_cycles > 0,
+                    "{name}: confirm_cycles must be positive"
+                );
+                common::validate_period(strategy.period)
+                    .with_context(|| format!("{name}: invalid period"))?;
+                strategy
+                    .rate_selector
+                    .validate()
+                    .with_context(|| format!("{name}: invalid rate_selector"))?;
+
+                let rate_display = RateDisplay::new(
+                    strategy.rate_display_decimals,
+                    strategy.apr_display_decimals,
+                );
+                rate_display
+                    .validate()
+                    .with_context(|| format!("{name}: invalid display decimals"))?;
+                let rate_guard = RateGuard::new(
+                    strategy.max_rate,
+                    strategy.sanity_max_apr,
+                    rate_display,
+                    shared.notifier.clone(),
+                );
+                rate_guard
+                    .validate()
+                    .with_context(|| format!("{name}: invalid rate ceilings"))?;
+
+                let budgets = strategy
+                    .currencies
+                    .iter()
+                    .map(|currency| {
+                        (
+                            currency.clone(),
+                            shared.budget.account(&strategy.keys, currency),
+                        )
+                    })
+                    .collect();
+                let owned_offers = strategy
+                    .currencies
+                    .iter()
+                    .map(|currency| {
+                        (
+                            currency.clone(),
+                            OwnedOffers::load(&name, currency, shared.state.clone(), rate_display),
+                        )
+                    })
+                    .collect();
+
+                Ok(Self::new(
+                    name,
+                    common::client_from_keys(&strategy.keys)?,
+                    strategy.currencies,
+                    strategy.min_amount,
+                    strategy.min_rate,
+                    strategy.period,
+                    strategy.monitored_window,
+                    strategy.rate_selector,
+                    strategy.candle_timeframe,
+                    strategy.margin,
+                    strategy.confirm_cycles,
+                    rate_guard,
+                    shared.state.clone(),
+                    shared.precisions.clone(),
+                    strategy.hidden,
+                    owned_offers,
+                    rate_display,
+                    budgets,
+                ))
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid rotation_strategies in {files}"))
+    }
+}
+
+#[async_trait]
+impl Strategy for RotationStrategy {
+    type Output = Result<()>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Resume the rotation persisted before the restart, if any.
+    async fn reconcile(&self) -> Result<()> {
+        if let Some(rotation) = self.state.get::<Rotation>(&self.state_key()) {
+            log::info!(
+                target: &self.log_target(),
+                "Resuming the rotation, preferring {}",
+                rotation.preferred.as_deref().unwrap_or("none")
+            );
+            *self.rotation.lock().unwrap() = rotation;
+        }
+
+        Ok(())
+    }
+
+    /// Cancel the offers submitted by the strategy on all its currencies.
+    async fn cancel_offers(&self) -> Self::Output {
+        for currency in &self.currencies {
+            self.owned_offers[currency]
+                .cancel_all(
+                    &self.client,
+                    &common::funding_symbol(currency, None),
+                    &self.log_target(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Execute the strategy.
+    async fn execute(&self) -> Self::Output {
+        log::info!(
+            target: &self.log_target(),
+            "Executing {} on {}...",
+            self.name(),
+            self.currencies.join(", ")
+        );
+
+        let rates = self.rates().await;
+
+        let preferred = {
+            let mut rotation = self.rotation.lock().unwrap();
+            let preferred = rotation
+                .update(&rates, self.margin, self.confirm_cycles)
+                .map(str::to_string);
+            self.state.set(&self.state_key(), &*rotation)?;
+            preferred
+        };
+
+        log::info!(target: &self.log_target(), "Rates comparison:");
+        for (currency, rate) in &rates {
+            log::info!(
+                target: &self.log_target(),
+                "  {currency}: {} per day ({} APR){}",
+                self.rate_display.rate(rate),
+                self.rate_display.apr(rate * 365.),
+                if preferred.as_deref() == Some(currency) {
+                    " <- preferred"
+                } else {
+                    ""
+                }
+            );
+        }
+
+        let Some(preferred) = preferred else {
+            log::info!(target: &self.log_target(), "No rate available, skipping");
+            return Ok(());
+        };
+
+        for currency in &self.currencies {
+            if *currency != preferred {
+                log::info!(
+                    target: &self.log_target(),
+                    "Newly available {currency} funds are held back for {preferred}"
+                );
+            }
+        }
+
+        let rate = rates
+            .iter()
+            .find(|(currency, _)| *currency == preferred)
+            .map(|(_, rate)| *rate)
+            .unwrap();
+
+        self.lend(&preferred, rate).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rates(usd: f64, ust: f64) -> Vec<(String, f64)> {
+        vec![("USD".to_string(), usd), ("UST".to_string(), ust)]
+    }
+
+    #[test]
+    fn best_currency_is_preferred_at_first() {
+        let mut rotation = Rotation::default();
+
+        assert_eq!(rotation.update(&[], 0.1, 3), None);
+        assert_eq!(rotation.update(&rates(0.0002, 0.0003), 0.1, 3), Some("UST"));
+    }
+
+    #[test]
+    fn switch_after_confirm_cycles_above_the_margin() {
+        let mut rotation = Rotation::default();
+        rotation.update(&rates(0.0003, 0.0002), 0.1, 2);
+
+        // Within the margin: no switch.
+        assert_eq!(
+            rotation.update(&rates(0.0003, 0.00032), 0.1, 2),
+            Some("USD")
+        );
+        // Above the margin, but not for long enough.
+        assert_eq!(rotation.update(&rates(0.0003, 0.0004), 0.1, 2), Some("USD"));
+        assert_eq!(rotation.update(&rates(0.0003, 0.0004), 0.1, 2), Some("UST"));
+    }
+
+    #[test]
+    fn streak_is_reset_when_the_challenger_falls_back() {
+        let mut rotation = Rotation::default();
+        rotation.update(&rates(0.0003, 0.0002), 0.1, 2);
+
+        assert_eq!(rotation.update(&rates(0.0003, 0.0004), 0.1, 2), Some("USD"));
+        assert_eq!(rotation.update(&rates(0.0003, 0.0003), 0.1, 2), Some("USD"));
+        assert_eq!(rotation.update(&rates(0.0003, 0.0004), 0.1, 2), Some("USD"));
+    }
+
+    #[test]
+    fn switch_right_away_when_the_preferred_rate_is_unavailable() {
+        let mut rotation = Rotation::default();
+        rotation.update(&rates(0.0003, 0.0002), 0.1, 3);
+
+        let ust_only = vec![("UST".to_string(), 0.0002)];
+        assert_eq!(rotation.update(&ust_only, 0.1, 3), Some("UST"));
+    }
+}
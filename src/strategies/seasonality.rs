@@ -0,0 +1,190 @@
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::{ensure, Result};
+use serde::Deserialize;
+
+/// Bounds of the seasonality multipliers.
+const MIN_MULTIPLIER: f64 = 0.5;
+const MAX_MULTIPLIER: f64 = 2.;
+
+/// Day of the week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Weekday {
+    #[serde(alias = "mon")]
+    Monday,
+    #[serde(alias = "tue")]
+    Tuesday,
+    #[serde(alias = "wed")]
+    Wednesday,
+    #[serde(alias = "thu")]
+    Thursday,
+    #[serde(alias = "fri")]
+    Friday,
+    #[serde(alias = "sat")]
+    Saturday,
+    #[serde(alias = "sun")]
+    Sunday,
+}
+
+impl Weekday {
+    /// Return the day of the week (UTC) of the UNIX `timestamp`, in seconds.
+    pub fn from_unix_time(timestamp: u64) -> Self {
+        // The epoch was a Thursday.
+        match (timestamp / 86400 + 3) % 7 {
+            0 => Self::Monday,
+            1 => Self::Tuesday,
+            2 => Self::Wednesday,
+            3 => Self::Thursday,
+            4 => Self::Friday,
+            5 => Self::Saturday,
+            _ => Self::Sunday,
+        }
+    }
+}
+
+/// Pricing of a day of the week.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct DayProfile {
+    /// Multiplier applied to the rate.
+    #[serde(default = "default_multiplier")]
+    pub multiplier: f64,
+    /// Minimum rate, per day, the rate is raised to.
+    pub min_rate: Option<f64>,
+}
+
+fn default_multiplier() -> f64 {
+    1.
+}
+
+impl Default for DayProfile {
+    fn default() -> Self {
+        Self {
+            multiplier: default_multiplier(),
+            min_rate: None,
+        }
+    }
+}
+
+/// Weekly seasonality of the funding demand: the pricing of each day of the week, the missing
+/// days being priced as is.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Seasonality {
+    days: BTreeMap<Weekday, DayProfile>,
+}
+
+impl Seasonality {
+    /// Ensure the multipliers are bounded and the minimum rates are not negative.
+    pub fn validate(&self) -> Result<()> {
+        for (weekday, day) in &self.days {
+            ensure!(
+                (MIN_MULTIPLIER..=MAX_MULTIPLIER).contains(&day.multiplier),
+                "multiplier of {weekday:?} must be within [{MIN_MULTIPLIER}, {MAX_MULTIPLIER}]"
+            );
+            ensure!(
+                day.min_rate.map_or(true, |min_rate| min_rate >= 0.),
+                "min_rate of {weekday:?} must not be negative"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Return whether no day is configured.
+    pub fn is_empty(&self) -> bool {
+        self.days.is_empty()
+    }
+
+    /// Return the pricing of `weekday`.
+    pub fn day(&self, weekday: Weekday) -> DayProfile {
+        self.days.get(&weekday).copied().unwrap_or_default()
+    }
+
+    /// Return `rate` multiplied and floored according to the pricing of `weekday`.
+    pub fn apply(&self, weekday: Weekday, rate: f64) -> f64 {
+        let day = self.day(weekday);
+        (rate * day.multiplier).max(day.min_rate.unwrap_or(0.))
+    }
+}
+
+impl fmt::Display for Seasonality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days: Vec<String> = self
+            .days
+            .iter()
+            .map(|(weekday, day)| match day.min_rate {
+                Some(min_rate) => format!(
+                    "{weekday:?}: x{} (min {:.4}%)",
+                    day.multiplier,
+                    min_rate * 100.
+                ),
+                None => format!("{weekday:?}: x{}", day.multiplier),
+            })
+            .collect();
+
+        write!(f, "{}", days.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seasonality() -> Seasonality {
+        Seasonality {
+            days: BTreeMap::from([
+                (
+                    Weekday::Wednesday,
+                    DayProfile {
+                        multiplier: 1.2,
+                        min_rate: None,
+                    },
+                ),
+                (
+                    Weekday::Sunday,
+                    DayProfile {
+                        multiplier: 0.9,
+                        min_rate: Some(0.0002),
+                    },
+                ),
+            ]),
+        }
+    }
+
+    #[test]
+    fn weekday_from_unix_time() {
+        assert_eq!(Weekday::from_unix_time(0), Weekday::Thursday);
+        // 2024-01-01 00:00:00 and 23:59:59 UTC, a Monday.
+        assert_eq!(Weekday::from_unix_time(1_704_067_200), Weekday::Monday);
+        assert_eq!(Weekday::from_unix_time(1_704_153_599), Weekday::Monday);
+        assert_eq!(Weekday::from_unix_time(1_704_585_600), Weekday::Sunday);
+    }
+
+    #[test]
+    fn missing_days_are_priced_as_is() {
+        assert_eq!(seasonality().apply(Weekday::Monday, 0.0003), 0.0003);
+    }
+
+    #[test]
+    fn days_are_multiplied_and_floored() {
+        assert!((seasonality().apply(Weekday::Wednesday, 0.0003) - 0.000_36).abs() < 1e-12);
+        assert!((seasonality().apply(Weekday::Sunday, 0.0003) - 0.000_27).abs() < 1e-12);
+        assert_eq!(seasonality().apply(Weekday::Sunday, 0.0001), 0.0002);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(seasonality().validate().is_ok());
+
+        let mut seasonality = seasonality();
+        seasonality.days.insert(
+            Weekday::Friday,
+            DayProfile {
+                multiplier: 3.,
+                min_rate: None,
+            },
+        );
+        assert!(seasonality.validate().is_err());
+    }
+}
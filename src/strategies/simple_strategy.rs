@@ -1,305 +1,5047 @@
 use std::{
-    collections::HashMap,
-    env, fs,
-    time::{SystemTime, UNIX_EPOCH},
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    sync::Mutex,
 };
 
-use anyhow::{anyhow, bail, Ok, Result};
+use anyhow::{anyhow, ensure, Context, Ok, Result};
 use async_trait::async_trait;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use bitfinex_api::{
     api::{
         authenticated::{
-            funding::{
-                active_funding_offers::{ActiveFundingOffers, ActiveFundingOffersResp},
-                cancel_all_funding_offers::CancelAllFundingOffers,
-                cancel_funding_offer::CancelFundingOffer,
-                submit_funding_offer::SubmitFundingOffer,
-                types::{FundingOffer, FundingOfferType},
-            },
-            wallets::{WalletResp, WalletType, Wallets, WalletsResp},
+            funding::{funding_close::FundingClose, types::FundingOffer},
+            wallets::WalletResp,
         },
-        common::{Section, Sort, TimeFrame},
         ignore::ignore,
-        public::candles::{AvailableCandles, Candles, HistCandlesResp},
         query::AsyncQuery,
     },
     bitfinex::AsyncBitfinex,
 };
 
-use super::Strategy;
+use crate::{
+    budget::{AccountBudget, AccountOffers, DailyBudget},
+    config::{self, ConfigFiles},
+    price_feed::PriceFeed,
+    skips::{SkipReason, SkipStats},
+    state::{Action, PendingAction, StateStore, Submission},
+};
 
-pub struct SimpleStrategy {
-    name: String,
-    client: AsyncBitfinex,
+use super::{
+    book::{self, BookLevel},
+    common,
+    credits::{CapitalUsage, CloseCredits, Credit, CreditsSummary},
+    error::BitfinexError,
+    indicators,
+    owned_offers::{reconcile_owned_offers, OwnedOffers},
+    period_optimizer::{self, PeriodOptimizer, PeriodQuote},
+    precision::Precision,
+    rate_display::{self, RateDisplay},
+    rate_guard::{self, OfferParams, RateGuard},
+    rate_selector::RateSelector,
+    seasonality::{Seasonality, Weekday},
+    timeframe::{CandleSection, CandleTimeFrame},
+    utilization::{SizingTable, UtilizationTable},
+    FromConfig, Shared, Strategy,
+};
+
+/// Bounds of the `hourly_premium` multipliers.
+const MIN_HOURLY_PREMIUM: f64 = 0.5;
+const MAX_HOURLY_PREMIUM: f64 = 2.;
+
+/// Relative rate rise below which the active offer is never replaced at a higher rate, keeping its
+/// queue position, and least downward drift the trailing holds out for.
+const REPRICE_DRIFT: f64 = 0.01;
+
+/// Age, in candles of the configured timeframe, beyond which the latest reference candle is
+/// considered stale.
+const MAX_REFERENCE_CANDLE_AGE: u64 = 4;
+
+/// Margin above the platform minimum offer size an offer rejected below it is retried with.
+const BELOW_MINIMUM_MARGIN: f64 = 1.01;
+
+/// Interval, in seconds, between two fetches of the platform minimum offer size.
+const PLATFORM_MIN_AMOUNT_REFRESH_SECS: u64 = 24 * 3600;
+
+/// API requests a submission may take at worst: the offer, then either the lookup of the offer
+/// already existing, or the refresh of the minimum offer size and the retry above it.
+const MAX_SUBMIT_REQUESTS: usize = 3;
+
+/// API requests the minimum amount may take at worst: the USD price and the platform minimum.
+const MIN_AMOUNT_REQUESTS: usize = 2;
+
+/// Derive the minimum rate from the market instead of using a static value.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DynamicMinRate {
+    /// Percentile, within (0, 100], of the candle closes used as the minimum rate.
+    percentile: f64,
+    /// Number of hours of candles to compute the percentile over.
+    lookback: u64,
+}
+
+/// Measure of the volatility of the candle highs.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VolatilityMeasure {
+    StdDev,
+    Range,
+}
+
+/// Raise the offered rate when the market is volatile.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VolatilityPremium {
+    #[serde(default = "default_volatility_measure")]
+    measure: VolatilityMeasure,
+    /// Multiplier applied to the volatility.
+    k: f64,
+    /// Maximum premium added to the rate, per day.
+    max_premium: f64,
+}
+
+fn default_volatility_measure() -> VolatilityMeasure {
+    VolatilityMeasure::StdDev
+}
+
+impl VolatilityPremium {
+    /// Return the premium to add to the rate selected from `highs`.
+    fn premium(&self, highs: &[f64]) -> f64 {
+        let volatility = match self.measure {
+            VolatilityMeasure::StdDev => indicators::std_dev(highs),
+            VolatilityMeasure::Range => indicators::range(highs),
+        };
+
+        (self.k * volatility.unwrap_or_default()).min(self.max_premium)
+    }
+}
+
+/// Return the available and total balances of the funding wallet, the amounts of the active
+/// offers being available again since the offers are about to be reallocated.
+fn compute_balances(balance: f64, available_balance: f64, offer_amounts: &[f64]) -> (f64, f64) {
+    (
+        available_balance + offer_amounts.iter().sum::<f64>(),
+        balance,
+    )
+}
+
+/// Return whether a candle of `volume` reaches `min_candle_volume`, if any.
+fn is_liquid(volume: f64, min_candle_volume: Option<f64>) -> bool {
+    min_candle_volume.map_or(true, |min_candle_volume| volume >= min_candle_volume)
+}
+
+/// Return the index of the offer to reconcile against the desired one among the active offers,
+/// given as `(id, creation timestamp)`: the tracked offer if it is still active, else the newest
+/// one. The other offers are surplus.
+fn primary_offer(offers: &[(u64, u64)], tracked_offer_id: Option<u64>) -> Option<usize> {
+    offers
+        .iter()
+        .position(|(id, _)| Some(*id) == tracked_offer_id)
+        .or_else(|| {
+            offers
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, (_, mts_create))| *mts_create)
+                .map(|(index, _)| index)
+        })
+}
+
+/// Split `amount` into the fewest offers of equal size at most `max_offer_size`, if any, each of
+/// at least `min_amount` so that no dust-sized chunk is rejected, even if the offers end up larger.
+fn offer_chunks(amount: f64, max_offer_size: Option<f64>, min_amount: f64) -> Vec<f64> {
+    let Some(max_offer_size) = max_offer_size else {
+        return vec![amount];
+    };
+
+    let count = (amount / max_offer_size)
+        .ceil()
+        .min((amount / min_amount).floor())
+        .max(1.) as usize;
+    vec![amount / count as f64; count]
+}
+
+/// What to do with the remainder of a partially filled offer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartialFill {
+    /// Leave the remainder as is.
+    Leave,
+    /// Top the remainder up with an extra offer of the filled amount at the same rate and period,
+    /// out of the newly available balance.
+    #[default]
+    TopUp,
+    /// Cancel the remainder, its funds being offered again by the next cycle.
+    Cancel,
+}
+
+/// Return the amount filled from the offer `id` now of `amount` since it was last seen, given as
+/// `(id, amount)`, or `None` if it was not partially filled.
+fn partially_filled(last_offer: Option<(u64, f64)>, id: u64, amount: f64) -> Option<f64> {
+    last_offer
+        .filter(|(last_id, last_amount)| *last_id == id && amount < last_amount - 1e-8)
+        .map(|(_, last_amount)| last_amount - amount)
+}
+
+/// Order of the cancellation and the submission when replacing the active offer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplaceOrder {
+    /// Cancel the active offer, then submit the new one right away.
+    #[default]
+    CancelFirst,
+    /// Submit the new offer, then cancel the active one, so that an offer is always on the book.
+    /// Both are held briefly, so it falls back to cancel first when the free balance does not
+    /// cover the new offer.
+    SubmitFirst,
+}
+
+/// What to do with an offer too large for the depth of the book.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DepthAction {
+    /// Warn and offer the amount anyway.
+    #[default]
+    Warn,
+    /// Scale the offer down to the max fraction of the depth.
+    ScaleDown,
+}
+
+/// Compare the offer to the depth of the bids around its rate, as most of an offer larger than
+/// what the borrowers ask for will not fill.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct DepthCheck {
+    /// Range below the rate, relative to it, within which the bids count in the depth.
+    rate_range: f64,
+    /// Maximum fraction of the depth an offer may take.
+    max_depth_fraction: f64,
+    #[serde(default)]
+    action: DepthAction,
+}
+
+impl DepthCheck {
+    /// Return the maximum amount to offer at `rate` given the `bids` of the book.
+    fn max_amount(&self, bids: &[BookLevel], rate: f64) -> f64 {
+        book::depth_above(bids, rate * (1. - self.rate_range)) * self.max_depth_fraction
+    }
+}
+
+/// Reprice the offers downward the longer they sit unfilled.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FillSla {
+    /// Age, in seconds, of the offer from which it is repriced.
+    after_secs: u64,
+    /// Fraction of the rate taken off at each step.
+    step: f64,
+    /// Number of seconds between two steps.
+    interval_secs: u64,
+}
+
+impl FillSla {
+    /// Return `rate` lowered by one `step` per `interval_secs` elapsed once the offer is
+    /// `after_secs` old, down to `min_rate` (a rate already below it is kept as is).
+    fn rate(&self, rate: f64, age_secs: u64, min_rate: f64) -> f64 {
+        if age_secs < self.after_secs {
+            return rate;
+        }
+
+        let steps = (age_secs - self.after_secs) / self.interval_secs + 1;
+        (rate * (1. - self.step * steps as f64)).max(min_rate.min(rate))
+    }
+}
+
+/// Follow the market up right away, but resist chasing it down in case the demand returns.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Trailing {
+    /// Relative drift below the active offer rate required to reprice it downward.
+    down_drift: f64,
+    /// Number of seconds since the last downward reprice required to reprice the offer downward
+    /// again.
+    down_hold_secs: u64,
+}
+
+impl Trailing {
+    /// Return the rate to target at `now` given the `offer_rate` of the active offer: the market
+    /// `rate` if it is higher, or if it drifted far enough below and the offer was not repriced
+    /// down since `down_hold_secs` (`last_down` being the time of the last downward reprice), else
+    /// `offer_rate`.
+    fn rate(&self, offer_rate: f64, rate: f64, now: u64, last_down: Option<u64>) -> f64 {
+        if rate >= offer_rate {
+            return rate;
+        }
+
+        let drift = (offer_rate - rate) / offer_rate;
+        let held_secs = last_down.map(|last_down| now.saturating_sub(last_down));
+
+        if drift > self.down_drift
+            && held_secs.map_or(true, |held_secs| held_secs >= self.down_hold_secs)
+        {
+            rate
+        } else {
+            offer_rate
+        }
+    }
+
+    /// Record in `last_down` the reprice of the offer from `previous_rate` to `rate` at `now`, if
+    /// it moved downward.
+    fn record(last_down: &mut Option<u64>, previous_rate: f64, rate: f64, now: u64) {
+        if rate < previous_rate {
+            *last_down = Some(now);
+        }
+    }
+}
+
+/// Only reprice the offer down when the trend of the candle highs confirms it, to not churn on a
+/// single bad candle.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MomentumFilter {
+    /// Number of candles of each of the compared averages.
+    k: usize,
+    /// Fraction by which the average of the last `k` highs must be below the average of the `k`
+    /// before to reprice down.
+    margin: f64,
+}
+
+impl MomentumFilter {
+    /// Return whether the `highs` confirm a downward trend.
+    fn confirms_down(&self, highs: &[f64]) -> bool {
+        indicators::momentum(highs, self.k).is_some_and(|momentum| momentum < -self.margin)
+    }
+}
+
+/// Last fill of an offer of the strategy, persisted across restarts.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Fill {
+    /// UNIX timestamp (in seconds) at which the fill was detected.
+    timestamp: u64,
+    rate: f64,
+}
+
+/// Exponential moving averages of the target rate of each period, persisted across restarts, so
+/// that alternating periods do not start them over.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+struct RateEmas(BTreeMap<u8, f64>);
+
+impl RateEmas {
+    /// Update the average of `period` with its `rate`, weighted by `alpha`, starting from `rate`
+    /// for a period without any, and return it.
+    fn update(&mut self, period: u8, rate: f64, alpha: f64) -> f64 {
+        *self
+            .0
+            .entry(period)
+            .and_modify(|ema| *ema = alpha * rate + (1. - alpha) * *ema)
+            .or_insert(rate)
+    }
+}
+
+/// Never undercut a recent fill by much, whatever a single low candle says.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HighWaterMark {
+    /// Number of seconds after a fill during which the floor applies.
+    window_secs: u64,
+    /// Fraction of the fill rate below which the new offers are not priced.
+    floor_factor: f64,
+}
+
+impl HighWaterMark {
+    /// Return the floor set by `fill` at `now`, or `None` once the window expired.
+    fn floor(&self, fill: Fill, now: u64) -> Option<f64> {
+        (now.saturating_sub(fill.timestamp) < self.window_secs)
+            .then_some(fill.rate * self.floor_factor)
+    }
+}
+
+/// Currency lent instead when the balance is below `min_amount`, off its own wallet and candles.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FallbackCurrency {
     currency: String,
+    /// Minimum amount of an offer, in the fallback currency.
     min_amount: f64,
-    max_balance_percent_per_loan: f64,
+    /// Minimum rate, per day.
     min_rate: f64,
-    target_period: u8,
-    monitored_window: u64,
-    nth_highest_candle: usize,
 }
 
-impl SimpleStrategy {
-    #[allow(clippy::too_many_arguments)]
-    pub fn new(
-        name: String,
-        client: AsyncBitfinex,
-        currency: String,
-        min_amount: f64,
-        max_balance_percent_per_loan: f64,
-        min_rate: f64,
-        target_duration: u8,
-        monitored_window: u64,
-        nth_highest_candle: usize,
-    ) -> Self {
+/// Replace the active offer only when the interest it would gain outweighs losing its queue
+/// position.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Replacement {
+    /// Expected time, in hours, for an offer to be filled, over which the interest gained by a
+    /// replacement is estimated.
+    #[serde(default = "default_fill_horizon_hours")]
+    fill_horizon_hours: f64,
+    /// Minimum interest, in units of the currency, a replacement must gain over the horizon.
+    #[serde(default)]
+    min_gain: f64,
+    /// Relative drift above the target rate beyond which the active offer is deemed unfillable,
+    /// and replaced even at a lower rate.
+    #[serde(default = "default_unfillable_drift")]
+    unfillable_drift: f64,
+}
+
+fn default_fill_horizon_hours() -> f64 {
+    24.
+}
+
+fn default_unfillable_drift() -> f64 {
+    0.05
+}
+
+impl Default for Replacement {
+    fn default() -> Self {
         Self {
-            name,
-            client,
-            currency,
-            min_amount,
-            max_balance_percent_per_loan,
-            min_rate,
-            target_period: target_duration,
-            monitored_window,
-            nth_highest_candle,
+            fill_horizon_hours: default_fill_horizon_hours(),
+            min_gain: 0.,
+            unfillable_drift: default_unfillable_drift(),
         }
     }
+}
 
-    /// Fetch the funding wallet from Bitfinex API.
-    async fn funding_wallet(&self) -> Result<WalletResp> {
-        let wallets: WalletsResp = Wallets::builder()
-            .build()?
-            .query_async(&self.client)
-            .await?;
+/// Outcome of the comparison between keeping the active offer and replacing it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ReplaceDecision {
+    /// Interest gained over the fill horizon by the replacement, negative if lost.
+    gain: f64,
+    /// Whether the active offer is too far above the target rate to be filled.
+    unfillable: bool,
+    replace: bool,
+}
 
-        let funding_wallet = wallets
-            .into_iter()
-            .find(|wallet| wallet.ty == WalletType::Funding && wallet.currency == self.currency)
-            .ok_or(anyhow!("Funding wallet not found"))?;
+impl Replacement {
+    /// Decide whether to replace the active offer at `active_rate` by an offer of `amount` at
+    /// `rate`: never to a strictly lower rate unless the active offer is unfillable, otherwise
+    /// only if the rate rises beyond `REPRICE_DRIFT` and the gain exceeds `min_gain`.
+    fn decide(&self, active_rate: f64, amount: f64, rate: f64) -> ReplaceDecision {
+        let gain = amount * (rate - active_rate) * self.fill_horizon_hours / 24.;
+        let unfillable = active_rate > rate * (1. + self.unfillable_drift);
 
-        Ok(funding_wallet)
+        ReplaceDecision {
+            gain,
+            unfillable,
+            replace: if rate < active_rate {
+                unfillable
+            } else {
+                rate > active_rate * (1. + REPRICE_DRIFT) && gain > self.min_gain
+            },
+        }
     }
+}
 
-    /// Fetch the current active offer from Bitfinex API.
-    async fn active_offer(&self) -> Result<Option<FundingOffer>> {
-        let mut active_offers: ActiveFundingOffersResp = ActiveFundingOffers::builder()
-            .symbol(&format!("f{}", self.currency))
-            .build()?
-            .query_async(&self.client)
-            .await?;
+/// Return the average of the `(rate, weight)` components weighted by their weights, renormalized
+/// over the available components, or `None` if there are none.
+fn blend(components: &[(f64, f64)]) -> Option<f64> {
+    let total_weight: f64 = components.iter().map(|(_, weight)| weight).sum();
+    if total_weight <= 0. {
+        return None;
+    }
 
-        // Prevent from having simulataneous active offers.
-        if active_offers.len() > 1 {
-            ignore(
-                CancelAllFundingOffers::builder()
-                    .currency(&self.currency)
-                    .build()?,
-            )
-            .query_async(&self.client)
-            .await?;
+    Some(
+        components
+            .iter()
+            .map(|(rate, weight)| rate * weight)
+            .sum::<f64>()
+            / total_weight,
+    )
+}
 
-            bail!(
-                "Detected {} active offers on {}, which have all been canceled",
-                active_offers.len(),
-                self.currency
-            );
+/// Premium demanded for lending over a period.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeriodPremium {
+    /// Multiplier applied to the rate.
+    Multiplier(f64),
+    /// Basis points per day added to the rate.
+    Bps(f64),
+}
+
+impl PeriodPremium {
+    fn apply(&self, rate: f64) -> f64 {
+        match self {
+            PeriodPremium::Multiplier(multiplier) => rate * multiplier,
+            PeriodPremium::Bps(bps) => rate + bps / 10_000.,
         }
+    }
+}
 
-        Ok(active_offers.pop())
+impl fmt::Display for PeriodPremium {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeriodPremium::Multiplier(multiplier) => write!(f, "x{multiplier}"),
+            PeriodPremium::Bps(bps) => write!(f, "{bps:+} bps"),
+        }
     }
+}
 
-    /// Fetch the nth highest candles from the Bitfinex API.
-    async fn get_highest_rate(&self, nth_highest_candle: usize, period: u8) -> Result<f64> {
-        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+/// Fall back to another period when the rate of the target period is below the min rate.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct PeriodFallback {
+    /// Period, in days, to fall back to.
+    #[serde(default = "default_fallback_period")]
+    period: u8,
+    /// Offer at the rate of the fallback period even if it is below the min rate, else the floor
+    /// blocks the offer (the default).
+    #[serde(default = "default_accept_below_min_rate")]
+    accept_below_min_rate: bool,
+}
 
-        let start_mts = now - (self.monitored_window as u128 * 3600 * 1000);
+fn default_fallback_period() -> u8 {
+    2
+}
 
-        // Get the candles over le last 24 hours.
-        let mut candles: HistCandlesResp = Candles::builder()
-            .candles(AvailableCandles::FundingCandles {
-                time_frame: TimeFrame::FifteenMins,
-                currency: &format!("f{}", self.currency),
-                period,
-            })
-            .section(Section::Hist)
-            .sort(Sort::Asc)
-            .start(start_mts as _)
-            .build()?
-            .query_async(&self.client)
-            .await?;
+fn default_accept_below_min_rate() -> bool {
+    false
+}
 
-        if candles.len() < nth_highest_candle {
-            bail!("Not enough candles fetched");
+impl Default for PeriodFallback {
+    fn default() -> Self {
+        Self {
+            period: default_fallback_period(),
+            accept_below_min_rate: default_accept_below_min_rate(),
         }
+    }
+}
 
-        candles.sort_by(|a, b| b.high.partial_cmp(&a.high).unwrap());
+fn default_period_fallback() -> Option<PeriodFallback> {
+    Some(PeriodFallback::default())
+}
 
-        Ok(candles[nth_highest_candle - 1].high)
+impl PeriodFallback {
+    /// Return whether to fall back because the `rate` of the target `period` is below
+    /// `min_rate`, the fallback period being validated at load.
+    fn applies(&self, period: u8, rate: Option<f64>, min_rate: f64) -> bool {
+        rate.is_some_and(|rate| rate < min_rate) && period != self.period
     }
+}
 
-    /// Return the total and available balances (accounting for the current active offer, if any)
-    fn compute_balances(
-        &self,
-        funding_wallet: &WalletResp,
-        active_offer: &Option<FundingOffer>,
-    ) -> (f64, f64) {
-        let available_balance = funding_wallet.available_balance
-            + active_offer
-                .as_ref()
-                .map_or(0., |active_offer| active_offer.amount);
-        let total_balance = funding_wallet.balance;
+/// Rates of the periods, behind a trait for the period selection to be tested without the
+/// exchange.
+#[async_trait]
+trait PeriodRates: Sync {
+    /// Return the rate of `period`, if any.
+    async fn rate(&self, period: u8) -> Result<Option<f64>>;
+}
+
+/// Return the `target_period` and its rate, or the period and the rate of the `period_fallback`
+/// if the rate of the target period is below `min_rate`, logging under `log_target`.
+async fn fallback_quote(
+    rates: &impl PeriodRates,
+    log_target: &str,
+    target_period: u8,
+    min_rate: f64,
+    period_fallback: Option<PeriodFallback>,
+) -> Result<Option<(u8, f64)>> {
+    let mut period = target_period;
+    let mut rate = rates.rate(period).await?;
 
-        (available_balance, total_balance)
+    log::debug!(target: log_target, "{period} days rate: {rate:?}, min rate: {min_rate}");
+
+    // If the rate is too low for the targeted duration, query for the fallback period.
+    if let Some(period_fallback) =
+        period_fallback.filter(|period_fallback| period_fallback.applies(period, rate, min_rate))
+    {
+        log::debug!(
+            target: log_target,
+            "{period} days rate is below the min rate, falling back to {} days",
+            period_fallback.period
+        );
+        period = period_fallback.period;
+        rate = rates.rate(period).await?;
     }
+
+    Ok(rate.map(|rate| (period, rate)))
 }
 
-#[async_trait]
-impl Strategy for SimpleStrategy {
-    type Output = Result<()>;
+/// What to do with the funds when the floor blocks the offer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FloorAction {
+    /// Leave the funds idle, skipping the cycle.
+    #[default]
+    Idle,
+    /// Park the funds in an offer at the min rate for the target period, as the funding
+    /// auto-renew would, until the market reaches it.
+    Park,
+}
 
-    fn from_config(path: &str) -> Vec<Self> {
-        #[derive(Debug, Deserialize)]
-        struct Strategy {
-            keys: String,
-            currency: String,
-            min_amount: f64,
-            max_balance_percent_per_loan: f64,
-            min_rate: f64,
-            target_period: u8,
-            monitored_window: u64,
-            nth_highest_candle: usize,
+/// Floor of the offers of a cycle, and what to do with the funds when it blocks them.
+#[derive(Debug, Clone, Copy)]
+struct Floor {
+    min_rate: f64,
+    target_period: u8,
+    period_fallback: Option<PeriodFallback>,
+    floor_action: FloorAction,
+}
+
+/// Outcome of the check of the final rate against the floor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FloorCheck {
+    /// The rate clears the floor, or the period fallback accepts it below.
+    Clear,
+    /// The floor blocks the offer, the funds being left idle.
+    Idle,
+    /// The floor blocks the offer, the funds being parked @ `rate` for `period` days.
+    Park { period: u8, rate: f64 },
+}
+
+impl Floor {
+    /// Check the final `rate` of `period` against the floor.
+    fn check(&self, period: u8, rate: f64) -> FloorCheck {
+        if !blocked_by_min_rate(rate, period, self.min_rate, self.period_fallback) {
+            return FloorCheck::Clear;
         }
 
-        #[derive(Debug, Deserialize)]
-        struct Config {
-            simple_strategies: HashMap<String, Strategy>,
+        match self.floor_action {
+            FloorAction::Idle => FloorCheck::Idle,
+            FloorAction::Park => FloorCheck::Park {
+                period: self.target_period,
+                rate: self.min_rate,
+            },
         }
+    }
+}
 
-        let config: Config = serde_yaml::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+/// Offer priced by a cycle, along with the state of the active offer it was priced against.
+struct PricedOffer {
+    params: OfferParams,
+    /// Placement time of the offer, carried over from the active offer it replaces.
+    placed_at: u64,
+    /// Whether the active offer was stale, and repriced from the market.
+    stale: bool,
+}
 
-        config
-            .simple_strategies
-            .into_iter()
-            .map(|(name, strategy)| {
-                let api_key_env = format!("API_KEY_{}", strategy.keys);
-                let secret_key_env = format!("SECRET_KEY_{}", strategy.keys);
-
-                Self::new(
-                    name,
-                    AsyncBitfinex::new_auth(
-                        &env::var(&api_key_env)
-                            .unwrap_or_else(|_| panic!("Missing {api_key_env} env variable")),
-                        &env::var(&secret_key_env)
-                            .unwrap_or_else(|_| panic!("Missing {secret_key_env} env variable")),
-                    ),
-                    strategy.currency,
-                    strategy.min_amount,
-                    strategy.max_balance_percent_per_loan,
-                    strategy.min_rate,
-                    strategy.target_period,
-                    strategy.monitored_window,
-                    strategy.nth_highest_candle,
-                )
-            })
-            .collect()
+/// Return the market `rate` of the final `period` smoothed with its moving average in
+/// `rate_ema`, along with the alpha to weight it by, if any, then with its `period_premium`
+/// applied, logging to `log_target` with the rates formatted with `display`.
+fn premium_rate(
+    period: u8,
+    mut rate: f64,
+    rate_ema: Option<(&mut RateEmas, f64)>,
+    period_premium: &HashMap<u8, PeriodPremium>,
+    display: RateDisplay,
+    log_target: &str,
+) -> f64 {
+    // Smooth the market rate across the cycles not to chase its every jump.
+    if let Some((rate_emas, rate_ema_alpha)) = rate_ema {
+        let rate_ema = rate_emas.update(period, rate, rate_ema_alpha);
+        log::debug!(
+            target: log_target,
+            "{period} days rate smoothed: {} -> {} per day",
+            display.rate(rate),
+            display.rate(rate_ema)
+        );
+        rate = rate_ema;
     }
 
-    /// Execute the strategy.
-    async fn execute(&self) -> Self::Output {
-        log::info!("Executing {} on {}...", self.name, self.currency);
+    // Demand more for locking the funds longer, now that the period is final.
+    if let Some(period_premium) = period_premium.get(&period) {
+        rate = period_premium.apply(rate);
+        log::info!(
+            target: log_target,
+            "{period} days premium {period_premium} applied: {} per day",
+            display.rate(rate)
+        );
+    }
 
-        let funding_wallet = self.funding_wallet().await?;
-        let active_offer = self.active_offer().await?;
+    rate
+}
 
-        let (available_balance, total_balance) =
-            self.compute_balances(&funding_wallet, &active_offer);
+/// Return whether the final `rate` of `period` is below `min_rate`, unless the `period_fallback`
+/// to `period` accepts so.
+fn blocked_by_min_rate(
+    rate: f64,
+    period: u8,
+    min_rate: f64,
+    period_fallback: Option<PeriodFallback>,
+) -> bool {
+    rate < min_rate
+        && !period_fallback.is_some_and(|period_fallback| {
+            period_fallback.accept_below_min_rate && period_fallback.period == period
+        })
+}
 
-        // Early return if there is not enough available balance to create an offer.
-        if available_balance < self.min_amount {
-            log::info!(
-                "Insufficient balance to submit a lend offer: {available_balance:.2} < {:.2}",
-                self.min_amount
-            );
-            return Ok(());
-        }
+/// Periods the strategy may lend for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PeriodFilter {
+    /// Only periods allowed, all of them if `None`.
+    allowed: Option<Vec<u8>>,
+    blocked: Vec<u8>,
+}
 
-        log::info!(
-            "{available_balance:.2} {} ({:.2}%) available and can be lended.",
-            self.currency,
-            available_balance * 100. / total_balance
-        );
+impl PeriodFilter {
+    /// Return whether lending for `period` days is allowed.
+    fn allows(&self, period: u8) -> bool {
+        self.allowed
+            .as_ref()
+            .map_or(true, |allowed| allowed.contains(&period))
+            && !self.blocked.contains(&period)
+    }
+}
 
-        // Query the nth highest rate.
-        let mut period = self.target_period;
-        let mut rate = self
-            .get_highest_rate(self.nth_highest_candle, period)
-            .await?;
+/// Return whether the `credit` lends the offer of the `submission`: at its rate and period, for
+/// at most its amount (a partial fill lending part of it), and opened after it.
+fn lends_submission(credit: &Credit, submission: &Submission) -> bool {
+    (credit.rate - submission.rate).abs() < 1e-8
+        && credit.period == submission.period
+        && credit.amount <= submission.amount + 1e-8
+        && credit.mts_opening >= submission.timestamp * 1000
+}
 
-        // If the rate is too low for the targeted duration, query for a period of 2 days.
-        if rate < self.min_rate && period > 2 {
-            period = 2;
-            rate = self
-                .get_highest_rate(self.nth_highest_candle, period)
-                .await?;
+/// Return the principal lent by the strategy in the active `credits`: the `owned_credits` still
+/// active and the new credits lending its last `submission`, which join them.
+fn owned_principal(
+    credits: &[Credit],
+    owned_credits: &mut HashSet<u64>,
+    submission: Option<&Submission>,
+) -> f64 {
+    owned_credits.retain(|id| credits.iter().any(|credit| credit.id == *id));
+    owned_credits.extend(
+        credits
+            .iter()
+            .filter(|credit| {
+                submission.is_some_and(|submission| lends_submission(credit, submission))
+            })
+            .map(|credit| credit.id),
+    );
+
+    credits
+        .iter()
+        .filter(|credit| owned_credits.contains(&credit.id))
+        .map(|credit| credit.amount)
+        .sum()
+}
+
+/// Return whether the `current` funding auto-renew settings of the exchange, as `(rate, period)`
+/// or `None` if disabled, match the `desired` ones.
+fn auto_renew_matches(current: Option<(f64, u8)>, desired: Option<(f64, u8)>) -> bool {
+    match (current, desired) {
+        (None, None) => true,
+        (Some((current_rate, current_period)), Some((desired_rate, desired_period))) => {
+            current_period == desired_period && (current_rate - desired_rate).abs() < 1e-8
         }
+        _ => false,
+    }
+}
 
-        // Take 99% of the highest rate.
-        rate *= 0.99;
+/// Return the amount to retry an offer of `amount` rejected below the minimum offer size with,
+/// bumped above `min_amount`, or `None` if `max_amount` cannot clear it.
+fn bumped_amount(amount: f64, min_amount: f64, max_amount: f64) -> Option<f64> {
+    if max_amount < min_amount * BELOW_MINIMUM_MARGIN {
+        return None;
+    }
 
-        // Clamp the amount to loan as a fraction of the total balance.
-        let loan_amount = self
-            .min_amount
-            .max(available_balance.min(total_balance * self.max_balance_percent_per_loan));
+    let bumped = (min_amount.max(amount) * BELOW_MINIMUM_MARGIN).min(max_amount);
+    (bumped > amount).then_some(bumped)
+}
 
-        // Check if the active offer needs to be canceled.
-        if let Some(active_offer) = active_offer {
-            let rate_diff_percent = (active_offer.rate - rate).abs() / rate;
-            let amount_diff = (loan_amount - active_offer.amount).abs();
+/// Amount, rate and period of an offer, to recognize the offer of a submission among the active
+/// ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct OfferTerms {
+    amount: f64,
+    rate: f64,
+    period: u8,
+}
 
-            // Cancel the active offer if:
-            //  - its period is not the same as the current one
-            //  - or if its loan amount is different from the current one
-            //  - or if its rate is too far from the current one
-            if active_offer.period != period || amount_diff > 1. || rate_diff_percent > 0.01 {
-                ignore(CancelFundingOffer::builder().id(active_offer.id).build()?)
-                    .query_async(&self.client)
-                    .await?;
+impl OfferTerms {
+    fn of(offer: &FundingOffer) -> Self {
+        Self {
+            amount: offer.amount,
+            rate: offer.rate,
+            period: offer.period,
+        }
+    }
+
+    /// Return whether the `offer` created at `mts_create` is the one submitted with these terms,
+    /// rounded with `precision`, at `submitted_at` (in seconds): at the same rate and period, for
+    /// at most the amount (a partial fill lending part of it), and created after the submission.
+    fn matches(
+        &self,
+        offer: OfferTerms,
+        mts_create: u64,
+        submitted_at: u64,
+        precision: Precision,
+    ) -> bool {
+        (offer.rate - precision.rate(self.rate)).abs() < 1e-8
+            && offer.period == self.period
+            && offer.amount <= precision.amount(self.amount) + 1e-8
+            && mts_create >= submitted_at * 1000
+    }
+}
+
+/// Settlement of the action interrupted by a restart.
+#[derive(Debug, PartialEq)]
+enum Recovery {
+    /// The offer being canceled is still active, to manage again.
+    StillActive(u64),
+    /// The offer being canceled is gone, canceled or filled since.
+    Gone(u64),
+    /// The submitted offer landed, to adopt.
+    Landed(u64),
+    /// The submitted offer is not active: lent since, or it did not go through.
+    NotLanded,
+}
+
+/// Settle the `pending_action` interrupted by a restart against the `active_offers`, as
+/// `(id, terms, mts_create)`: only the offer matching the submitted terms (rounded with
+/// `precision`), created since and not already `owned`, is the submitted one, never a foreign
+/// offer.
+fn recover(
+    pending_action: PendingAction,
+    active_offers: &[(u64, OfferTerms, u64)],
+    owned_offers: &HashSet<u64>,
+    precision: Precision,
+) -> Recovery {
+    match pending_action.action {
+        Action::Cancel { offer_id } => {
+            if active_offers.iter().any(|(id, _, _)| *id == offer_id) {
+                Recovery::StillActive(offer_id)
             } else {
-                log::info!(
-                    "Active offer is good enough: {:.2} for {} days @ {:.4}% per day ({:.2}% APR)",
-                    active_offer.amount,
-                    active_offer.period,
-                    active_offer.rate * 100.,
-                    active_offer.rate * 100. * 365.
-                );
-                return Ok(());
+                Recovery::Gone(offer_id)
             }
         }
+        Action::Submit {
+            amount,
+            rate,
+            period,
+        } => {
+            let terms = OfferTerms {
+                amount,
+                rate,
+                period,
+            };
+            active_offers
+                .iter()
+                .find(|(id, offer, mts_create)| {
+                    !owned_offers.contains(id)
+                        && terms.matches(*offer, *mts_create, pending_action.timestamp, precision)
+                })
+                .map_or(Recovery::NotLanded, |(id, _, _)| Recovery::Landed(*id))
+        }
+    }
+}
 
-        ignore(
-            SubmitFundingOffer::builder()
-                .ty(FundingOfferType::Limit)
-                .symbol(&format!("f{}", self.currency))
-                .amount(loan_amount)
-                .rate(rate)
-                .period(period)
-                .hidden(true)
-                .build()?,
-        )
-        .query_async(&self.client)
-        .await?;
+/// Return the interest earned by lending `amount` at `rate` for `period` days.
+fn projected_interest(amount: f64, rate: f64, period: u8) -> f64 {
+    amount * rate * period as f64
+}
 
-        log::info!(
-            "Offer submitted: {:.2} for {} days @ {:.4}% per day ({:.2}% APR)",
-            loan_amount,
-            period,
-            rate * 100.,
-            rate * 100. * 365.
+/// Outcome of the `min_projected_interest` check of an offer.
+#[derive(Debug, PartialEq)]
+enum InterestCheck {
+    /// The interest of every chunk reaches the minimum.
+    Enough,
+    /// The interest of the smallest chunk is below the minimum: the active offer it would
+    /// replace is kept.
+    KeepActive(f64),
+    /// The interest of the smallest chunk is below the minimum, the funds being left idle.
+    Skip(f64),
+}
+
+/// Check the interest projected for each of the `chunks` lent at `rate` for `period` days
+/// against `min_projected_interest`, each chunk taking an offer slot of its own, `replacing` an
+/// active offer or not.
+fn check_interest(
+    chunks: &[f64],
+    rate: f64,
+    period: u8,
+    min_projected_interest: Option<f64>,
+    replacing: bool,
+) -> InterestCheck {
+    let Some(min_projected_interest) = min_projected_interest else {
+        return InterestCheck::Enough;
+    };
+    let smallest = chunks.iter().copied().fold(f64::INFINITY, f64::min);
+    let interest = projected_interest(smallest, rate, period);
+
+    if interest >= min_projected_interest {
+        InterestCheck::Enough
+    } else if replacing {
+        InterestCheck::KeepActive(interest)
+    } else {
+        InterestCheck::Skip(interest)
+    }
+}
+
+/// Return whether `count` more API requests after the `requests` of the cycle fit within
+/// `max_requests_per_cycle`.
+fn fits_request_cap(requests: usize, count: usize, max_requests_per_cycle: Option<usize>) -> bool {
+    max_requests_per_cycle.map_or(true, |max_requests_per_cycle| {
+        requests + count <= max_requests_per_cycle
+    })
+}
+
+/// Return whether `rate` falls short of `min_rate` plus the `min_rate_margin` fraction, the rates
+/// below `min_rate` included. A zero margin disables the check, the floor being enforced on its
+/// own.
+fn below_min_rate_margin(rate: f64, min_rate: f64, min_rate_margin: f64) -> bool {
+    min_rate_margin > 0. && rate < min_rate * (1. + min_rate_margin)
+}
+
+/// Ensure the features selecting from several candles are not combined with the `Last`
+/// `candle_section`, which only fetches the latest one: they would never get enough candles.
+fn validate_candle_section(
+    candle_section: CandleSection,
+    rate_selector: RateSelector,
+    momentum_filter: bool,
+    volatility_premium: bool,
+) -> Result<()> {
+    if candle_section == CandleSection::Hist {
+        return Ok(());
+    }
+
+    ensure!(
+        !momentum_filter,
+        "the last candle_section does not support momentum_filter"
+    );
+    ensure!(
+        !volatility_premium,
+        "the last candle_section does not support volatility_premium"
+    );
+    ensure!(
+        !matches!(
+            rate_selector,
+            RateSelector::NthHighest(n) | RateSelector::Ema { period: n, .. } if n > 1
+        ),
+        "the last candle_section does not support the {rate_selector} rate_selector, which needs \
+         several candles"
+    );
+
+    Ok(())
+}
+
+pub struct SimpleStrategy {
+    name: String,
+    client: AsyncBitfinex,
+    currency: String,
+    symbol: String,
+    /// Minimum amount in native units, superseded by `min_amount_usd` if set.
+    min_amount: Option<f64>,
+    max_balance_percent_per_loan: f64,
+    min_rate: f64,
+    target_period: u8,
+    monitored_window: u64,
+    rate_selector: RateSelector,
+    dynamic_min_rate: Option<DynamicMinRate>,
+    rate_guard: RateGuard,
+    volatility_premium: Option<VolatilityPremium>,
+    period_optimizer: Option<PeriodOptimizer>,
+    hourly_premium: HashMap<u8, f64>,
+    candle_timeframe: CandleTimeFrame,
+    frr_offset: Option<f64>,
+    utilization_table: Option<UtilizationTable>,
+    min_rate_margin: f64,
+    frr_factor: Option<f64>,
+    reference_currency: Option<String>,
+    spread_bps: f64,
+    state: StateStore,
+    max_offer_age_secs: Option<u64>,
+    price_feed: PriceFeed,
+    auto_renew: Option<bool>,
+    fill_sla: Option<FillSla>,
+    utilization_sizing: Option<SizingTable>,
+    trailing: Option<Trailing>,
+    /// UNIX timestamp (in seconds) of the last downward reprice of the active offer, for the
+    /// trailing mode.
+    last_down_reprice: Mutex<Option<u64>>,
+    seasonality: Seasonality,
+    momentum_filter: Option<MomentumFilter>,
+    high_water_mark: Option<HighWaterMark>,
+    skips: SkipStats,
+    blended_windows: BTreeMap<u64, f64>,
+    /// Same strategy on the fallback currency, run when the balance is too low.
+    fallback: Option<Box<SimpleStrategy>>,
+    period_premium: HashMap<u8, PeriodPremium>,
+    /// Minimum amount in USD, converted to native units at the spot price every cycle.
+    min_amount_usd: Option<f64>,
+    /// Last known USD price of the currency, for when the ticker fails.
+    last_usd_price: Mutex<Option<f64>>,
+    /// Cap on the new principal committed per UTC day.
+    daily_budget: Option<DailyBudget>,
+    /// Active offers submitted by the strategy, the only ones it may cancel or replace.
+    owned_offers: OwnedOffers,
+    /// UNIX timestamp (in milliseconds) of the latest candle the rate was selected from.
+    rate_candle_mts: Mutex<Option<u64>>,
+    /// Number of API requests of the current cycle, for `max_requests_per_cycle`.
+    requests: Mutex<usize>,
+    /// ID and amount of the active offer as last seen, to detect its partial fills.
+    last_offer: Mutex<Option<(u64, f64)>>,
+    /// Platform minimum offer size of the currency, and the UNIX timestamp it was fetched at.
+    platform_min_amount: Mutex<Option<(f64, u64)>>,
+    /// Whether the last refresh of the platform minimum offer size failed, warned about once.
+    platform_min_amount_failed: Mutex<bool>,
+    min_candle_volume: Option<f64>,
+    precision: Precision,
+    rate_display: RateDisplay,
+    replacement: Replacement,
+    hidden: bool,
+    close_credits: Option<CloseCredits>,
+    max_borrow_spread: Option<f64>,
+    partial_fill: PartialFill,
+    period_fallback: Option<PeriodFallback>,
+    floor_action: FloorAction,
+    period_filter: PeriodFilter,
+    depth_check: Option<DepthCheck>,
+    visible_after_secs: Option<u64>,
+    stale_after_secs: Option<u64>,
+    stale_discount: f64,
+    max_offer_size: Option<f64>,
+    offer_slots: AccountOffers,
+    candle_section: CandleSection,
+    replace_order: ReplaceOrder,
+    min_projected_interest: Option<f64>,
+    rate_ema_alpha: Option<f64>,
+    max_requests_per_cycle: Option<usize>,
+    /// Window of the capital usage, in seconds, if tracked.
+    capital_usage_window_secs: Option<u64>,
+    budget: AccountBudget,
+}
+
+impl SimpleStrategy {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        name: String,
+        client: AsyncBitfinex,
+        currency: String,
+        symbol: String,
+        min_amount: Option<f64>,
+        max_balance_percent_per_loan: f64,
+        min_rate: f64,
+        target_duration: u8,
+        monitored_window: u64,
+        rate_selector: RateSelector,
+        dynamic_min_rate: Option<DynamicMinRate>,
+        rate_guard: RateGuard,
+        volatility_premium: Option<VolatilityPremium>,
+        period_optimizer: Option<PeriodOptimizer>,
+        hourly_premium: HashMap<u8, f64>,
+        candle_timeframe: CandleTimeFrame,
+        frr_offset: Option<f64>,
+        utilization_table: Option<UtilizationTable>,
+        min_rate_margin: f64,
+        frr_factor: Option<f64>,
+        reference_currency: Option<String>,
+        spread_bps: f64,
+        state: StateStore,
+        max_offer_age_secs: Option<u64>,
+        price_feed: PriceFeed,
+        auto_renew: Option<bool>,
+        fill_sla: Option<FillSla>,
+        utilization_sizing: Option<SizingTable>,
+        trailing: Option<Trailing>,
+        seasonality: Seasonality,
+        momentum_filter: Option<MomentumFilter>,
+        high_water_mark: Option<HighWaterMark>,
+        skips: SkipStats,
+        blended_windows: BTreeMap<u64, f64>,
+        fallback: Option<Box<SimpleStrategy>>,
+        period_premium: HashMap<u8, PeriodPremium>,
+        min_amount_usd: Option<f64>,
+        daily_budget: Option<DailyBudget>,
+        min_candle_volume: Option<f64>,
+        precision: Precision,
+        rate_display: RateDisplay,
+        replacement: Replacement,
+        hidden: bool,
+        close_credits: Option<CloseCredits>,
+        max_borrow_spread: Option<f64>,
+        partial_fill: PartialFill,
+        period_fallback: Option<PeriodFallback>,
+        floor_action: FloorAction,
+        period_filter: PeriodFilter,
+        depth_check: Option<DepthCheck>,
+        visible_after_secs: Option<u64>,
+        stale_after_secs: Option<u64>,
+        stale_discount: f64,
+        max_offer_size: Option<f64>,
+        offer_slots: AccountOffers,
+        candle_section: CandleSection,
+        replace_order: ReplaceOrder,
+        min_projected_interest: Option<f64>,
+        rate_ema_alpha: Option<f64>,
+        max_requests_per_cycle: Option<usize>,
+        capital_usage_window_secs: Option<u64>,
+        budget: AccountBudget,
+    ) -> Self {
+        let owned_offers = OwnedOffers::load(&name, &currency, state.clone(), rate_display);
+
+        Self {
+            name,
+            client,
+            currency,
+            symbol,
+            min_amount,
+            max_balance_percent_per_loan,
+            min_rate,
+            target_period: target_duration,
+            monitored_window,
+            rate_selector,
+            dynamic_min_rate,
+            rate_guard,
+            volatility_premium,
+            period_optimizer,
+            hourly_premium,
+            candle_timeframe,
+            frr_offset,
+            utilization_table,
+            min_rate_margin,
+            frr_factor,
+            reference_currency,
+            spread_bps,
+            state,
+            max_offer_age_secs,
+            price_feed,
+            auto_renew,
+            fill_sla,
+            utilization_sizing,
+            trailing,
+            last_down_reprice: Mutex::new(None),
+            seasonality,
+            momentum_filter,
+            high_water_mark,
+            skips,
+            blended_windows,
+            fallback,
+            period_premium,
+            min_amount_usd,
+            last_usd_price: Mutex::new(None),
+            daily_budget,
+            owned_offers,
+            rate_candle_mts: Mutex::new(None),
+            requests: Mutex::new(0),
+            last_offer: Mutex::new(None),
+            platform_min_amount: Mutex::new(None),
+            platform_min_amount_failed: Mutex::new(false),
+            min_candle_volume,
+            precision,
+            rate_display,
+            replacement,
+            hidden,
+            close_credits,
+            max_borrow_spread,
+            partial_fill,
+            period_fallback,
+            floor_action,
+            period_filter,
+            depth_check,
+            visible_after_secs,
+            stale_after_secs,
+            stale_discount,
+            max_offer_size,
+            offer_slots,
+            candle_section,
+            replace_order,
+            min_projected_interest,
+            rate_ema_alpha,
+            max_requests_per_cycle,
+            capital_usage_window_secs,
+            budget,
+        }
+    }
+
+    /// Return the minimum amount in native units, raised to the platform minimum offer size.
+    async fn min_amount(&self) -> Result<f64> {
+        let min_amount = self.configured_min_amount().await?;
+
+        Ok(match self.platform_min_amount().await {
+            Some(platform_min_amount) => min_amount.max(platform_min_amount),
+            None => min_amount,
+        })
+    }
+
+    /// Return the platform minimum offer size of the currency, refreshed once a day, or the last
+    /// known one if the refresh fails, warning about the failures once until a refresh succeeds.
+    async fn platform_min_amount(&self) -> Option<f64> {
+        let now = common::unix_time().ok()?;
+        let last = *self.platform_min_amount.lock().unwrap();
+
+        if let Some((platform_min_amount, fetched_at)) = last {
+            if now.saturating_sub(fetched_at) < PLATFORM_MIN_AMOUNT_REFRESH_SECS {
+                return Some(platform_min_amount);
+            }
+        }
+
+        let fetched = async { common::platform_min_amount(self.client()?, &self.currency).await };
+        match fetched.await {
+            Result::Ok(platform_min_amount) => {
+                *self.platform_min_amount.lock().unwrap() = Some((platform_min_amount, now));
+                *self.platform_min_amount_failed.lock().unwrap() = false;
+
+                if let Result::Ok(min_amount) = self.configured_min_amount().await {
+                    if min_amount < platform_min_amount {
+                        log::warn!(
+                            target: &self.log_target(),
+                            "The configured min amount {min_amount:.2} is below the platform \
+                             minimum offer size of {platform_min_amount:.2} {}, using the latter",
+                            self.currency
+                        );
+                    }
+                }
+
+                Some(platform_min_amount)
+            }
+            Err(e) => {
+                let already_failed =
+                    std::mem::replace(&mut *self.platform_min_amount_failed.lock().unwrap(), true);
+                let level = if already_failed {
+                    log::Level::Debug
+                } else {
+                    log::Level::Warn
+                };
+                log::log!(
+                    target: &self.log_target(),
+                    level,
+                    "Failed to fetch the platform minimum offer size of {}: {e:#}",
+                    self.currency
+                );
+                last.map(|(platform_min_amount, _)| platform_min_amount)
+            }
+        }
+    }
+
+    /// Cancel the offer `id` along with the other `chunk_ids` it was submitted with.
+    async fn cancel_chunked_offer(&self, id: u64, chunk_ids: &[u64]) -> Result<()> {
+        for id in std::iter::once(id).chain(chunk_ids.iter().copied()) {
+            self.set_pending_action(Some(Action::Cancel { offer_id: id }))?;
+            common::cancel_offer(self.client()?, id, &self.log_target()).await?;
+            self.owned_offers.remove(id)?;
+        }
+        self.set_pending_action(None)?;
+
+        // Forget the canceled offer, not to mistake it for a vanished one in the next cycle.
+        if let Some(mut submission) = self.state.submission(&self.name, &self.currency) {
+            if submission.offer_id == Some(id) {
+                submission.offer_id = None;
+                self.state
+                    .set_submission(&self.name, &self.currency, &submission)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the client for an API request, counted against `max_requests_per_cycle`, or an
+    /// error aborting the cycle once the cap is exceeded.
+    fn client(&self) -> Result<&AsyncBitfinex> {
+        let mut requests = self.requests.lock().unwrap();
+
+        if let Some(max_requests_per_cycle) = self.max_requests_per_cycle {
+            if !fits_request_cap(*requests, 1, Some(max_requests_per_cycle)) {
+                log::warn!(
+                    target: &self.log_target(),
+                    "Cap of {max_requests_per_cycle} API requests per cycle reached, aborting the \
+                     cycle"
+                );
+                return Err(anyhow!(
+                    "{}: more than {max_requests_per_cycle} API requests in the cycle",
+                    self.name
+                ));
+            }
+        }
+        *requests += 1;
+
+        Ok(&self.client)
+    }
+
+    /// Return whether `count` more API requests fit within `max_requests_per_cycle`.
+    fn has_requests_left(&self, count: usize) -> bool {
+        fits_request_cap(
+            *self.requests.lock().unwrap(),
+            count,
+            self.max_requests_per_cycle,
+        )
+    }
+
+    /// Record the `action` about to be taken, `None` once it completed.
+    fn set_pending_action(&self, action: Option<Action>) -> Result<()> {
+        let pending_action = match action {
+            Some(action) => Some(PendingAction {
+                action,
+                timestamp: common::unix_time()?,
+            }),
+            None => None,
+        };
+
+        self.state
+            .set_pending_action(&self.name, &self.currency, pending_action)
+    }
+
+    /// Return whether a credit was opened since `timestamp`, in seconds, e.g. an offer submitted
+    /// then was lent.
+    async fn filled_since(&self, timestamp: u64) -> Result<bool> {
+        let submitted_at = timestamp * 1000;
+
+        Ok(self
+            .credits()
+            .await?
+            .iter()
+            .any(|credit| credit.mts_opening >= submitted_at))
+    }
+
+    /// Submit an offer of `params`, recorded as the pending action until the caller clears it, and
+    /// return its ID and amount, recovering from the rejections that allow
+    /// it: an offer already existing is adopted if it is the one just submitted, e.g. a retried
+    /// request which landed, `None` being returned,
+    /// and an amount below the minimum offer size is bumped above the refreshed minimum, up to
+    /// `max_amount`, and submitted once more. The other rejections are fatal to the cycle.
+    async fn submit(
+        &self,
+        params: OfferParams,
+        hidden: bool,
+        max_amount: f64,
+    ) -> Result<Option<(u64, f64)>> {
+        let amount = params.amount();
+        let rate = params.rate();
+        let period = params.period();
+        // Recorded before each attempt, the amount being bumped by the retry.
+        let submit = |amount| async move {
+            self.set_pending_action(Some(Action::Submit {
+                amount,
+                rate,
+                period,
+            }))?;
+            common::submit_offer(
+                self.client()?,
+                &self.symbol,
+                params.with_amount(amount),
+                self.precision,
+                hidden,
+                &self.log_target(),
+            )
+            .await
+        };
+
+        let submitted_at = common::unix_time()?;
+        let e = match submit(amount).await {
+            Result::Ok(offer) => return Ok(Some((offer.id, offer.amount))),
+            Err(e) => e,
+        };
+
+        match BitfinexError::classify(&e) {
+            Some(error @ BitfinexError::OfferExists(_)) => {
+                let terms = OfferTerms {
+                    amount,
+                    rate,
+                    period,
+                };
+                let Some(active_offer) = common::active_offers(self.client()?, &self.symbol)
+                    .await?
+                    .into_iter()
+                    .find(|active_offer| {
+                        terms.matches(
+                            OfferTerms::of(active_offer),
+                            active_offer.mts_create,
+                            submitted_at,
+                            self.precision,
+                        )
+                    })
+                else {
+                    return Err(e);
+                };
+
+                log::warn!(
+                    target: &self.log_target(),
+                    "{error}, adopting the submitted offer {}: {:.2} for {} days @ {} per day",
+                    active_offer.id,
+                    active_offer.amount,
+                    active_offer.period,
+                    self.rate_display.rate(active_offer.rate)
+                );
+                self.budget.deploy(active_offer.amount);
+                self.owned_offers.insert(active_offer.id)?;
+                Ok(None)
+            }
+            Some(error @ BitfinexError::BelowMinimum(_)) => {
+                // The cached platform minimum is obviously outdated.
+                *self.platform_min_amount.lock().unwrap() = None;
+                let min_amount = self.platform_min_amount().await.unwrap_or(amount);
+                let Some(bumped_amount) = bumped_amount(amount, min_amount, max_amount) else {
+                    return Err(e);
+                };
+
+                log::warn!(
+                    target: &self.log_target(),
+                    "{error}, retrying with {amount:.2} bumped to {bumped_amount:.2}"
+                );
+                let offer = submit(bumped_amount).await?;
+                Ok(Some((offer.id, offer.amount)))
+            }
+            _ => Err(e),
+        }
+    }
+
+    /// Top the partially filled `offer`, made of `active_count` offers, up with an extra offer of
+    /// `amount` at its rate and period, managed as a chunk of it.
+    async fn top_up(&self, offer: &FundingOffer, active_count: usize, amount: f64) -> Result<()> {
+        self.budget.deploy(offer.amount);
+
+        if !self.has_requests_left(MIN_AMOUNT_REQUESTS + MAX_SUBMIT_REQUESTS) {
+            log::warn!(
+                target: &self.log_target(),
+                "Too few API requests left in the cycle to top the offer {} up, leaving it",
+                offer.id
+            );
+            self.skips
+                .record(self.name(), SkipReason::RequestCapReached);
+            return Ok(());
+        }
+
+        let now = common::unix_time()?;
+        let amount = match &self.daily_budget {
+            Some(daily_budget) => amount.min(daily_budget.remaining(now)),
+            None => amount,
+        };
+        let min_amount = self.min_amount().await?;
+        if amount < min_amount {
+            log::info!(
+                target: &self.log_target(),
+                "Not topping up the offer {}: {amount:.2} < {min_amount:.2}",
+                offer.id
+            );
+            return Ok(());
+        }
+
+        self.offer_slots.update(active_count, active_count + 1);
+        if self.offer_slots.allowed() <= active_count {
+            log::info!(
+                target: &self.log_target(),
+                "Not topping up the offer {}: the account offer limit is reached",
+                offer.id
+            );
+            return Ok(());
+        }
+
+        let Some(params) =
+            self.rate_guard
+                .offer_params(amount, offer.rate, offer.period, &self.log_target())
+        else {
+            return Ok(());
+        };
+        let hidden = self.hidden && !self.visible();
+        let Some((id, amount)) = self.submit(params, hidden, amount).await? else {
+            self.set_pending_action(None)?;
+            return Ok(());
+        };
+        self.owned_offers.insert(id)?;
+        self.set_pending_action(None)?;
+        self.offer_slots.update(active_count + 1, active_count + 1);
+        self.budget.deploy(amount);
+        if let Some(daily_budget) = &self.daily_budget {
+            daily_budget.spend(amount, now)?;
+        }
+        *self.last_offer.lock().unwrap() = Some((offer.id, offer.amount + amount));
+
+        log::info!(
+            target: &self.log_target(),
+            "Offer {} topped up with the offer {id}: {amount:.2} for {} days @ {} per day",
+            offer.id,
+            offer.period,
+            self.rate_display.rate(offer.rate)
         );
 
         Ok(())
     }
+
+    /// Return the configured minimum amount in native units: `min_amount_usd` converted at the
+    /// spot price, or at the last known price if the ticker fails, else `min_amount`.
+    async fn configured_min_amount(&self) -> Result<f64> {
+        let Some(min_amount_usd) = self.min_amount_usd else {
+            return self.min_amount.context("No min_amount configured");
+        };
+
+        let usd_price = match self
+            .price_feed
+            .usd_price(self.client()?, &self.currency)
+            .await
+        {
+            Result::Ok(usd_price) => {
+                *self.last_usd_price.lock().unwrap() = Some(usd_price);
+                Some(usd_price)
+            }
+            Err(e) => {
+                let last_usd_price = *self.last_usd_price.lock().unwrap();
+                log::warn!(
+                    target: &self.log_target(),
+                    "Failed to fetch the {} price in USD, using the last known one \
+                     ({last_usd_price:?}): {e:#}",
+                    self.currency
+                );
+                last_usd_price
+            }
+        };
+
+        match usd_price {
+            Some(usd_price) if usd_price > 0. => {
+                let min_amount = min_amount_usd / usd_price;
+                log::debug!(
+                    target: &self.log_target(),
+                    "Min amount: {min_amount_usd:.2} USD = {min_amount:.8} {} @ {usd_price}",
+                    self.currency
+                );
+                Ok(min_amount)
+            }
+            _ => self
+                .min_amount
+                .with_context(|| format!("No {} price to convert min_amount_usd", self.currency)),
+        }
+    }
+
+    /// Fetch the funding wallet from Bitfinex API.
+    async fn funding_wallet(&self) -> Result<WalletResp> {
+        common::funding_wallet(self.client()?, &self.currency).await
+    }
+
+    /// Fetch the active offers from Bitfinex API.
+    async fn active_offers(&self) -> Result<Vec<FundingOffer>> {
+        common::active_offers(self.client()?, &self.symbol).await
+    }
+
+    /// Fetch the candle highs of `period` over the monitored window.
+    async fn candle_highs(&self, period: u8) -> Result<Vec<f64>> {
+        let highs = self
+            .timed_candle_highs(period, self.monitored_window)
+            .await?;
+
+        Ok(highs.into_iter().map(|(_, high)| high).collect())
+    }
+
+    /// Fetch the candle highs of `period` over the last `window` hours, along with their
+    /// timestamps (in milliseconds), from the reference currency market shifted by `spread_bps`
+    /// if it is set and not stale, else from the strategy market.
+    async fn timed_candle_highs(&self, period: u8, window: u64) -> Result<Vec<(u64, f64)>> {
+        if let Some(reference_currency) = &self.reference_currency {
+            let reference_symbol = format!("f{reference_currency}");
+            let candles = common::candles(
+                self.client()?,
+                &reference_symbol,
+                window,
+                period,
+                self.candle_timeframe,
+                self.candle_section,
+            )
+            .await?;
+
+            let now = common::unix_time()? * 1000;
+            let max_age = MAX_REFERENCE_CANDLE_AGE * self.candle_timeframe.millis();
+            match candles.last() {
+                Some(latest) if now.saturating_sub(latest.mts) <= max_age => {
+                    let spread = 1. + self.spread_bps / 10_000.;
+                    log::info!(
+                        target: &self.log_target(),
+                        "Pricing off the {reference_symbol} candles with a {:+} bps spread",
+                        self.spread_bps
+                    );
+                    return Ok(candles
+                        .iter()
+                        .filter(|candle| self.is_liquid(candle.volume))
+                        .map(|candle| (candle.mts, candle.high * spread))
+                        .collect());
+                }
+                _ => log::warn!(
+                    target: &self.log_target(),
+                    "The {reference_symbol} candles are stale, falling back to the {} ones",
+                    self.symbol
+                ),
+            }
+        }
+
+        let candles = common::candles(
+            self.client()?,
+            &self.symbol,
+            window,
+            period,
+            self.candle_timeframe,
+            self.candle_section,
+        )
+        .await?;
+
+        Ok(candles
+            .iter()
+            .filter(|candle| self.is_liquid(candle.volume))
+            .map(|candle| (candle.mts, candle.high))
+            .collect())
+    }
+
+    /// Return whether a candle of `volume` is a reliable rate signal, the candles below
+    /// `min_candle_volume` being excluded from the ranking.
+    fn is_liquid(&self, volume: f64) -> bool {
+        is_liquid(volume, self.min_candle_volume)
+    }
+
+    /// Select the rate of each of the blended windows from a single fetch of the longest one, and
+    /// blend them by weight. Return the blended rate along with the highs of the longest window,
+    /// or `None` if no window has enough candles for the rate selector.
+    async fn blended_rate(&self, period: u8) -> Result<Option<(f64, Vec<f64>)>> {
+        let longest_window = self
+            .blended_windows
+            .keys()
+            .max()
+            .copied()
+            .unwrap_or_default();
+        let highs = self.timed_candle_highs(period, longest_window).await?;
+        *self.rate_candle_mts.lock().unwrap() = highs.last().map(|(mts, _)| *mts);
+        let now = common::unix_time()? * 1000;
+
+        let mut components = vec![];
+        for (window, weight) in &self.blended_windows {
+            let since = now.saturating_sub(window * 3600 * 1000);
+            let window_highs: Vec<f64> = highs
+                .iter()
+                .filter(|(mts, _)| *mts >= since)
+                .map(|(_, high)| *high)
+                .collect();
+
+            match self.rate_selector.select(&window_highs) {
+                Some(rate) => {
+                    log::info!(
+                        target: &self.log_target(),
+                        "{} candle rate for {period} days over {window}h: {} per day (weight \
+                         {weight})",
+                        self.rate_selector,
+                        self.rate_display.rate(rate)
+                    );
+                    components.push((rate, *weight));
+                }
+                None => log::info!(
+                    target: &self.log_target(),
+                    "Not enough candles over {window}h, leaving it out"
+                ),
+            }
+        }
+
+        let Some(rate) = blend(&components) else {
+            return Ok(None);
+        };
+        log::info!(
+            target: &self.log_target(),
+            "Blended candle rate for {period} days: {} per day",
+            self.rate_display.rate(rate)
+        );
+
+        Ok(Some((
+            rate,
+            highs.into_iter().map(|(_, high)| high).collect(),
+        )))
+    }
+
+    /// Select the rate from the candles of the Bitfinex API, or `None` if there are not enough
+    /// candles for the rate selector.
+    async fn get_rate(&self, period: u8) -> Result<Option<f64>> {
+        let (rate, highs) = if self.blended_windows.is_empty() {
+            let timed_highs = self
+                .timed_candle_highs(period, self.monitored_window)
+                .await?;
+            *self.rate_candle_mts.lock().unwrap() = timed_highs.last().map(|(mts, _)| *mts);
+            let highs: Vec<f64> = timed_highs.into_iter().map(|(_, high)| high).collect();
+
+            let Some(rate) = self.rate_selector.select(&highs) else {
+                return Ok(None);
+            };
+
+            if let Some(latest_high) = highs.last() {
+                log::info!(
+                    target: &self.log_target(),
+                    "{} candle rate for {period} days: {} per day (latest high {})",
+                    self.rate_selector,
+                    self.rate_display.rate(rate),
+                    self.rate_display.rate(*latest_high)
+                );
+            }
+
+            (rate, highs)
+        } else {
+            let Some(blended) = self.blended_rate(period).await? else {
+                return Ok(None);
+            };
+
+            blended
+        };
+
+        let Some(volatility_premium) = &self.volatility_premium else {
+            return Ok(Some(rate));
+        };
+
+        let premium = volatility_premium.premium(&highs);
+        log::info!(
+            target: &self.log_target(),
+            "Volatility premium: {} per day",
+            self.rate_display.offset(premium)
+        );
+
+        Ok(Some(rate + premium))
+    }
+
+    /// Return the period and the rate selected from the candles, for the best candidate period if
+    /// the optimizer is enabled.
+    async fn candle_quote(&self, min_rate: f64) -> Result<Option<(u8, f64)>> {
+        if let Some(period_optimizer) = &self.period_optimizer {
+            return self.optimize_period(period_optimizer, min_rate).await;
+        }
+
+        fallback_quote(
+            self,
+            &self.log_target(),
+            self.target_period,
+            min_rate,
+            self.period_fallback,
+        )
+        .await
+    }
+
+    /// Price the offer of `loan_amount` from the market `quote` of a period and its rate, against
+    /// `min_rate` and the `active_offer` if any, at `now`: the rate is smoothed, the premiums and
+    /// the repricing rules are applied, then it is checked against the floor and by the rate
+    /// guard. Return `None` if the cycle is skipped, its reason being recorded.
+    async fn price_offer(
+        &self,
+        quote: (u8, f64),
+        min_rate: f64,
+        loan_amount: f64,
+        utilization: Option<f64>,
+        active_offer: Option<&FundingOffer>,
+        now: u64,
+    ) -> Result<Option<PricedOffer>> {
+        let (mut period, mut rate) = quote;
+        let market_rate = rate;
+
+        let rate_ema_key = self.rate_ema_key();
+        let mut rate_emas: RateEmas = self.state.get(&rate_ema_key).unwrap_or_default();
+        rate = premium_rate(
+            period,
+            rate,
+            self.rate_ema_alpha
+                .map(|rate_ema_alpha| (&mut rate_emas, rate_ema_alpha)),
+            &self.period_premium,
+            self.rate_display,
+            &self.log_target(),
+        );
+        if self.rate_ema_alpha.is_some() {
+            self.state.set(&rate_ema_key, &rate_emas)?;
+        }
+
+        // Hold out for higher rates when the demand is high, undercut when it is low.
+        if let (Some(utilization_table), Some(utilization)) = (&self.utilization_table, utilization)
+        {
+            let multiplier = utilization_table.multiplier(utilization);
+            log::info!(target: &self.log_target(), "Utilization rate multiplier: x{multiplier:.4}");
+            rate *= multiplier;
+        }
+
+        // Demand more (or less) depending on the hour of the day.
+        let hour = common::utc_hour(now);
+        if let Some(multiplier) = self.hourly_premium.get(&hour) {
+            rate *= multiplier;
+            log::info!(
+                target: &self.log_target(),
+                "Hourly premium x{multiplier} applied at {hour}h UTC: {} per day",
+                self.rate_display.rate(rate)
+            );
+        }
+
+        // Follow the weekly cycle of the demand.
+        if !self.seasonality.is_empty() {
+            let weekday = Weekday::from_unix_time(now);
+            let day = self.seasonality.day(weekday);
+            rate = self.seasonality.apply(weekday, rate);
+            log::info!(
+                target: &self.log_target(),
+                "{weekday:?} seasonality x{}{} applied: {} per day",
+                day.multiplier,
+                day.min_rate
+                    .map(|min_rate| format!(" (min {})", self.rate_display.rate(min_rate)))
+                    .unwrap_or_default(),
+                self.rate_display.rate(rate)
+            );
+        }
+
+        log::debug!(
+            target: &self.log_target(),
+            "Target: {period} days @ {rate} (min rate: {min_rate})"
+        );
+
+        // Sit out the markets that are barely above the floor.
+        if below_min_rate_margin(rate, min_rate, self.min_rate_margin) {
+            log::info!(
+                target: &self.log_target(),
+                "Rate {} per day is not {:.0}% above the min rate {}, skipping",
+                self.rate_display.rate(rate),
+                self.min_rate_margin * 100.,
+                self.rate_display.rate(min_rate)
+            );
+            self.skips.record(self.name(), SkipReason::RateTooLow);
+            return Ok(None);
+        }
+
+        // Get more aggressive the longer the offer sits unfilled. The placement time is carried
+        // over when the offer is replaced, and reset once no offer is open (i.e. it was filled).
+        let placed_at = match active_offer {
+            Some(active_offer) => self
+                .state
+                .get(&self.placed_at_key())
+                .unwrap_or(active_offer.mts_create / 1000),
+            None => now,
+        };
+
+        // Reprice from the market an offer unfilled for too long, e.g. placed during a spike,
+        // whatever holds it otherwise, at most once every `stale_after_secs`.
+        let stale = match (self.stale_after_secs, active_offer) {
+            (Some(stale_after_secs), Some(_)) => {
+                let since = self
+                    .state
+                    .get::<u64>(&self.stale_reprice_key())
+                    .map_or(placed_at, |stale_reprice| stale_reprice.max(placed_at));
+                now.saturating_sub(since) > stale_after_secs
+            }
+            _ => false,
+        };
+        if stale {
+            let discounted_rate = (rate * (1. - self.stale_discount)).max(min_rate);
+            log::info!(
+                target: &self.log_target(),
+                "Offer stale, repriced from the market at {} per day ({:.0}% discount)",
+                self.rate_display.rate(discounted_rate),
+                self.stale_discount * 100.
+            );
+            rate = discounted_rate;
+        }
+        let held_offer = active_offer.filter(|_| !stale);
+
+        // Follow the market up right away, but only follow it down once the trend confirms it.
+        if let (Some(momentum_filter), Some(active_offer)) = (&self.momentum_filter, held_offer) {
+            if rate < active_offer.rate
+                && !momentum_filter.confirms_down(&self.candle_highs(period).await?)
+            {
+                log::info!(
+                    target: &self.log_target(),
+                    "Downtrend not confirmed by the last {} candles, holding the offer rate \
+                     {} per day above the market {}",
+                    momentum_filter.k,
+                    self.rate_display.rate(active_offer.rate),
+                    self.rate_display.rate(rate)
+                );
+                rate = active_offer.rate;
+            }
+        }
+
+        // Follow the market up, but only follow it down once it faded far and long enough.
+        if let (Some(trailing), Some(active_offer)) = (&self.trailing, held_offer) {
+            let last_down = *self.last_down_reprice.lock().unwrap();
+            let trailing_rate = trailing.rate(active_offer.rate, rate, now, last_down);
+
+            if trailing_rate != rate {
+                log::info!(
+                    target: &self.log_target(),
+                    "Holding the offer rate {} per day above the market {} (last downward \
+                     reprice: {:?}s ago)",
+                    self.rate_display.rate(active_offer.rate),
+                    self.rate_display.rate(rate),
+                    last_down.map(|last_down| now.saturating_sub(last_down))
+                );
+            }
+            rate = trailing_rate;
+        }
+
+        if let Some(fill_sla) = &self.fill_sla {
+            let age_secs = now.saturating_sub(placed_at);
+            let sla_rate = fill_sla.rate(rate, age_secs, min_rate);
+
+            if sla_rate < rate {
+                log::info!(
+                    target: &self.log_target(),
+                    "Offer unfilled for {age_secs}s, repriced from {} to {} per day",
+                    self.rate_display.rate(rate),
+                    self.rate_display.rate(sla_rate)
+                );
+                rate = sla_rate;
+            }
+        }
+
+        // Do not undercut a recent fill by much because of a single low candle.
+        if let Some(high_water_mark) = &self.high_water_mark {
+            let floor = self
+                .state
+                .get::<Fill>(&self.fill_key())
+                .and_then(|fill| high_water_mark.floor(fill, now));
+
+            if let Some(floor) = floor.filter(|floor| rate < *floor) {
+                log::info!(
+                    target: &self.log_target(),
+                    "Rate raised from {} to the high-water mark {} per day",
+                    self.rate_display.rate(rate),
+                    self.rate_display.rate(floor)
+                );
+                rate = floor;
+            }
+        }
+
+        let quantized_rate = self.precision.rate(rate);
+        if (quantized_rate - rate).abs() > rate * 1e-4 {
+            log::info!(
+                target: &self.log_target(),
+                "Rate quantized to {} decimals: {rate} -> {quantized_rate}",
+                self.precision.rate_decimals
+            );
+        }
+        rate = quantized_rate;
+
+        // Check the floor one last time, whatever adjusted the rate since the market quote.
+        let floor = Floor {
+            min_rate,
+            target_period: self.target_period,
+            period_fallback: self.period_fallback,
+            floor_action: self.floor_action,
+        };
+        let floor_check = floor.check(period, rate);
+        if floor_check != FloorCheck::Clear {
+            log::info!(
+                target: &self.log_target(),
+                "Rate {} per day for {period} days is below the min rate {}, the market being at \
+                 {}: the floor blocks the offer, {}",
+                self.rate_display.rate(rate),
+                self.rate_display.rate(min_rate),
+                self.rate_display.rate(market_rate),
+                match floor_check {
+                    FloorCheck::Park { .. } => "parking the funds at the min rate",
+                    _ => "skipping",
+                }
+            );
+        }
+        match floor_check {
+            FloorCheck::Clear => {}
+            FloorCheck::Idle => {
+                self.skips.record(self.name(), SkipReason::RateTooLow);
+                return Ok(None);
+            }
+            FloorCheck::Park {
+                period: park_period,
+                rate: park_rate,
+            } => {
+                period = park_period;
+                rate = self.precision.rate(park_rate);
+            }
+        }
+
+        let Some(params) =
+            self.rate_guard
+                .offer_params(loan_amount, rate, period, &self.log_target())
+        else {
+            if let Some(rate_candle_mts) = *self.rate_candle_mts.lock().unwrap() {
+                log::error!(
+                    target: &self.log_target(),
+                    "Rate {rate} derived from the candles up to {rate_candle_mts}"
+                );
+            }
+            self.skips.record(self.name(), SkipReason::InsaneRate);
+            return Ok(None);
+        };
+
+        Ok(Some(PricedOffer {
+            params,
+            placed_at,
+            stale,
+        }))
+    }
+
+    /// Return the target period and the depth-weighted rate at which `amount` would be absorbed
+    /// by the bids of the funding book, whatever their period.
+    async fn book_quote(&self, amount: f64) -> Result<Option<(u8, f64)>> {
+        let funding_book = common::funding_book(self.client()?, &self.symbol).await?;
+
+        let Some(absorption) = book::absorption_rate(&funding_book.bids, amount) else {
+            log::info!(target: &self.log_target(), "No bid on the {} funding book", self.symbol);
+            return Ok(None);
+        };
+
+        if absorption.thin {
+            log::warn!(
+                target: &self.log_target(),
+                "The {} bids are thinner than {amount:.2}, using the worst level @ {} per day",
+                self.symbol,
+                self.rate_display.rate(absorption.rate)
+            );
+        } else {
+            log::info!(
+                target: &self.log_target(),
+                "Book depth rate for {amount:.2}: {} per day",
+                self.rate_display.rate(absorption.rate)
+            );
+        }
+
+        Ok(Some((self.target_period, absorption.rate)))
+    }
+
+    /// Return the FRR plus `frr_offset` if the FRR mode is enabled, or `None` to fall back to the
+    /// candles if it is disabled or the FRR is unavailable. Exceeding the request cap is an error.
+    async fn frr_rate(&self) -> Result<Option<f64>> {
+        let Some(frr_offset) = self.frr_offset else {
+            return Ok(None);
+        };
+
+        Ok(match common::frr(self.client()?, &self.symbol).await {
+            Result::Ok(frr) if frr > 0. => {
+                log::info!(
+                    target: &self.log_target(),
+                    "FRR: {} per day, offset: {} per day",
+                    self.rate_display.rate(frr),
+                    self.rate_display.offset(frr_offset)
+                );
+                Some(frr + frr_offset)
+            }
+            Result::Ok(_) => {
+                log::warn!(
+                    target: &self.log_target(),
+                    "No FRR available, falling back to the candles"
+                );
+                None
+            }
+            Err(e) => {
+                log::warn!(
+                    target: &self.log_target(),
+                    "Failed to fetch the FRR, falling back to the candles: {e}"
+                );
+                None
+            }
+        })
+    }
+
+    /// Floor `candle_rate` to the FRR times `frr_factor` if the FRR floor is enabled, so that the
+    /// offers are never below the market benchmark. Exceeding the request cap is an error.
+    async fn apply_frr_floor(&self, candle_rate: f64) -> Result<f64> {
+        let Some(frr_factor) = self.frr_factor else {
+            return Ok(candle_rate);
+        };
+
+        let frr = match common::frr(self.client()?, &self.symbol).await {
+            Result::Ok(frr) => frr,
+            Err(e) => {
+                log::warn!(
+                    target: &self.log_target(),
+                    "Failed to fetch the FRR, the candle rate is not floored: {e}"
+                );
+                return Ok(candle_rate);
+            }
+        };
+
+        let frr_rate = frr * frr_factor;
+        log::info!(
+            target: &self.log_target(),
+            "Candle rate: {} per day, FRR floor: {} per day ({} x {frr_factor}), {} wins",
+            self.rate_display.rate(candle_rate),
+            self.rate_display.rate(frr_rate),
+            self.rate_display.rate(frr),
+            if frr_rate > candle_rate {
+                "FRR"
+            } else {
+                "candles"
+            }
+        );
+
+        Ok(candle_rate.max(frr_rate))
+    }
+
+    /// Fetch the funding utilization if the rate or the size depend on it, or `None` if they do
+    /// not or the funding stats are unavailable. Exceeding the request cap is an error.
+    async fn funding_utilization(&self) -> Result<Option<f64>> {
+        if self.utilization_table.is_none() && self.utilization_sizing.is_none() {
+            return Ok(None);
+        }
+
+        Ok(
+            match common::funding_utilization(self.client()?, &self.symbol).await {
+                Result::Ok(utilization) => {
+                    log::info!(
+                        target: &self.log_target(),
+                        "Funding utilization: {:.2}%",
+                        utilization * 100.
+                    );
+                    Some(utilization)
+                }
+                Err(e) => {
+                    log::warn!(
+                        target: &self.log_target(),
+                        "Failed to fetch the funding stats, ignoring the utilization: {e}"
+                    );
+                    None
+                }
+            },
+        )
+    }
+
+    /// Query the rate of each candidate period and return the period with the best expected APR
+    /// among those above `min_rate`.
+    async fn optimize_period(
+        &self,
+        period_optimizer: &PeriodOptimizer,
+        min_rate: f64,
+    ) -> Result<Option<(u8, f64)>> {
+        let mut quotes = Vec::new();
+
+        for candidate in &period_optimizer.candidates {
+            let Some(rate) = self.get_rate(candidate.period).await? else {
+                log::info!(
+                    target: &self.log_target(),
+                    "Not enough candles for {} days, skipping",
+                    candidate.period
+                );
+                continue;
+            };
+
+            let quote = PeriodQuote {
+                period: candidate.period,
+                rate,
+                premium: candidate.premium,
+            };
+
+            log::info!(
+                target: &self.log_target(),
+                "{} days: {} APR, {} APR adjusted for the {:.0}% premium",
+                quote.period,
+                self.rate_display.apr(quote.apr()),
+                self.rate_display.apr(quote.adjusted_apr()),
+                quote.premium * 100.
+            );
+
+            if rate < min_rate {
+                log::info!(
+                    target: &self.log_target(),
+                    "{} days rate is below the minimum rate",
+                    quote.period
+                );
+                continue;
+            }
+
+            quotes.push(quote);
+        }
+
+        let best = period_optimizer::best_period(&quotes, period_optimizer.criterion);
+        match best {
+            Some(best) => log::info!(
+                target: &self.log_target(),
+                "Chosen period: {} days @ {} APR, best {} among {} candidates",
+                best.period,
+                self.rate_display.apr(best.apr()),
+                period_optimizer.criterion,
+                quotes.len()
+            ),
+            None => log::info!(
+                target: &self.log_target(),
+                "No candidate period above the minimum rate"
+            ),
+        }
+
+        Ok(best.map(|best| (best.period, best.rate)))
+    }
+
+    /// Key of the time the tracked offer was first placed, for the fill SLA and the stale offers.
+    fn placed_at_key(&self) -> String {
+        format!("fill_sla.{}.{}", self.name, self.currency)
+    }
+
+    /// Key of the time the tracked offer was last repriced for being stale.
+    fn stale_reprice_key(&self) -> String {
+        format!("stale_reprice.{}.{}", self.name, self.currency)
+    }
+
+    /// Key of whether the active offer was turned visible after `visible_after_secs`.
+    fn visible_key(&self) -> String {
+        format!("visible.{}.{}", self.name, self.currency)
+    }
+
+    /// Return whether the active offer was turned visible after `visible_after_secs`.
+    fn visible(&self) -> bool {
+        self.state.get(&self.visible_key()).unwrap_or(false)
+    }
+
+    /// Key of the moving averages of the target rate, by period.
+    fn rate_ema_key(&self) -> String {
+        format!("rate_ema.{}.{}", self.name, self.currency)
+    }
+
+    /// Key of the last fill, for the high-water mark.
+    fn fill_key(&self) -> String {
+        format!("high_water_mark.{}.{}", self.name, self.currency)
+    }
+
+    /// Fetch the active credits of the strategy symbol.
+    async fn credits(&self) -> Result<Vec<Credit>> {
+        let active_credits = common::active_credits(self.client()?, &self.symbol).await?;
+
+        Ok(active_credits
+            .iter()
+            .map(|credit| Credit {
+                id: credit.id,
+                amount: credit.amount,
+                rate: credit.rate,
+                period: credit.period,
+                mts_opening: credit.mts_opening,
+            })
+            .collect())
+    }
+
+    /// Log what the active credits lend and how the `balance` splits between them, the
+    /// `offered` amount and the idle funds, and return the credits.
+    async fn log_credits(&self, balance: f64, offered: f64) -> Result<Vec<Credit>> {
+        let credits = self.credits().await?;
+        let summary = CreditsSummary::new(&credits, common::unix_time()? * 1000);
+        let (lent, offered, idle) = summary.deployment(balance, offered);
+
+        log::info!(
+            target: &self.log_target(),
+            "Credits: {:.2} {} lent in {} credits @ {} per day ({} APR) on average, returned in \
+             {:.1} days on average",
+            summary.principal,
+            self.currency,
+            summary.count,
+            self.rate_display.rate(summary.average_rate),
+            self.rate_display.apr(summary.average_rate * 365.),
+            summary.average_remaining_days
+        );
+        log::info!(
+            target: &self.log_target(),
+            "Balance: {:.2}% lent, {:.2}% offered, {:.2}% idle",
+            lent * 100.,
+            offered * 100.,
+            idle * 100.
+        );
+
+        Ok(credits)
+    }
+
+    /// Key of the capital usage.
+    fn capital_usage_key(&self) -> String {
+        format!("capital_usage.{}.{}", self.name, self.currency)
+    }
+
+    /// Key of the IDs of the credits lending the offers of the strategy.
+    fn owned_credits_key(&self) -> String {
+        format!("owned_credits.{}.{}", self.name, self.currency)
+    }
+
+    /// Record the amount `offered` by the strategy and the amount it lends in the active
+    /// `credits` for the capital usage, if tracked, and log it hourly.
+    fn track_capital_usage(&self, offered: f64, credits: &[Credit]) -> Result<()> {
+        let Some(window_secs) = self.capital_usage_window_secs else {
+            return Ok(());
+        };
+
+        // Only the credits lending the offers of the strategy, not those of the other strategies
+        // on the currency nor the manual ones.
+        let mut owned_credits: HashSet<u64> = self
+            .state
+            .get(&self.owned_credits_key())
+            .unwrap_or_default();
+        let lent = owned_principal(
+            credits,
+            &mut owned_credits,
+            self.state.submission(&self.name, &self.currency).as_ref(),
+        );
+        self.state.set(&self.owned_credits_key(), &owned_credits)?;
+
+        let now = common::unix_time()?;
+        let key = self.capital_usage_key();
+        let mut capital_usage = CapitalUsage::restore(self.state.get(&key), window_secs);
+        capital_usage.record(now, offered, lent);
+
+        if let Some(utilization) = capital_usage.utilization() {
+            if capital_usage.log_due(now) {
+                log::info!(
+                    target: &self.log_target(),
+                    "Capital utilization: {:.2}% of the capital offered or lent was lent",
+                    utilization * 100.
+                );
+            }
+        }
+
+        self.state.set(&key, &capital_usage)
+    }
+
+    /// Return whether the best bid of the funding book is no more than `max_borrow_spread` below
+    /// `rate`, relatively, logging the observed spread.
+    async fn is_spread_healthy(&self, rate: f64, max_borrow_spread: f64) -> Result<bool> {
+        let funding_book = common::funding_book(self.client()?, &self.symbol).await?;
+
+        let Some(spread) = book::borrow_spread(&funding_book, rate) else {
+            log::info!(
+                target: &self.log_target(),
+                "No bid on the {} funding book, skipping",
+                self.symbol
+            );
+            return Ok(false);
+        };
+
+        log::info!(
+            target: &self.log_target(),
+            "Best bid {} per day, {:.2}% below the rate {}",
+            self.rate_display.rate(funding_book.bids[0].rate),
+            spread * 100.,
+            self.rate_display.rate(rate)
+        );
+        if spread > max_borrow_spread {
+            log::info!(
+                target: &self.log_target(),
+                "Spread above the max {:.2}%, skipping",
+                max_borrow_spread * 100.
+            );
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// Close the credits about to be returned whose rate is well below the achievable `rate`, the
+    /// returned funds being lent again by the normal lending flow.
+    async fn close_credits(&self, close_credits: &CloseCredits, rate: f64) -> Result<()> {
+        let now = common::unix_time()? * 1000;
+
+        for credit in self.credits().await? {
+            if !close_credits.should_close(&credit, rate, now) {
+                log::debug!(
+                    target: &self.log_target(),
+                    "Keeping the credit {}: {:.2} lent @ {} per day, the market pays {} per day",
+                    credit.id,
+                    credit.amount,
+                    self.rate_display.rate(credit.rate),
+                    self.rate_display.rate(rate)
+                );
+                continue;
+            }
+
+            log::info!(
+                target: &self.log_target(),
+                "Closing the credit {}: {:.2} lent @ {} per day, the market now pays {} per day",
+                credit.id,
+                credit.amount,
+                self.rate_display.rate(credit.rate),
+                self.rate_display.rate(rate)
+            );
+            // A credit failing to close, e.g. already returned, must not keep the others open.
+            let closed = async {
+                ignore(FundingClose::builder().id(credit.id).build()?)
+                    .query_async(self.client()?)
+                    .await?;
+                Ok(())
+            };
+            if let Err(e) = closed.await {
+                log::warn!(
+                    target: &self.log_target(),
+                    "Failed to close the credit {}: {e:#}",
+                    credit.id
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remember the rate of the submitted offer if it disappeared since the last cycle because
+    /// it was filled, i.e. a credit now lends its terms.
+    async fn track_fill(&self, submission: &Submission, now: u64) -> Result<()> {
+        if !self
+            .credits()
+            .await?
+            .iter()
+            .any(|credit| lends_submission(credit, submission))
+        {
+            return Ok(());
+        }
+
+        log::info!(
+            target: &self.log_target(),
+            "Offer filled @ {} per day, new offers are floored for the next {}s",
+            self.rate_display.rate(submission.rate),
+            self.high_water_mark
+                .as_ref()
+                .map_or(0, |high_water_mark| high_water_mark.window_secs)
+        );
+
+        self.state.set(
+            &self.fill_key(),
+            &Fill {
+                timestamp: now,
+                rate: submission.rate,
+            },
+        )
+    }
+
+    /// Keep the funding auto-renew in line with the strategy floor (the configured `min_rate` for
+    /// the target period), or disabled, so that it takes over sensibly when the bot is down.
+    ///
+    /// The settings are compared against the current ones of the exchange, so that a change made
+    /// elsewhere (e.g. on the website) is reverted, and only written when they differ.
+    async fn sync_auto_renew(&self) -> Result<()> {
+        let Some(enabled) = self.auto_renew else {
+            return Ok(());
+        };
+
+        let desired = enabled.then_some((self.precision.rate(self.min_rate), self.target_period));
+        let current = common::auto_renew(self.client()?, &self.currency).await?;
+        let describe = |settings: Option<(f64, u8)>| match settings {
+            Some((rate, period)) => {
+                format!("{period} days @ {} per day", self.rate_display.rate(rate))
+            }
+            None => "disabled".to_string(),
+        };
+
+        if auto_renew_matches(current, desired) {
+            log::debug!(
+                target: &self.log_target(),
+                "Funding auto-renew is up to date: {}",
+                describe(current)
+            );
+            return Ok(());
+        }
+
+        common::set_auto_renew(self.client()?, &self.currency, desired).await?;
+
+        log::info!(
+            target: &self.log_target(),
+            "Funding auto-renew changed from {} to {}",
+            describe(current),
+            describe(desired)
+        );
+
+        Ok(())
+    }
+
+    /// Return the minimum rate, derived from the historical candles if `dynamic_min_rate` is set.
+    async fn min_rate(&self) -> Result<f64> {
+        let Some(dynamic_min_rate) = &self.dynamic_min_rate else {
+            return Ok(self.min_rate);
+        };
+
+        let candles = common::candles(
+            self.client()?,
+            &self.symbol,
+            dynamic_min_rate.lookback,
+            self.target_period,
+            self.candle_timeframe,
+            CandleSection::Hist,
+        )
+        .await?;
+        let closes: Vec<f64> = candles.iter().map(|candle| candle.close).collect();
+
+        let min_rate = indicators::percentile(&closes, dynamic_min_rate.percentile)
+            .ok_or(anyhow!("No candles to derive the minimum rate from"))?;
+
+        log::info!(
+            target: &self.log_target(),
+            "Derived minimum rate: {} per day ({}th percentile of the closes over {}h)",
+            self.rate_display.rate(min_rate),
+            dynamic_min_rate.percentile,
+            dynamic_min_rate.lookback
+        );
+
+        Ok(min_rate)
+    }
+}
+
+impl fmt::Display for SimpleStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {}: {} days offers of at least {} (max {:.0}% of the balance) @ min {} per day, \
+             {} candle over {}h",
+            self.name(),
+            self.currency,
+            self.target_period,
+            match (self.min_amount_usd, self.min_amount) {
+                (Some(min_amount_usd), _) => format!("{min_amount_usd:.2} USD"),
+                (None, min_amount) => format!("{:.2}", min_amount.unwrap_or_default()),
+            },
+            self.max_balance_percent_per_loan * 100.,
+            self.rate_display.rate(self.min_rate),
+            self.rate_selector,
+            self.monitored_window
+        )?;
+
+        if let Some(period_optimizer) = &self.period_optimizer {
+            write!(f, ", period: {period_optimizer}")?;
+        }
+
+        if let Some(utilization_table) = &self.utilization_table {
+            write!(f, ", utilization: {utilization_table}")?;
+        }
+
+        if let Some(utilization_sizing) = &self.utilization_sizing {
+            write!(f, ", sizing: {utilization_sizing}")?;
+        }
+
+        if !self.seasonality.is_empty() {
+            write!(f, ", seasonality: {}", self.seasonality)?;
+        }
+
+        if let Some(fallback) = &self.fallback {
+            write!(f, ", falling back to {}", fallback.currency)?;
+        }
+
+        if let Some(daily_budget) = &self.daily_budget {
+            write!(f, ", daily budget: {daily_budget}")?;
+        }
+
+        if !self.period_premium.is_empty() {
+            let mut premiums: Vec<(&u8, &PeriodPremium)> = self.period_premium.iter().collect();
+            premiums.sort_by_key(|(period, _)| **period);
+            let premiums: Vec<String> = premiums
+                .into_iter()
+                .map(|(period, premium)| format!("{period}d: {premium}"))
+                .collect();
+            write!(f, ", period premiums: {}", premiums.join(", "))?;
+        }
+
+        if !self.blended_windows.is_empty() {
+            let windows: Vec<String> = self
+                .blended_windows
+                .iter()
+                .map(|(window, weight)| format!("{window}h: x{weight}"))
+                .collect();
+            write!(f, ", blending {}", windows.join(", "))?;
+        }
+
+        if let Some(high_water_mark) = &self.high_water_mark {
+            write!(
+                f,
+                ", {:.0}% of the last fill for {}s",
+                high_water_mark.floor_factor * 100.,
+                high_water_mark.window_secs
+            )?;
+        }
+
+        if let Some(momentum_filter) = &self.momentum_filter {
+            write!(
+                f,
+                ", down on a {:.0}% momentum over {} candles",
+                momentum_filter.margin * 100.,
+                momentum_filter.k
+            )?;
+        }
+
+        if let Some(trailing) = &self.trailing {
+            write!(
+                f,
+                ", trailing down after {:.0}% and {}s",
+                trailing.down_drift * 100.,
+                trailing.down_hold_secs
+            )?;
+        }
+
+        match self.auto_renew {
+            Some(true) => write!(f, ", auto-renew at the floor")?,
+            Some(false) => write!(f, ", auto-renew disabled")?,
+            None => {}
+        }
+
+        if let Some(reference_currency) = &self.reference_currency {
+            write!(
+                f,
+                ", priced off f{reference_currency} {:+} bps",
+                self.spread_bps
+            )?;
+        }
+
+        fmt::Result::Ok(())
+    }
+}
+
+impl FromConfig for SimpleStrategy {
+    fn from_config(files: &ConfigFiles, shared: &Shared) -> Result<Vec<Self>> {
+        #[derive(Debug, Clone, Deserialize)]
+        struct Strategy {
+            keys: String,
+            currency: String,
+            /// Funding symbol, defaults to `f{currency}`.
+            #[serde(default, alias = "funding_symbol")]
+            symbol: Option<String>,
+            min_amount: Option<f64>,
+            max_balance_percent_per_loan: f64,
+            min_rate: f64,
+            target_period: u8,
+            monitored_window: u64,
+            nth_highest_candle: Option<usize>,
+            rate_selector: Option<RateSelector>,
+            dynamic_min_rate: Option<DynamicMinRate>,
+            #[serde(default = "rate_guard::default_max_rate")]
+            max_rate: f64,
+            #[serde(default = "rate_guard::default_sanity_max_apr")]
+            sanity_max_apr: f64,
+            volatility_premium: Option<VolatilityPremium>,
+            #[serde(alias = "auto_period")]
+            period_optimizer: Option<PeriodOptimizer>,
+            #[serde(default)]
+            hourly_premium: HashMap<u8, f64>,
+            #[serde(default)]
+            candle_timeframe: CandleTimeFrame,
+            frr_offset: Option<f64>,
+            utilization_table: Option<UtilizationTable>,
+            #[serde(default)]
+            min_rate_margin: f64,
+            frr_factor: Option<f64>,
+            reference_currency: Option<String>,
+            #[serde(default)]
+            spread_bps: f64,
+            max_offer_age_secs: Option<u64>,
+            auto_renew: Option<bool>,
+            #[serde(default = "common::default_enabled")]
+            enabled: bool,
+            fill_sla: Option<FillSla>,
+            utilization_sizing: Option<SizingTable>,
+            trailing: Option<Trailing>,
+            #[serde(default)]
+            seasonality: Seasonality,
+            momentum_filter: Option<MomentumFilter>,
+            high_water_mark: Option<HighWaterMark>,
+            #[serde(default)]
+            blended_windows: BTreeMap<u64, f64>,
+            fallback_currency: Option<FallbackCurrency>,
+            #[serde(default)]
+            period_premium: HashMap<u8, PeriodPremium>,
+            min_amount_usd: Option<f64>,
+            daily_budget: Option<f64>,
+            min_candle_volume: Option<f64>,
+            #[serde(default = "rate_display::default_rate_decimals")]
+            rate_display_decimals: usize,
+            #[serde(default = "rate_display::default_apr_decimals")]
+            apr_display_decimals: usize,
+            #[serde(default)]
+            replacement: Replacement,
+            #[serde(default = "common::default_hidden")]
+            hidden: bool,
+            close_credits: Option<CloseCredits>,
+            max_borrow_spread: Option<f64>,
+            #[serde(default)]
+            partial_fill: PartialFill,
+            #[serde(default = "default_period_fallback")]
+            period_fallback: Option<PeriodFallback>,
+            #[serde(default)]
+            floor_action: FloorAction,
+            /// Only periods the strategy may lend for, all of them if not set.
+            allowed_periods: Option<Vec<u8>>,
+            #[serde(default)]
+            blocked_periods: Vec<u8>,
+            depth_check: Option<DepthCheck>,
+            visible_after_secs: Option<u64>,
+            stale_after_secs: Option<u64>,
+            #[serde(default)]
+            stale_discount: f64,
+            max_offer_size: Option<f64>,
+            /// Priority of the strategy for the active offer slots of the account.
+            #[serde(default)]
+            offer_priority: u8,
+            #[serde(default)]
+            candle_section: CandleSection,
+            #[serde(default)]
+            replace_order: ReplaceOrder,
+            min_projected_interest: Option<f64>,
+            rate_ema_alpha: Option<f64>,
+            max_requests_per_cycle: Option<usize>,
+            capital_usage_window_days: Option<u64>,
+        }
+
+        fn build(
+            name: String,
+            strategy: Strategy,
+            rate_selector: RateSelector,
+            fallback: Option<Box<SimpleStrategy>>,
+            shared: &Shared,
+        ) -> Result<SimpleStrategy> {
+            let account_budget = shared.budget.account(&strategy.keys, &strategy.currency);
+            let offer_slots = shared.offer_limit.account(
+                &strategy.keys,
+                &format!("{name}.{}", strategy.currency),
+                strategy.offer_priority,
+            );
+            let precision = shared.precisions.get(&strategy.currency);
+            let symbol = common::funding_symbol(&strategy.currency, strategy.symbol);
+            let rate_display = RateDisplay::new(
+                strategy.rate_display_decimals,
+                strategy.apr_display_decimals,
+            );
+            let rate_guard = RateGuard::new(
+                strategy.max_rate,
+                strategy.sanity_max_apr,
+                rate_display,
+                shared.notifier.clone(),
+            );
+            rate_guard
+                .validate()
+                .with_context(|| format!("{name}: invalid rate ceilings"))?;
+            let daily_budget = strategy.daily_budget.map(|daily_budget| {
+                DailyBudget::new(
+                    daily_budget,
+                    &name,
+                    &strategy.currency,
+                    shared.state.clone(),
+                )
+            });
+
+            Ok(SimpleStrategy::new(
+                name,
+                common::client_from_keys(&strategy.keys)?,
+                strategy.currency,
+                symbol,
+                strategy.min_amount,
+                strategy.max_balance_percent_per_loan,
+                strategy.min_rate,
+                strategy.target_period,
+                strategy.monitored_window,
+                rate_selector,
+                strategy.dynamic_min_rate,
+                rate_guard,
+                strategy.volatility_premium,
+                strategy.period_optimizer,
+                strategy.hourly_premium,
+                strategy.candle_timeframe,
+                strategy.frr_offset,
+                strategy.utilization_table,
+                strategy.min_rate_margin,
+                strategy.frr_factor,
+                strategy.reference_currency,
+                strategy.spread_bps,
+                shared.state.clone(),
+                strategy.max_offer_age_secs,
+                shared.price_feed.clone(),
+                strategy.auto_renew,
+                strategy.fill_sla,
+                strategy.utilization_sizing,
+                strategy.trailing,
+                strategy.seasonality,
+                strategy.momentum_filter,
+                strategy.high_water_mark,
+                shared.skips.clone(),
+                strategy.blended_windows,
+                fallback,
+                strategy.period_premium,
+                strategy.min_amount_usd,
+                daily_budget,
+                strategy.min_candle_volume,
+                precision,
+                rate_display,
+                strategy.replacement,
+                strategy.hidden,
+                strategy.close_credits,
+                strategy.max_borrow_spread,
+                strategy.partial_fill,
+                strategy.period_fallback,
+                strategy.floor_action,
+                PeriodFilter {
+                    allowed: strategy.allowed_periods,
+                    blocked: strategy.blocked_periods,
+                },
+                strategy.depth_check,
+                strategy.visible_after_secs,
+                strategy.stale_after_secs,
+                strategy.stale_discount,
+                strategy.max_offer_size,
+                offer_slots,
+                strategy.candle_section,
+                strategy.replace_order,
+                strategy.min_projected_interest,
+                strategy.rate_ema_alpha,
+                strategy.max_requests_per_cycle,
+                strategy.capital_usage_window_days.map(|days| days * 86400),
+                account_budget,
+            ))
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[serde(default)]
+            simple_strategies: HashMap<String, Strategy>,
+        }
+
+        let config: Config = config::read(files)?;
+
+        config
+            .simple_strategies
+            .into_iter()
+            .filter(|(name, strategy)| common::is_enabled(name, strategy.enabled))
+            .map(|(name, strategy)| {
+                ensure!(
+                    strategy.min_amount.is_some() || strategy.min_amount_usd.is_some(),
+                    "{name}: either min_amount or min_amount_usd is required"
+                );
+                ensure!(
+                    strategy
+                        .min_amount
+                        .map_or(true, |min_amount| min_amount > 0.),
+                    "{name}: min_amount must be positive"
+                );
+                ensure!(
+                    strategy
+                        .min_amount_usd
+                        .map_or(true, |min_amount_usd| min_amount_usd > 0.),
+                    "{name}: min_amount_usd must be positive"
+                );
+                ensure!(
+                    strategy.max_balance_percent_per_loan > 0.
+                        && strategy.max_balance_percent_per_loan <= 1.,
+                    "{name}: max_balance_percent_per_loan must be within (0, 1]"
+                );
+                ensure!(
+                    strategy.min_rate >= 0.,
+                    "{name}: min_rate must not be negative"
+                );
+                common::validate_period(strategy.target_period)
+                    .with_context(|| format!("{name}: invalid target_period"))?;
+                if let Some(period_fallback) = strategy.period_fallback {
+                    common::validate_period(period_fallback.period)
+                        .with_context(|| format!("{name}: invalid period_fallback.period"))?;
+                }
+                ensure!(
+                    strategy.monitored_window > 0,
+                    "{name}: monitored_window must be positive"
+                );
+
+                // `nth_highest_candle` is kept as a shorthand for the `nth_highest` selector.
+                let rate_selector = strategy
+                    .rate_selector
+                    .or(strategy.nth_highest_candle.map(RateSelector::NthHighest))
+                    .with_context(|| {
+                        format!("{name}: either rate_selector or nth_highest_candle is required")
+                    })?;
+                if rate_selector == RateSelector::BookDepth {
+                    // The other features needing a rate from the candles could not get any.
+                    ensure!(
+                        strategy.period_optimizer.is_none()
+                            && strategy.blended_windows.is_empty()
+                            && strategy.close_credits.is_none(),
+                        "{name}: the book_depth rate_selector does not support period_optimizer, \
+                         blended_windows nor close_credits"
+                    );
+                } else {
+                    rate_selector
+                        .validate()
+                        .with_context(|| format!("{name}: invalid rate_selector"))?;
+                }
+                validate_candle_section(
+                    strategy.candle_section,
+                    rate_selector,
+                    strategy.momentum_filter.is_some(),
+                    strategy.volatility_premium.is_some(),
+                )
+                .with_context(|| format!("{name}: invalid candle_section"))?;
+
+                for (hour, multiplier) in &strategy.hourly_premium {
+                    ensure!(
+                        *hour < 24,
+                        "{name}: hourly_premium hour {hour} must be within [0, 23]"
+                    );
+                    ensure!(
+                        (MIN_HOURLY_PREMIUM..=MAX_HOURLY_PREMIUM).contains(multiplier),
+                        "{name}: hourly_premium multiplier of {hour}h must be within \
+                         [{MIN_HOURLY_PREMIUM}, {MAX_HOURLY_PREMIUM}]"
+                    );
+                }
+
+                for (period, period_premium) in &strategy.period_premium {
+                    common::validate_period(*period)
+                        .with_context(|| format!("{name}: invalid period_premium period"))?;
+                    if let PeriodPremium::Multiplier(multiplier) = period_premium {
+                        ensure!(
+                            *multiplier > 0.,
+                            "{name}: period_premium multiplier of {period} days must be positive"
+                        );
+                    }
+                }
+
+                strategy
+                    .seasonality
+                    .validate()
+                    .with_context(|| format!("{name}: invalid seasonality"))?;
+
+                if let Some(period_optimizer) = &strategy.period_optimizer {
+                    period_optimizer
+                        .validate()
+                        .with_context(|| format!("{name}: invalid period_optimizer"))?;
+                }
+
+                ensure!(
+                    strategy
+                        .allowed_periods
+                        .as_ref()
+                        .map_or(true, |allowed_periods| !allowed_periods.is_empty()),
+                    "{name}: allowed_periods must not be empty"
+                );
+                let period_filter = PeriodFilter {
+                    allowed: strategy.allowed_periods.clone(),
+                    blocked: strategy.blocked_periods.clone(),
+                };
+                let periods = [strategy.target_period]
+                    .into_iter()
+                    .chain(
+                        strategy
+                            .period_fallback
+                            .map(|period_fallback| period_fallback.period),
+                    )
+                    .chain(
+                        strategy
+                            .period_optimizer
+                            .iter()
+                            .flat_map(|period_optimizer| &period_optimizer.candidates)
+                            .map(|candidate| candidate.period),
+                    );
+                for period in periods {
+                    ensure!(
+                        period_filter.allows(period),
+                        "{name}: period {period} is not allowed by allowed_periods/blocked_periods"
+                    );
+                }
+
+                if let Some(dynamic_min_rate) = &strategy.dynamic_min_rate {
+                    ensure!(
+                        dynamic_min_rate.percentile > 0. && dynamic_min_rate.percentile <= 100.,
+                        "{name}: dynamic_min_rate percentile must be within (0, 100]"
+                    );
+                    ensure!(
+                        dynamic_min_rate.lookback > 0,
+                        "{name}: dynamic_min_rate lookback must be positive"
+                    );
+                }
+
+                if let Some(volatility_premium) = &strategy.volatility_premium {
+                    ensure!(
+                        volatility_premium.k >= 0.,
+                        "{name}: volatility_premium k must not be negative"
+                    );
+                    ensure!(
+                        volatility_premium.max_premium >= 0.,
+                        "{name}: volatility_premium max_premium must not be negative"
+                    );
+                }
+
+                ensure!(
+                    strategy.spread_bps > -10_000.,
+                    "{name}: spread_bps must be above -10000"
+                );
+
+                if let Some(fill_sla) = &strategy.fill_sla {
+                    ensure!(
+                        fill_sla.step > 0. && fill_sla.step < 1.,
+                        "{name}: fill_sla step must be within (0, 1)"
+                    );
+                    ensure!(
+                        fill_sla.interval_secs > 0,
+                        "{name}: fill_sla interval_secs must be positive"
+                    );
+                }
+
+                if let Some(max_offer_age_secs) = strategy.max_offer_age_secs {
+                    ensure!(
+                        max_offer_age_secs > 0,
+                        "{name}: max_offer_age_secs must be positive"
+                    );
+                }
+
+                if let Some(frr_factor) = strategy.frr_factor {
+                    ensure!(frr_factor > 0., "{name}: frr_factor must be positive");
+                }
+                ensure!(
+                    strategy.min_rate_margin >= 0.,
+                    "{name}: min_rate_margin must not be negative"
+                );
+
+                if !strategy.blended_windows.is_empty() {
+                    ensure!(
+                        strategy
+                            .blended_windows
+                            .iter()
+                            .all(|(window, weight)| *window > 0 && *weight > 0.),
+                        "{name}: blended_windows must have positive windows and weights"
+                    );
+                    ensure!(
+                        (strategy.blended_windows.values().sum::<f64>() - 1.).abs() < 1e-6,
+                        "{name}: blended_windows weights must sum to 1"
+                    );
+                }
+
+                if let Some(min_candle_volume) = strategy.min_candle_volume {
+                    ensure!(
+                        min_candle_volume >= 0.,
+                        "{name}: min_candle_volume must not be negative"
+                    );
+                }
+
+                RateDisplay::new(
+                    strategy.rate_display_decimals,
+                    strategy.apr_display_decimals,
+                )
+                .validate()
+                .with_context(|| format!("{name}: invalid display decimals"))?;
+
+                ensure!(
+                    strategy.replacement.fill_horizon_hours > 0.,
+                    "{name}: replacement.fill_horizon_hours must be positive"
+                );
+                ensure!(
+                    strategy.replacement.min_gain >= 0.,
+                    "{name}: replacement.min_gain must not be negative"
+                );
+                ensure!(
+                    strategy.replacement.unfillable_drift > 0.,
+                    "{name}: replacement.unfillable_drift must be positive"
+                );
+
+                if let Some(max_offer_size) = strategy.max_offer_size {
+                    ensure!(
+                        max_offer_size > 0.,
+                        "{name}: max_offer_size must be positive"
+                    );
+                }
+
+                if let Some(capital_usage_window_days) = strategy.capital_usage_window_days {
+                    ensure!(
+                        capital_usage_window_days > 0,
+                        "{name}: capital_usage_window_days must be positive"
+                    );
+                }
+
+                if let Some(max_requests_per_cycle) = strategy.max_requests_per_cycle {
+                    ensure!(
+                        max_requests_per_cycle > 0,
+                        "{name}: max_requests_per_cycle must be positive"
+                    );
+                }
+
+                if let Some(rate_ema_alpha) = strategy.rate_ema_alpha {
+                    ensure!(
+                        rate_ema_alpha > 0. && rate_ema_alpha <= 1.,
+                        "{name}: rate_ema_alpha must be within (0, 1]"
+                    );
+                }
+
+                if let Some(min_projected_interest) = strategy.min_projected_interest {
+                    ensure!(
+                        min_projected_interest > 0.,
+                        "{name}: min_projected_interest must be positive"
+                    );
+                }
+
+                if let Some(stale_after_secs) = strategy.stale_after_secs {
+                    ensure!(
+                        stale_after_secs > 0,
+                        "{name}: stale_after_secs must be positive"
+                    );
+                }
+                ensure!(
+                    (0. ..1.).contains(&strategy.stale_discount),
+                    "{name}: stale_discount must be within [0, 1)"
+                );
+
+                if let Some(visible_after_secs) = strategy.visible_after_secs {
+                    ensure!(
+                        strategy.hidden,
+                        "{name}: visible_after_secs requires hidden offers"
+                    );
+                    ensure!(
+                        visible_after_secs > 0,
+                        "{name}: visible_after_secs must be positive"
+                    );
+                }
+
+                if let Some(depth_check) = &strategy.depth_check {
+                    ensure!(
+                        depth_check.rate_range >= 0. && depth_check.rate_range < 1.,
+                        "{name}: depth_check.rate_range must be within [0, 1)"
+                    );
+                    ensure!(
+                        depth_check.max_depth_fraction > 0.,
+                        "{name}: depth_check.max_depth_fraction must be positive"
+                    );
+                }
+
+                if let Some(max_borrow_spread) = strategy.max_borrow_spread {
+                    ensure!(
+                        max_borrow_spread >= 0.,
+                        "{name}: max_borrow_spread must not be negative"
+                    );
+                }
+
+                if let Some(close_credits) = &strategy.close_credits {
+                    close_credits
+                        .validate()
+                        .with_context(|| format!("{name}: invalid close_credits"))?;
+                }
+
+                if let Some(high_water_mark) = &strategy.high_water_mark {
+                    ensure!(
+                        high_water_mark.floor_factor > 0. && high_water_mark.floor_factor <= 1.,
+                        "{name}: high_water_mark.floor_factor must be within (0, 1]"
+                    );
+                }
+
+                if let Some(momentum_filter) = &strategy.momentum_filter {
+                    ensure!(
+                        momentum_filter.k > 0,
+                        "{name}: momentum_filter.k must be positive"
+                    );
+                    ensure!(
+                        momentum_filter.margin >= 0.,
+                        "{name}: momentum_filter.margin must not be negative"
+                    );
+                }
+
+                if let Some(trailing) = &strategy.trailing {
+                    ensure!(
+                        trailing.down_drift >= REPRICE_DRIFT,
+                        "{name}: trailing.down_drift must be at least {REPRICE_DRIFT}"
+                    );
+                }
+
+                if let Some(utilization_sizing) = &strategy.utilization_sizing {
+                    utilization_sizing
+                        .validate()
+                        .with_context(|| format!("{name}: invalid utilization_sizing"))?;
+                }
+
+                if let Some(utilization_table) = &strategy.utilization_table {
+                    utilization_table
+                        .validate()
+                        .with_context(|| format!("{name}: invalid utilization_table"))?;
+                }
+
+                let fallback = match &strategy.fallback_currency {
+                    Some(fallback_currency) => {
+                        ensure!(
+                            fallback_currency.currency != strategy.currency,
+                            "{name}: fallback_currency must differ from currency"
+                        );
+                        ensure!(
+                            fallback_currency.min_amount > 0.,
+                            "{name}: fallback_currency min_amount must be positive"
+                        );
+                        ensure!(
+                            fallback_currency.min_rate >= 0.,
+                            "{name}: fallback_currency min_rate must not be negative"
+                        );
+
+                        let fallback_strategy = Strategy {
+                            currency: fallback_currency.currency.clone(),
+                            symbol: None,
+                            min_amount: Some(fallback_currency.min_amount),
+                            min_amount_usd: None,
+                            min_rate: fallback_currency.min_rate,
+                            reference_currency: None,
+                            fallback_currency: None,
+                            ..strategy.clone()
+                        };
+                        let fallback =
+                            build(name.clone(), fallback_strategy, rate_selector, None, shared)?;
+
+                        Some(Box::new(fallback))
+                    }
+                    None => None,
+                };
+
+                build(name, strategy, rate_selector, fallback, shared)
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid simple_strategies in {files}"))
+    }
+}
+
+#[async_trait]
+impl PeriodRates for SimpleStrategy {
+    async fn rate(&self, period: u8) -> Result<Option<f64>> {
+        self.get_rate(period).await
+    }
+}
+
+#[async_trait]
+impl Strategy for SimpleStrategy {
+    type Output = Result<()>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn honors_offer_limit(&self) -> bool {
+        true
+    }
+
+    /// Adopt the persisted owned offers still active, and settle the action interrupted by the
+    /// restart if any: a canceled offer still active is managed again, and the offer of an
+    /// interrupted submission is adopted if it landed, its response having been lost.
+    async fn reconcile(&self) -> Result<()> {
+        *self.requests.lock().unwrap() = 0;
+        let active_offers = self.active_offers().await?;
+        let active_offer_ids: Vec<u64> = active_offers
+            .iter()
+            .map(|active_offer| active_offer.id)
+            .collect();
+
+        let mut owned_offers = self.owned_offers.ids();
+        reconcile_owned_offers(&mut owned_offers, &active_offer_ids, None);
+
+        if let Some(pending_action) = self.state.pending_action(&self.name, &self.currency) {
+            let active_offers: Vec<(u64, OfferTerms, u64)> = active_offers
+                .iter()
+                .map(|active_offer| {
+                    (
+                        active_offer.id,
+                        OfferTerms::of(active_offer),
+                        active_offer.mts_create,
+                    )
+                })
+                .collect();
+
+            match recover(
+                pending_action,
+                &active_offers,
+                &owned_offers,
+                self.precision,
+            ) {
+                Recovery::StillActive(offer_id) => {
+                    log::warn!(
+                        target: &self.log_target(),
+                        "Interrupted while canceling the offer {offer_id}, still active: \
+                         managing it again"
+                    );
+                    owned_offers.insert(offer_id);
+                }
+                Recovery::Gone(offer_id) => log::info!(
+                    target: &self.log_target(),
+                    "Interrupted while canceling the offer {offer_id}, canceled or filled since"
+                ),
+                Recovery::Landed(offer_id) => {
+                    log::warn!(
+                        target: &self.log_target(),
+                        "Interrupted while submitting an offer, adopting the offer {offer_id} \
+                         which landed"
+                    );
+                    owned_offers.insert(offer_id);
+                }
+                Recovery::NotLanded => {
+                    if self.filled_since(pending_action.timestamp).await? {
+                        log::info!(
+                            target: &self.log_target(),
+                            "Interrupted while submitting an offer, lent since"
+                        );
+                    } else {
+                        log::info!(
+                            target: &self.log_target(),
+                            "Interrupted while submitting an offer, which did not go through"
+                        );
+                    }
+                }
+            }
+            self.set_pending_action(None)?;
+        }
+
+        log::info!(
+            target: &self.log_target(),
+            "{} owned offers still active after the restart",
+            owned_offers.len()
+        );
+        self.owned_offers.set(owned_offers)?;
+
+        if let Some(fallback) = &self.fallback {
+            fallback.reconcile().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Cancel the offers submitted by the strategy, and by its fallback if any.
+    ///
+    /// The requests are not capped, not to leave owned offers on the book, and a failure to cancel
+    /// one does not stop the others.
+    async fn cancel_offers(&self) -> Self::Output {
+        let mut owned_offers = self.owned_offers.ids();
+        owned_offers.extend(
+            self.state
+                .submission(&self.name, &self.currency)
+                .and_then(|submission| submission.offer_id),
+        );
+
+        for active_offer in common::active_offers(&self.client, &self.symbol).await? {
+            if !owned_offers.contains(&active_offer.id) {
+                continue;
+            }
+            if let Err(e) = self
+                .owned_offers
+                .cancel(&self.client, active_offer.id, &self.log_target())
+                .await
+            {
+                log::error!(
+                    target: &self.log_target(),
+                    "Failed to cancel the offer {}: {e:#}",
+                    active_offer.id
+                );
+            }
+        }
+
+        if let Some(fallback) = &self.fallback {
+            fallback.cancel_offers().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Execute the strategy.
+    async fn execute(&self) -> Self::Output {
+        log::info!(target: &self.log_target(), "Executing {} on {}...", self.name(), self.currency);
+        *self.requests.lock().unwrap() = 0;
+        // Set again if the rate of the cycle derives from the candles.
+        *self.rate_candle_mts.lock().unwrap() = None;
+
+        self.sync_auto_renew().await?;
+
+        let funding_wallet = self.funding_wallet().await?;
+        let active_offers = self.active_offers().await?;
+        let tracked_offer_id = self
+            .state
+            .submission(&self.name, &self.currency)
+            .and_then(|submission| submission.offer_id);
+
+        // Leave the offers placed manually or by other strategies alone, as reserved balance.
+        let (mut active_offers, foreign_offers) =
+            self.owned_offers
+                .partition(active_offers, tracked_offer_id, &self.log_target())?;
+        let last_submission = self.state.reconcile_submission(
+            &self.name,
+            &self.currency,
+            active_offers.iter().map(|active_offer| active_offer.id),
+        )?;
+        log::debug!(target: &self.log_target(), "Last submission: {last_submission:?}");
+
+        if let Some(last_submission) = &last_submission {
+            if self.high_water_mark.is_some()
+                && tracked_offer_id.is_some()
+                && last_submission.offer_id.is_none()
+            {
+                self.track_fill(last_submission, common::unix_time()?)
+                    .await?;
+            }
+
+            // Make sure the offer that vanished since the last cycle was actually lent.
+            if let (Some(offer_id), None) = (tracked_offer_id, last_submission.offer_id) {
+                if !self.filled_since(last_submission.timestamp).await? {
+                    log::warn!(
+                        target: &self.log_target(),
+                        "Offer {offer_id} vanished without any credit opened since its submission \
+                         at {}: it was rejected or canceled outside of the bot, not lent",
+                        last_submission.timestamp
+                    );
+                }
+            }
+        }
+
+        let offer_amounts: Vec<f64> = active_offers
+            .iter()
+            .map(|active_offer| active_offer.amount)
+            .collect();
+        let (available_balance, total_balance) = compute_balances(
+            funding_wallet.balance,
+            funding_wallet.available_balance,
+            &offer_amounts,
+        );
+
+        log::debug!(
+            target: &self.log_target(),
+            "Wallet balance: {:.2}, wallet available: {:.2}, active offers: {offer_amounts:?}, \
+             available: {available_balance:.2}",
+            funding_wallet.balance,
+            funding_wallet.available_balance
+        );
+
+        // Everything but the funds we are about to reallocate counts as deployed.
+        self.budget
+            .update(total_balance, total_balance - available_balance);
+
+        let offered: f64 = offer_amounts.iter().sum::<f64>()
+            + foreign_offers
+                .iter()
+                .map(|foreign_offer| foreign_offer.amount)
+                .sum::<f64>();
+        // The credits are only reported: failing to fetch them must not stop the lending.
+        match self.log_credits(funding_wallet.balance, offered).await {
+            Result::Ok(credits) => {
+                self.track_capital_usage(offer_amounts.iter().sum(), &credits)?
+            }
+            Err(e) => log::warn!(
+                target: &self.log_target(),
+                "Failed to fetch the credits, not reporting them this cycle: {e:#}"
+            ),
+        }
+
+        // Reconcile a single offer against the target and cancel the surplus ones, so that their
+        // funds are reallocated to it.
+        let offer_ids: Vec<(u64, u64)> = active_offers
+            .iter()
+            .map(|active_offer| (active_offer.id, active_offer.mts_create))
+            .collect();
+        let mut active_offer = primary_offer(&offer_ids, tracked_offer_id)
+            .map(|index| active_offers.swap_remove(index));
+
+        // The offers at the same rate and period, chunks or top-ups, are managed as a single one.
+        let mut chunk_ids = Vec::new();
+        if let Some(active_offer) = &mut active_offer {
+            active_offers.retain(|offer| {
+                let is_chunk =
+                    offer.period == active_offer.period && offer.rate == active_offer.rate;
+                if is_chunk {
+                    chunk_ids.push(offer.id);
+                    active_offer.amount += offer.amount;
+                }
+                !is_chunk
+            });
+        }
+
+        for surplus_offer in active_offers {
+            log::info!(
+                target: &self.log_target(),
+                "Canceling the surplus offer {}: {:.2} for {} days @ {} per day",
+                surplus_offer.id,
+                surplus_offer.amount,
+                surplus_offer.period,
+                self.rate_display.rate(surplus_offer.rate)
+            );
+            common::cancel_offer(self.client()?, surplus_offer.id, &self.log_target()).await?;
+        }
+        let active_count = active_offer.as_ref().map_or(0, |_| 1 + chunk_ids.len());
+        self.offer_slots.update(active_count, active_count);
+
+        // Yield the slots wanted by the strategies of higher priority on the account, the funds of
+        // the canceled offer being offered again within the slots left.
+        if self.offer_slots.preempted() > 0 {
+            if let Some(preempted_offer) = active_offer.take() {
+                log::info!(
+                    target: &self.log_target(),
+                    "Canceling the offer {}: its slots are wanted by the strategies of higher \
+                     priority on the account",
+                    preempted_offer.id
+                );
+                self.cancel_chunked_offer(preempted_offer.id, &chunk_ids)
+                    .await?;
+                chunk_ids.clear();
+                self.offer_slots.update(0, 0);
+            }
+        }
+
+        // Close the underpriced credits first, even when everything is lent, so that their funds
+        // are lent again at the market rate.
+        if let Some(close_credits) = &self.close_credits {
+            if let Some((_, rate)) = self.candle_quote(self.min_rate().await?).await? {
+                self.close_credits(close_credits, rate).await?;
+            }
+        }
+
+        // The amount of the offer decreasing without being replaced means it was partially filled.
+        if let Some(active_offer) = &active_offer {
+            let last_offer = self
+                .last_offer
+                .lock()
+                .unwrap()
+                .replace((active_offer.id, active_offer.amount));
+            if let Some(filled) = partially_filled(last_offer, active_offer.id, active_offer.amount)
+            {
+                log::info!(
+                    target: &self.log_target(),
+                    "Offer {} partially filled: {filled:.2} lent @ {} per day, {:.2} left, {}",
+                    active_offer.id,
+                    self.rate_display.rate(active_offer.rate),
+                    active_offer.amount,
+                    match self.partial_fill {
+                        PartialFill::Leave => "leaving it",
+                        PartialFill::TopUp => "topping it up",
+                        PartialFill::Cancel => "canceling it",
+                    }
+                );
+
+                match self.partial_fill {
+                    PartialFill::Leave => {
+                        self.budget.deploy(active_offer.amount);
+                        return Ok(());
+                    }
+                    PartialFill::Cancel => {
+                        self.cancel_chunked_offer(active_offer.id, &chunk_ids)
+                            .await?;
+                        return Ok(());
+                    }
+                    PartialFill::TopUp => {
+                        let free_balance = funding_wallet.available_balance;
+                        self.top_up(active_offer, active_count, filled.min(free_balance))
+                            .await?;
+                        return Ok(());
+                    }
+                }
+            }
+        } else {
+            *self.last_offer.lock().unwrap() = None;
+        }
+
+        let min_amount = self.min_amount().await?;
+
+        // Early return if there is not enough available balance to create an offer.
+        if available_balance < min_amount {
+            log::info!(
+                target: &self.log_target(),
+                "Insufficient balance to submit a lend offer: {available_balance:.2} < \
+                 {min_amount:.2}"
+            );
+            if let Some(fallback) = &self.fallback {
+                log::info!(
+                    target: &self.log_target(),
+                    "Falling back to lending {} instead of {}",
+                    fallback.currency,
+                    self.currency
+                );
+                return fallback.execute().await;
+            }
+            self.skips
+                .record(self.name(), SkipReason::InsufficientBalance);
+            return Ok(());
+        }
+
+        log::info!(
+            target: &self.log_target(),
+            "{available_balance:.2} {} ({:.2}%) available and can be lended.",
+            self.currency,
+            available_balance * 100. / total_balance
+        );
+
+        let min_rate = self.min_rate().await?;
+
+        let utilization = self.funding_utilization().await?;
+
+        // Deploy more of the balance per loan when the demand is high.
+        let max_balance_percent_per_loan = match (&self.utilization_sizing, utilization) {
+            (Some(utilization_sizing), Some(utilization)) => {
+                let fraction = utilization_sizing.fraction(utilization);
+                log::info!(
+                    target: &self.log_target(),
+                    "Max balance per loan sized by the utilization: {:.0}%",
+                    fraction * 100.
+                );
+                fraction
+            }
+            _ => self.max_balance_percent_per_loan,
+        };
+
+        // Clamp the amount to loan as a fraction of the total balance.
+        let loan_amount =
+            min_amount.max(available_balance.min(total_balance * max_balance_percent_per_loan));
+
+        log::debug!(
+            target: &self.log_target(),
+            "Loan amount: {loan_amount:.2} (min amount: {min_amount:.2}, available: \
+             {available_balance:.2}, max per loan: {:.2})",
+            total_balance * max_balance_percent_per_loan
+        );
+
+        // Price relative to the FRR if enabled, else from the book depth or 99% of the candle rate.
+        let quote = match self.frr_rate().await? {
+            Some(rate) => Some((self.target_period, rate)),
+            None if self.rate_selector == RateSelector::BookDepth => {
+                self.book_quote(loan_amount).await?
+            }
+            None => match self.candle_quote(min_rate).await? {
+                Some((period, rate)) => {
+                    log::debug!(
+                        target: &self.log_target(),
+                        "{period} days candle rate: {rate} before the shave, {} after",
+                        rate * 0.99
+                    );
+                    // Never lend below the market benchmark.
+                    Some((period, self.apply_frr_floor(rate * 0.99).await?))
+                }
+                None => None,
+            },
+        };
+
+        let Some(quote) = quote else {
+            log::info!(target: &self.log_target(), "No rate available, skipping");
+            self.skips.record(self.name(), SkipReason::NoRate);
+            return Ok(());
+        };
+
+        let now = common::unix_time()?;
+        let Some(PricedOffer {
+            mut params,
+            placed_at,
+            stale,
+        }) = self
+            .price_offer(
+                quote,
+                min_rate,
+                loan_amount,
+                utilization,
+                active_offer.as_ref(),
+                now,
+            )
+            .await?
+        else {
+            return Ok(());
+        };
+        let period = params.period();
+        let mut rate = params.rate();
+
+        // Do not lend into a collapsing market, the borrowers bidding far below the rate.
+        if let Some(max_borrow_spread) = self.max_borrow_spread {
+            if !self.is_spread_healthy(rate, max_borrow_spread).await? {
+                self.skips.record(self.name(), SkipReason::UnhealthySpread);
+                return Ok(());
+            }
+        }
+
+        // Scale down the amount to loan to stay within the global deployment budget.
+        let remaining_budget = self.budget.remaining();
+        let mut loan_amount = if remaining_budget < loan_amount {
+            log::info!(
+                target: &self.log_target(),
+                "Loan amount scaled down to the global budget: {:.2} -> {:.2}",
+                loan_amount,
+                remaining_budget
+            );
+            remaining_budget
+        } else {
+            loan_amount
+        };
+
+        if loan_amount < min_amount {
+            log::info!(
+                target: &self.log_target(),
+                "Global deployment budget exhausted for {}: {loan_amount:.2} < {min_amount:.2}",
+                self.currency
+            );
+
+            if let Some(active_offer) = active_offer {
+                self.cancel_chunked_offer(active_offer.id, &chunk_ids)
+                    .await?;
+            }
+
+            self.skips.record(self.name(), SkipReason::BudgetExhausted);
+            return Ok(());
+        }
+
+        // Only the principal beyond the replaced offer counts against the daily budget.
+        let replaced_amount = active_offer
+            .as_ref()
+            .map_or(0., |active_offer| active_offer.amount);
+        if let Some(daily_budget) = &self.daily_budget {
+            let remaining_today = daily_budget.remaining(now);
+
+            if loan_amount - replaced_amount > remaining_today {
+                let scaled_amount = replaced_amount + remaining_today;
+                if scaled_amount < min_amount {
+                    log::info!(
+                        target: &self.log_target(),
+                        "Daily budget of {:.2} {} spent, no new offer until tomorrow (UTC)",
+                        daily_budget.limit(),
+                        self.currency
+                    );
+                    if let Some(active_offer) = &active_offer {
+                        self.budget.deploy(active_offer.amount);
+                    }
+                    self.skips.record(self.name(), SkipReason::DailyBudgetSpent);
+                    return Ok(());
+                }
+
+                log::info!(
+                    target: &self.log_target(),
+                    "Loan amount scaled down to the daily budget: {loan_amount:.2} -> \
+                     {scaled_amount:.2}"
+                );
+                loan_amount = scaled_amount;
+            }
+        }
+
+        // Do not offer much more than what the borrowers ask for around the rate.
+        if let Some(depth_check) = &self.depth_check {
+            let funding_book = common::funding_book(self.client()?, &self.symbol).await?;
+            let max_amount = depth_check.max_amount(&funding_book.bids, rate);
+
+            if loan_amount > max_amount {
+                log::warn!(
+                    target: &self.log_target(),
+                    "Loan amount {loan_amount:.2} is larger than {:.0}% of the bids within {:.0}% \
+                     of the rate: {max_amount:.2}",
+                    depth_check.max_depth_fraction * 100.,
+                    depth_check.rate_range * 100.
+                );
+
+                if depth_check.action == DepthAction::ScaleDown {
+                    if max_amount < min_amount {
+                        log::info!(
+                            target: &self.log_target(),
+                            "The book is too thin for an offer: {max_amount:.2} < \
+                             {min_amount:.2}"
+                        );
+                        if let Some(active_offer) = &active_offer {
+                            self.budget.deploy(active_offer.amount);
+                        }
+                        self.skips.record(self.name(), SkipReason::ThinBook);
+                        return Ok(());
+                    }
+
+                    log::info!(
+                        target: &self.log_target(),
+                        "Loan amount scaled down to the book depth: {loan_amount:.2} -> \
+                         {max_amount:.2}"
+                    );
+                    loan_amount = max_amount;
+                }
+            }
+        }
+
+        let previous_rate = active_offer.as_ref().map(|active_offer| active_offer.rate);
+        // A hidden offer turned visible stays so until it is repriced.
+        let mut visible = active_offer.is_some() && self.visible();
+        // Active offer to replace, canceled next to the submission to minimize the time without
+        // an offer on the book.
+        let mut replaced_id = None;
+
+        // Check if the active offer needs to be canceled.
+        if let Some(active_offer) = active_offer {
+            let rate_diff_percent = (active_offer.rate - rate).abs() / rate;
+            let amount_diff = (loan_amount - active_offer.amount).abs();
+
+            log::debug!(
+                target: &self.log_target(),
+                "Active offer: {:.2} for {} days @ {}, amount diff: {amount_diff:.2}, rate diff: \
+                 {:.2}%",
+                active_offer.amount,
+                active_offer.period,
+                active_offer.rate,
+                rate_diff_percent * 100.
+            );
+
+            // Cancel the active offer if:
+            //  - its period is not the same as the current one
+            //  - or if its loan amount is different from the current one
+            //  - or if replacing it at the current rate pays off
+            //  - or if it is older than the max offer age, to refresh its queue position
+            //  - or if it stayed hidden for too long, to resubmit it as visible
+            let age_secs =
+                (common::unix_time()? * 1000).saturating_sub(active_offer.mts_create) / 1000;
+            let too_old = self
+                .max_offer_age_secs
+                .is_some_and(|max_offer_age_secs| age_secs > max_offer_age_secs);
+            let hidden_too_long = !visible
+                && self
+                    .visible_after_secs
+                    .is_some_and(|visible_after_secs| age_secs > visible_after_secs);
+
+            let decision = self
+                .replacement
+                .decide(active_offer.rate, loan_amount, rate);
+            log::info!(
+                target: &self.log_target(),
+                "Replacing the offer @ {} by {} per day would {} {:.4} {} over {}h{}: {}",
+                self.rate_display.rate(active_offer.rate),
+                self.rate_display.rate(rate),
+                if decision.gain >= 0. { "gain" } else { "lose" },
+                decision.gain.abs(),
+                self.currency,
+                self.replacement.fill_horizon_hours,
+                if decision.unfillable {
+                    ", the offer being unfillable"
+                } else {
+                    ""
+                },
+                if decision.replace { "replace" } else { "keep" }
+            );
+
+            if active_offer.period != period || amount_diff > 1. || decision.replace || stale {
+                log::debug!(
+                    target: &self.log_target(),
+                    "Active offer {} is replaced",
+                    active_offer.id
+                );
+                visible = false;
+                replaced_id = Some(active_offer.id);
+            } else if too_old {
+                log::info!(
+                    target: &self.log_target(),
+                    "Active offer {} is {age_secs}s old, refreshing it despite a good enough rate",
+                    active_offer.id
+                );
+                replaced_id = Some(active_offer.id);
+            } else if hidden_too_long {
+                log::info!(
+                    target: &self.log_target(),
+                    "Active offer {} is hidden for {age_secs}s, resubmitting it as visible @ {} \
+                     per day",
+                    active_offer.id,
+                    self.rate_display.rate(active_offer.rate)
+                );
+                let Some(visible_params) = self.rate_guard.offer_params(
+                    loan_amount,
+                    active_offer.rate,
+                    period,
+                    &self.log_target(),
+                ) else {
+                    self.budget.deploy(active_offer.amount);
+                    self.skips.record(self.name(), SkipReason::InsaneRate);
+                    return Ok(());
+                };
+                replaced_id = Some(active_offer.id);
+                rate = active_offer.rate;
+                params = visible_params;
+                visible = true;
+            } else {
+                log::info!(
+                    target: &self.log_target(),
+                    "Active offer is good enough: {:.2} for {} days @ {} per day ({} APR)",
+                    active_offer.amount,
+                    active_offer.period,
+                    self.rate_display.rate(active_offer.rate),
+                    self.rate_display.apr(active_offer.rate * 365.)
+                );
+                self.budget.deploy(active_offer.amount);
+                self.skips.record(self.name(), SkipReason::OfferGoodEnough);
+                return Ok(());
+            }
+        }
+
+        // Never lend for a period outside of the allowed ones, whatever the fallbacks chose.
+        if !self.period_filter.allows(period) {
+            log::warn!(
+                target: &self.log_target(),
+                "No allowed period to lend for, {period} days being disallowed, skipping"
+            );
+            if let Some(replaced_id) = replaced_id {
+                self.cancel_chunked_offer(replaced_id, &chunk_ids).await?;
+            }
+            self.skips.record(self.name(), SkipReason::PeriodNotAllowed);
+            return Ok(());
+        }
+
+        // Split a large amount into several offers filled independently, as many as the active
+        // offer slots of the account allow.
+        let mut chunks = offer_chunks(loan_amount, self.max_offer_size, min_amount);
+
+        // Leave the funds idle rather than taking an offer slot to earn next to nothing, and never
+        // replace an offer by such one.
+        let below_min_interest = match check_interest(
+            &chunks,
+            rate,
+            period,
+            self.min_projected_interest,
+            replaced_id.is_some(),
+        ) {
+            InterestCheck::Enough => None,
+            InterestCheck::KeepActive(interest) => Some((interest, "keeping the active offer")),
+            InterestCheck::Skip(interest) => Some((interest, "skipping")),
+        };
+        if let Some((interest, outcome)) = below_min_interest {
+            log::info!(
+                target: &self.log_target(),
+                "Projected interest {interest:.4} {} of an offer of {:.2} for {period} days @ {} \
+                 per day is below {} {}, {outcome}",
+                self.currency,
+                chunks[0],
+                self.rate_display.rate(rate),
+                self.min_projected_interest.unwrap_or_default(),
+                self.currency
+            );
+            if replaced_id.is_some() {
+                self.budget.deploy(replaced_amount);
+            }
+            self.skips.record(self.name(), SkipReason::InterestTooLow);
+            return Ok(());
+        }
+
+        self.offer_slots.update(0, chunks.len());
+        let allowed = self.offer_slots.allowed();
+        if allowed < chunks.len() {
+            log::warn!(
+                target: &self.log_target(),
+                "Only {allowed} of the {} offers fit within the max active offers of the account, \
+                 the others being taken by its other strategies",
+                chunks.len()
+            );
+            chunks.truncate(allowed);
+        }
+        if chunks.is_empty() {
+            if let Some(replaced_id) = replaced_id {
+                self.cancel_chunked_offer(replaced_id, &chunk_ids).await?;
+            }
+            self.skips
+                .record(self.name(), SkipReason::OfferLimitReached);
+            return Ok(());
+        }
+
+        // Never cancel the offer without the requests left to replace it, the submissions taking
+        // their retries at worst, and the projected interest its price.
+        let cancels = replaced_id.map_or(0, |_| 1 + chunk_ids.len());
+        if !self.has_requests_left(cancels + chunks.len() * MAX_SUBMIT_REQUESTS + 1) {
+            log::warn!(
+                target: &self.log_target(),
+                "Too few API requests left in the cycle to {} {} offers, {}",
+                if replaced_id.is_some() {
+                    "replace the active offer with"
+                } else {
+                    "submit"
+                },
+                chunks.len(),
+                if replaced_id.is_some() {
+                    "keeping it"
+                } else {
+                    "skipping"
+                }
+            );
+            if replaced_id.is_some() {
+                self.budget.deploy(replaced_amount);
+            }
+            self.skips
+                .record(self.name(), SkipReason::RequestCapReached);
+            return Ok(());
+        }
+
+        // Holding both offers briefly requires the free balance, i.e. without the replaced
+        // offer, to cover the new one.
+        let submit_first = replaced_id.is_some()
+            && self.replace_order == ReplaceOrder::SubmitFirst
+            && funding_wallet.available_balance >= loan_amount;
+        if replaced_id.is_some() && !submit_first && self.replace_order == ReplaceOrder::SubmitFirst
+        {
+            log::debug!(
+                target: &self.log_target(),
+                "Free balance {:.2} too low to hold both offers, canceling first",
+                funding_wallet.available_balance
+            );
+        }
+        let balance = if submit_first {
+            funding_wallet.available_balance
+        } else {
+            if let Some(replaced_id) = replaced_id {
+                self.cancel_chunked_offer(replaced_id, &chunk_ids).await?;
+            }
+            available_balance
+        };
+
+        let mut submitted = Vec::new();
+        for chunk in chunks {
+            let submitted_amount: f64 = submitted.iter().map(|(_, amount)| amount).sum();
+            let max_amount = (balance - submitted_amount).min(self.budget.remaining());
+            let Some((offer_id, chunk)) = self
+                .submit(
+                    params.with_amount(chunk),
+                    self.hidden && !visible,
+                    max_amount,
+                )
+                .await?
+            else {
+                self.set_pending_action(None)?;
+                break;
+            };
+            self.budget.deploy(chunk);
+            self.owned_offers.insert(offer_id)?;
+            self.set_pending_action(None)?;
+            submitted.push((offer_id, chunk));
+        }
+        if let Some(replaced_id) = replaced_id.filter(|_| submit_first) {
+            if submitted.is_empty() {
+                log::info!(
+                    target: &self.log_target(),
+                    "No offer submitted, keeping the active offer {replaced_id}"
+                );
+                self.budget.deploy(replaced_amount);
+            } else {
+                self.cancel_chunked_offer(replaced_id, &chunk_ids).await?;
+            }
+        }
+        let Some(&(offer_id, _)) = submitted.first() else {
+            return Ok(());
+        };
+        let loan_amount: f64 = submitted.iter().map(|(_, amount)| amount).sum();
+        self.offer_slots.update(submitted.len(), submitted.len());
+
+        if self.visible_after_secs.is_some() {
+            self.state.set(&self.visible_key(), &visible)?;
+        }
+        *self.last_offer.lock().unwrap() = Some((
+            offer_id,
+            submitted
+                .iter()
+                .map(|(_, amount)| self.precision.amount(*amount))
+                .sum(),
+        ));
+        if let Some(daily_budget) = &self.daily_budget {
+            daily_budget.spend((loan_amount - replaced_amount).max(0.), now)?;
+        }
+        self.state.set_submission(
+            &self.name,
+            &self.currency,
+            &Submission {
+                timestamp: common::unix_time()?,
+                rate,
+                amount: loan_amount,
+                period,
+                offer_id: Some(offer_id),
+            },
+        )?;
+
+        if self.fill_sla.is_some() || self.stale_after_secs.is_some() {
+            self.state.set(&self.placed_at_key(), &placed_at)?;
+        }
+        if stale {
+            self.state.set(&self.stale_reprice_key(), &now)?;
+        }
+
+        if let Some(previous_rate) = previous_rate {
+            Trailing::record(
+                &mut self.last_down_reprice.lock().unwrap(),
+                previous_rate,
+                rate,
+                now,
+            );
+        }
+
+        log::info!(
+            target: &self.log_target(),
+            "Offer submitted: {:.2} for {} days @ {} per day ({} APR){}",
+            loan_amount,
+            period,
+            self.rate_display.rate(rate),
+            self.rate_display.apr(rate * 365.),
+            if submitted.len() > 1 {
+                format!(", in {} offers", submitted.len())
+            } else {
+                String::new()
+            }
+        );
+        // The offer being submitted, the cap only skips the projection.
+        if let Result::Ok(client) = self.client() {
+            common::log_projected_interest(
+                client,
+                &self.price_feed,
+                &self.currency,
+                loan_amount,
+                rate,
+                &self.log_target(),
+            )
+            .await;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use crate::{
+        budget::{DeploymentBudget, OfferLimit},
+        notifier::Notifier,
+        strategies::precision::Precisions,
+    };
+
+    use super::*;
+
+    fn fill_sla() -> FillSla {
+        // -2% of the rate every 30 minutes after an hour.
+        FillSla {
+            after_secs: 3600,
+            step: 0.02,
+            interval_secs: 1800,
+        }
+    }
+
+    #[test]
+    fn fill_sla_keeps_the_rate_of_young_offers() {
+        assert_eq!(fill_sla().rate(0.0003, 0, 0.0002), 0.0003);
+        assert_eq!(fill_sla().rate(0.0003, 3599, 0.0002), 0.0003);
+    }
+
+    #[test]
+    fn fill_sla_steps_down_the_rate() {
+        let assert_close = |actual: f64, expected: f64| assert!((actual - expected).abs() < 1e-12);
+
+        assert_close(fill_sla().rate(0.0003, 3600, 0.0002), 0.0003 * 0.98);
+        assert_close(fill_sla().rate(0.0003, 3600 + 1799, 0.0002), 0.0003 * 0.98);
+        assert_close(fill_sla().rate(0.0003, 3600 + 1800, 0.0002), 0.0003 * 0.96);
+    }
+
+    #[test]
+    fn fill_sla_stops_at_the_floor() {
+        assert_eq!(fill_sla().rate(0.0003, 48 * 3600, 0.0002), 0.0002);
+        // A rate already below the floor is never raised.
+        assert_eq!(fill_sla().rate(0.0001, 48 * 3600, 0.0002), 0.0001);
+    }
+
+    #[test]
+    fn bumped_amount_clears_the_minimum_within_the_max_amount() {
+        assert_eq!(
+            bumped_amount(100., 150., 1000.),
+            Some(150. * BELOW_MINIMUM_MARGIN)
+        );
+        // Capped below the margin, the retry would be rejected once more.
+        assert_eq!(bumped_amount(100., 150., 120.), None);
+        assert_eq!(bumped_amount(100., 150., 151.), None);
+        assert_eq!(bumped_amount(100., 150., 100.), None);
+    }
+
+    fn pending(action: Action) -> PendingAction {
+        PendingAction {
+            action,
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn recovery_manages_a_cancel_still_active_again() {
+        let terms = OfferTerms {
+            amount: 100.,
+            rate: 0.0002,
+            period: 2,
+        };
+        let active_offers = [(1, terms, 1_600_000_000_000)];
+        let owned_offers = HashSet::new();
+        let precision = Precision::default();
+
+        assert_eq!(
+            recover(
+                pending(Action::Cancel { offer_id: 1 }),
+                &active_offers,
+                &owned_offers,
+                precision
+            ),
+            Recovery::StillActive(1)
+        );
+        assert_eq!(
+            recover(
+                pending(Action::Cancel { offer_id: 2 }),
+                &active_offers,
+                &owned_offers,
+                precision
+            ),
+            Recovery::Gone(2)
+        );
+    }
+
+    #[test]
+    fn recovery_adopts_the_submission_which_landed() {
+        let submit = pending(Action::Submit {
+            amount: 100.,
+            rate: 0.0002,
+            period: 2,
+        });
+        let terms = OfferTerms {
+            amount: 100.,
+            rate: 0.0002,
+            period: 2,
+        };
+        let created = submit.timestamp * 1000 + 300;
+        let precision = Precision::default();
+
+        // Along an older owned offer at the same terms.
+        let active_offers = [(1, terms, created - 60_000), (2, terms, created)];
+        assert_eq!(
+            recover(submit, &active_offers, &HashSet::from([1]), precision),
+            Recovery::Landed(2)
+        );
+        assert_eq!(
+            recover(submit, &active_offers[..1], &HashSet::from([1]), precision),
+            Recovery::NotLanded
+        );
+    }
+
+    #[test]
+    fn recovery_never_adopts_a_foreign_offer() {
+        let submit = pending(Action::Submit {
+            amount: 100.,
+            rate: 0.0002,
+            period: 2,
+        });
+        let created = submit.timestamp * 1000 + 300;
+        let precision = Precision::default();
+
+        // Placed manually since, at other terms.
+        let foreign = OfferTerms {
+            amount: 500.,
+            rate: 0.0003,
+            period: 30,
+        };
+        assert_eq!(
+            recover(submit, &[(3, foreign, created)], &HashSet::new(), precision),
+            Recovery::NotLanded
+        );
+    }
+
+    #[test]
+    fn only_the_submitted_offer_is_adopted() {
+        let precision = Precision::default();
+        let terms = OfferTerms {
+            amount: 100.,
+            rate: 0.0002,
+            period: 2,
+        };
+        let submitted_at = 1_700_000_000;
+        let created = submitted_at * 1000 + 500;
+
+        assert!(terms.matches(terms, created, submitted_at, precision));
+        // Partially filled since.
+        let partially_filled = OfferTerms {
+            amount: 40.,
+            ..terms
+        };
+        assert!(terms.matches(partially_filled, created, submitted_at, precision));
+
+        // An older offer, or one at other terms, is not the submitted one.
+        assert!(!terms.matches(terms, created - 1000, submitted_at, precision));
+        let other_rate = OfferTerms {
+            rate: 0.0003,
+            ..terms
+        };
+        assert!(!terms.matches(other_rate, created, submitted_at, precision));
+        let other_period = OfferTerms {
+            period: 30,
+            ..terms
+        };
+        assert!(!terms.matches(other_period, created, submitted_at, precision));
+        let larger = OfferTerms {
+            amount: 150.,
+            ..terms
+        };
+        assert!(!terms.matches(larger, created, submitted_at, precision));
+    }
+
+    #[test]
+    fn rate_ema_smooths_the_rate_of_a_period() {
+        let mut rate_emas = RateEmas::default();
+        assert_eq!(rate_emas.update(2, 0.0003, 0.25), 0.0003);
+        assert!((rate_emas.update(2, 0.0007, 0.25) - 0.0004).abs() < 1e-12);
+
+        // Each period has its own average, kept when alternating.
+        assert_eq!(rate_emas.update(30, 0.0007, 0.25), 0.0007);
+        assert!((rate_emas.update(2, 0.0004, 0.25) - 0.0004).abs() < 1e-12);
+        assert!((rate_emas.update(30, 0.0003, 0.25) - 0.0006).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rate_emas_are_keyed_by_period_in_the_state() {
+        let mut rate_emas = RateEmas::default();
+        rate_emas.update(2, 0.0003, 0.25);
+        rate_emas.update(30, 0.0005, 0.25);
+
+        let value = serde_json::to_value(&rate_emas).unwrap();
+        assert_eq!(value, serde_json::json!({"2": 0.0003, "30": 0.0005}));
+        assert_eq!(
+            serde_json::from_value::<RateEmas>(value).unwrap(),
+            rate_emas
+        );
+    }
+
+    #[test]
+    fn auto_renew_is_compared_with_the_exchange_settings() {
+        assert!(auto_renew_matches(None, None));
+        assert!(auto_renew_matches(Some((0.0002, 30)), Some((0.0002, 30))));
+        assert!(!auto_renew_matches(Some((0.0002, 2)), Some((0.0002, 30))));
+        assert!(!auto_renew_matches(Some((0.0001, 30)), Some((0.0002, 30))));
+        // Enabled or disabled elsewhere, e.g. on the website.
+        assert!(!auto_renew_matches(Some((0.0002, 30)), None));
+        assert!(!auto_renew_matches(None, Some((0.0002, 30))));
+    }
+
+    #[test]
+    fn fill_is_matched_on_the_submitted_offer() {
+        let submission = Submission {
+            timestamp: 1_000,
+            rate: 0.0002,
+            amount: 500.,
+            period: 30,
+            offer_id: None,
+        };
+        let credit = Credit {
+            id: 1,
+            amount: 500.,
+            rate: 0.0002,
+            period: 30,
+            mts_opening: 1_000_000,
+        };
+
+        assert!(lends_submission(&credit, &submission));
+        // Partially filled.
+        assert!(lends_submission(
+            &Credit {
+                amount: 200.,
+                ..credit
+            },
+            &submission
+        ));
+        // Another credit at the same rate.
+        assert!(!lends_submission(
+            &Credit {
+                amount: 600.,
+                ..credit
+            },
+            &submission
+        ));
+        assert!(!lends_submission(
+            &Credit {
+                period: 2,
+                ..credit
+            },
+            &submission
+        ));
+        assert!(!lends_submission(
+            &Credit {
+                mts_opening: 999_000,
+                ..credit
+            },
+            &submission
+        ));
+        assert!(!lends_submission(
+            &Credit {
+                rate: 0.000_21,
+                ..credit
+            },
+            &submission
+        ));
+    }
+
+    #[test]
+    fn only_the_credits_of_the_strategy_count_as_lent() {
+        let submission = Submission {
+            timestamp: 1_000,
+            rate: 0.0002,
+            amount: 500.,
+            period: 30,
+            offer_id: None,
+        };
+        let credit = |id, amount, rate| Credit {
+            id,
+            amount,
+            rate,
+            period: 30,
+            mts_opening: 1_000_000,
+        };
+        let mut owned_credits = HashSet::from([1]);
+
+        // A credit lent earlier, a fill of the submission, and a manual loan or one of another
+        // strategy on the currency.
+        let credits = [
+            credit(1, 300., 0.0003),
+            credit(2, 500., 0.0002),
+            credit(3, 1000., 0.0004),
+        ];
+        assert_eq!(
+            owned_principal(&credits, &mut owned_credits, Some(&submission)),
+            800.
+        );
+        assert_eq!(owned_credits, HashSet::from([1, 2]));
+
+        // The credit 1 was returned, the credit 2 still counts once the offer is repriced.
+        assert_eq!(
+            owned_principal(&credits[1..], &mut owned_credits, None),
+            500.
+        );
+        assert_eq!(owned_credits, HashSet::from([2]));
+    }
+
+    #[test]
+    fn projected_interest_over_the_period() {
+        assert!((projected_interest(151., 0.0001, 2) - 0.0302).abs() < 1e-12);
+        assert_eq!(projected_interest(1000., 0.0002, 0), 0.);
+    }
+
+    #[test]
+    fn min_rate_margin() {
+        let min_rate = 0.0002;
+
+        assert!(below_min_rate_margin(0.0002, min_rate, 0.1));
+        assert!(below_min_rate_margin(0.000_21, min_rate, 0.1));
+        assert!(!below_min_rate_margin(0.000_23, min_rate, 0.1));
+        // Below the floor itself.
+        assert!(below_min_rate_margin(0.0001, min_rate, 0.1));
+        assert!(!below_min_rate_margin(0.000_21, min_rate, 0.));
+    }
+
+    /// Replay `market` (timestamp, rate) points against an offer initially at `offer_rate`,
+    /// replacing it whenever the trailing rate differs, and return the offer rate after each point.
+    fn trailing_path(trailing: &Trailing, mut offer_rate: f64, market: &[(u64, f64)]) -> Vec<f64> {
+        let mut last_down = None;
+
+        market
+            .iter()
+            .map(|&(now, market_rate)| {
+                let rate = trailing.rate(offer_rate, market_rate, now, last_down);
+                Trailing::record(&mut last_down, offer_rate, rate, now);
+                offer_rate = rate;
+                offer_rate
+            })
+            .collect()
+    }
+
+    #[test]
+    fn trailing_follows_a_spike_up_and_holds_on_the_fade() {
+        // Reprice down only 5% below the offer, an hour after the last downward reprice.
+        let trailing = Trailing {
+            down_drift: 0.05,
+            down_hold_secs: 3600,
+        };
+
+        let market = [
+            (0, 0.000_20),
+            // Spike: followed right away.
+            (60, 0.000_30),
+            (120, 0.000_40),
+            // Fade: a first step down right away, then one per hour.
+            (180, 0.000_30),
+            (1800, 0.000_25),
+            (180 + 3600, 0.000_25),
+            // Neither a small dip nor a step down within the hour after the last one.
+            (180 + 3600 + 30, 0.000_24),
+            (180 + 3600 + 60, 0.000_20),
+            (180 + 2 * 3600, 0.000_20),
+        ];
+
+        assert_eq!(
+            trailing_path(&trailing, 0.000_20, &market),
+            [
+                0.000_20, 0.000_30, 0.000_40, 0.000_30, 0.000_30, 0.000_25, 0.000_25, 0.000_25,
+                0.000_20
+            ]
+        );
+    }
+
+    #[test]
+    fn trailing_holds_from_the_last_downward_reprice() {
+        let trailing = Trailing {
+            down_drift: 0.05,
+            down_hold_secs: 3600,
+        };
+
+        let market = [
+            (0, 0.000_30),
+            (100, 0.000_20),
+            // An upward reprice does not restart the hold.
+            (3000, 0.000_30),
+            (3700, 0.000_20),
+        ];
+
+        assert_eq!(
+            trailing_path(&trailing, 0.000_30, &market),
+            [0.000_30, 0.000_20, 0.000_30, 0.000_20]
+        );
+    }
+
+    #[test]
+    fn trailing_reprices_down_right_away_without_history() {
+        let trailing = Trailing {
+            down_drift: 0.05,
+            down_hold_secs: 3600,
+        };
+
+        assert_eq!(trailing.rate(0.0003, 0.0002, 0, None), 0.0002);
+        assert_eq!(trailing.rate(0.0003, 0.000_29, 0, None), 0.0003);
+    }
+
+    #[test]
+    fn no_fallback_from_two_days_or_without_rate() {
+        assert!(!PeriodFallback::default().applies(2, Some(0.0001), 0.0002));
+        assert!(!PeriodFallback::default().applies(30, None, 0.0002));
+    }
+
+    #[test]
+    fn fallback_to_a_configured_period() {
+        let period_fallback = PeriodFallback {
+            period: 7,
+            accept_below_min_rate: false,
+        };
+
+        assert!(period_fallback.applies(30, Some(0.0001), 0.0002));
+        assert!(period_fallback.applies(2, Some(0.0001), 0.0002));
+        assert!(!period_fallback.applies(7, Some(0.0001), 0.0002));
+    }
+
+    /// Rates of the periods, recording the periods queried.
+    struct MockRates {
+        rates: HashMap<u8, f64>,
+        queried: Mutex<Vec<u8>>,
+    }
+
+    impl MockRates {
+        fn new(rates: &[(u8, f64)]) -> Self {
+            Self {
+                rates: rates.iter().copied().collect(),
+                queried: Mutex::new(vec![]),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl PeriodRates for MockRates {
+        async fn rate(&self, period: u8) -> Result<Option<f64>> {
+            self.queried.lock().unwrap().push(period);
+            Ok(self.rates.get(&period).copied())
+        }
+    }
+
+    /// Load the simple strategies of the YAML `config`, their state being kept in a file named
+    /// after the `test`.
+    fn load(test: &str, config: &str) -> Result<Vec<SimpleStrategy>> {
+        let config_path = env::temp_dir().join(format!("{test}_{}.yaml", process::id()));
+        let state_path = env::temp_dir().join(format!("{test}_{}.json", process::id()));
+        fs::write(&config_path, config).unwrap();
+        let _ = fs::remove_file(&state_path);
+        env::set_var("API_KEY_TEST", "key");
+        env::set_var("SECRET_KEY_TEST", "secret");
+
+        let shared = Shared {
+            budget: DeploymentBudget::new(None, None),
+            offer_limit: OfferLimit::new(None),
+            state: StateStore::load(state_path.to_str().unwrap()),
+            price_feed: PriceFeed::new(None),
+            notifier: Notifier::new(None),
+            skips: SkipStats::default(),
+            precisions: Precisions::default(),
+        };
+        let files = ConfigFiles::new(vec![config_path.to_str().unwrap().to_string()]);
+        let strategies = SimpleStrategy::from_config(&files, &shared);
+        fs::remove_file(&config_path).unwrap();
+
+        strategies
+    }
+
+    /// Return the config of the strategy `test` lending USD for `target_period` days above 0.02%
+    /// per day, with a 0.5 bps premium for 2 days and a 1.5 multiplier for 30 days, and the
+    /// `extra` settings.
+    fn strategy_config(test: &str, target_period: u8, extra: &str) -> String {
+        format!(
+            "simple_strategies:
+  {test}:
+    keys: TEST
+    currency: USD
+    min_amount: 150
+    max_balance_percent_per_loan: 1
+    min_rate: 0.0002
+    target_period: {target_period}
+    monitored_window: 24
+    nth_highest_candle: 1
+    period_premium:
+      2: !bps 0.5
+      30: !multiplier 1.5
+{extra}"
+        )
+    }
+
+    /// Load the strategy of `strategy_config`.
+    fn load_strategy(test: &str, target_period: u8, extra: &str) -> SimpleStrategy {
+        load(test, &strategy_config(test, target_period, extra))
+            .unwrap()
+            .pop()
+            .unwrap()
+    }
+
+    /// Return the parameters of the offer of 500 `strategy` submits from the quote of `rates`,
+    /// without any active offer.
+    async fn submitted(strategy: &SimpleStrategy, rates: &MockRates) -> Option<OfferParams> {
+        let quote = fallback_quote(
+            rates,
+            "test",
+            strategy.target_period,
+            strategy.min_rate,
+            strategy.period_fallback,
+        )
+        .await
+        .unwrap()?;
+
+        // 2023-01-01 12:00 UTC.
+        let now = 19_358 * 86400 + 12 * 3600;
+        strategy
+            .price_offer(quote, strategy.min_rate, 500., None, None, now)
+            .await
+            .unwrap()
+            .map(|priced_offer| priced_offer.params)
+    }
+
+    /// Assert that `params` are an offer of 500 for `period` days @ `rate` per day.
+    fn assert_offer(params: Option<OfferParams>, period: u8, rate: f64) {
+        let params = params.unwrap();
+        assert_eq!(params.amount(), 500.);
+        assert_eq!(params.period(), period);
+        assert!((params.rate() - rate).abs() < 1e-12, "{}", params.rate());
+    }
+
+    #[tokio::test]
+    async fn target_period_meeting_the_min_rate_is_submitted_with_its_premium() {
+        let strategy = load_strategy("submit_target_period", 30, "");
+        let rates = MockRates::new(&[(30, 0.0003), (2, 0.0001)]);
+
+        assert_offer(submitted(&strategy, &rates).await, 30, 0.000_45);
+        assert_eq!(*rates.queried.lock().unwrap(), [30]);
+    }
+
+    #[tokio::test]
+    async fn fallback_above_the_min_rate_is_submitted_with_its_premium() {
+        let strategy = load_strategy("submit_fallback", 30, "");
+        let rates = MockRates::new(&[(30, 0.000_15), (2, 0.000_25)]);
+
+        assert_offer(submitted(&strategy, &rates).await, 2, 0.0003);
+        assert_eq!(*rates.queried.lock().unwrap(), [30, 2]);
+    }
+
+    #[tokio::test]
+    async fn premium_lifts_the_fallback_rate_over_the_floor() {
+        let strategy = load_strategy("submit_premium_over_floor", 30, "");
+        let rates = MockRates::new(&[(30, 0.000_15), (2, 0.000_18)]);
+
+        // The fallback rate is quoted below the floor, then 0.018% + 0.5 bps clears it.
+        assert_offer(submitted(&strategy, &rates).await, 2, 0.000_23);
+        assert_eq!(*rates.queried.lock().unwrap(), [30, 2]);
+    }
+
+    #[tokio::test]
+    async fn floor_blocks_the_fallback_below_the_min_rate() {
+        let strategy = load_strategy("submit_floor_idle", 30, "");
+        let rates = MockRates::new(&[(30, 0.0001), (2, 0.0001)]);
+        assert_eq!(submitted(&strategy, &rates).await, None);
+        assert_eq!(*rates.queried.lock().unwrap(), [30, 2]);
+
+        // Parked at the min rate for the target period instead.
+        let strategy = load_strategy("submit_floor_park", 30, "    floor_action: park");
+        let rates = MockRates::new(&[(30, 0.0001), (2, 0.0001)]);
+        assert_offer(submitted(&strategy, &rates).await, 30, 0.0002);
+    }
+
+    #[tokio::test]
+    async fn fallback_accepting_a_lower_rate_is_submitted_below_the_floor() {
+        let strategy = load_strategy(
+            "submit_fallback_below_floor",
+            30,
+            "    period_fallback:
+      period: 2
+      accept_below_min_rate: true",
+        );
+        let rates = MockRates::new(&[(30, 0.0001), (2, 0.0001)]);
+
+        assert_offer(submitted(&strategy, &rates).await, 2, 0.000_15);
+    }
+
+    #[tokio::test]
+    async fn two_days_target_period_below_the_min_rate_does_not_fall_back() {
+        let strategy = load_strategy("submit_two_days_idle", 2, "");
+        let rates = MockRates::new(&[(2, 0.0001)]);
+        assert_eq!(submitted(&strategy, &rates).await, None);
+        assert_eq!(*rates.queried.lock().unwrap(), [2]);
+
+        let strategy = load_strategy("submit_two_days_park", 2, "    floor_action: park");
+        let rates = MockRates::new(&[(2, 0.0001)]);
+        assert_offer(submitted(&strategy, &rates).await, 2, 0.0002);
+    }
+
+    #[tokio::test]
+    async fn smoothed_rate_is_submitted_and_checked_against_the_floor() {
+        let strategy = load_strategy("submit_smoothed", 30, "    rate_ema_alpha: 0.5");
+        let mut rate_emas = RateEmas::default();
+        rate_emas.update(30, 0.0004, 0.5);
+        rate_emas.update(2, 0.000_05, 0.5);
+        strategy
+            .state
+            .set(&strategy.rate_ema_key(), &rate_emas)
+            .unwrap();
+
+        // Halfway between 0.04% and 0.02%, then the 1.5 multiplier.
+        let rates = MockRates::new(&[(30, 0.0002)]);
+        assert_offer(submitted(&strategy, &rates).await, 30, 0.000_45);
+
+        // The average of the 2 days rate fallen back to stays below the floor.
+        let rates = MockRates::new(&[(30, 0.0001), (2, 0.0002)]);
+        assert_eq!(submitted(&strategy, &rates).await, None);
+    }
+
+    #[tokio::test]
+    async fn implausible_quote_is_never_submitted() {
+        let strategy = load_strategy("submit_implausible", 30, "");
+        // A flash print of 9% per day.
+        let rates = MockRates::new(&[(30, 0.09)]);
+
+        assert_eq!(submitted(&strategy, &rates).await, None);
+    }
+
+    #[test]
+    fn floor_blocks_the_offer_when_both_periods_are_below_it() {
+        let min_rate = 0.0002;
+        // Not accepting a fallback rate below the floor, by default.
+        let period_fallback = PeriodFallback::default();
+
+        // The 30 days rate is below the floor, and so is the 2 days rate fallen back to.
+        assert!(period_fallback.applies(30, Some(0.000_15), min_rate));
+        assert!(blocked_by_min_rate(
+            0.000_18,
+            period_fallback.period,
+            min_rate,
+            Some(period_fallback)
+        ));
+        assert!(blocked_by_min_rate(0.000_15, 30, min_rate, None));
+        assert!(!blocked_by_min_rate(0.0002, 30, min_rate, None));
+    }
+
+    #[test]
+    fn floor_lets_through_the_fallback_accepting_a_lower_rate() {
+        let period_fallback = PeriodFallback {
+            period: 2,
+            accept_below_min_rate: true,
+        };
+
+        assert!(!blocked_by_min_rate(
+            0.000_18,
+            2,
+            0.0002,
+            Some(period_fallback)
+        ));
+        assert!(blocked_by_min_rate(
+            0.000_18,
+            30,
+            0.0002,
+            Some(period_fallback)
+        ));
+    }
+
+    #[test]
+    fn out_of_bounds_fallback_periods_are_rejected_at_load() {
+        let period_fallback = |period| format!("    period_fallback:\n      period: {period}");
+
+        for period in [1, 121] {
+            let test = format!("fallback_period_{period}");
+            let e = load(&test, &strategy_config(&test, 30, &period_fallback(period)))
+                .err()
+                .unwrap();
+            assert!(
+                format!("{e:#}").contains("invalid period_fallback.period"),
+                "{e:#}"
+            );
+        }
+
+        let strategy = load_strategy("fallback_period_120", 30, &period_fallback(120));
+        assert_eq!(strategy.period_fallback.unwrap().period, 120);
+    }
+
+    #[test]
+    fn low_volume_candles_are_excluded() {
+        let volumes = [0., 5_000., 200_000.];
+
+        let liquid: Vec<bool> = volumes
+            .iter()
+            .map(|volume| is_liquid(*volume, Some(10_000.)))
+            .collect();
+        assert_eq!(liquid, [false, false, true]);
+
+        assert!(volumes.iter().all(|volume| is_liquid(*volume, None)));
+    }
+
+    #[test]
+    fn blend_weights_the_windows() {
+        let blended = blend(&[(0.0003, 0.7), (0.0002, 0.3)]).unwrap();
+
+        assert!((blended - 0.000_27).abs() < 1e-12);
+    }
+
+    #[test]
+    fn blend_renormalizes_the_missing_windows() {
+        assert!((blend(&[(0.0002, 0.3)]).unwrap() - 0.0002).abs() < 1e-12);
+        assert_eq!(blend(&[]), None);
+    }
+
+    #[test]
+    fn high_water_mark_floors_within_the_window() {
+        let high_water_mark = HighWaterMark {
+            window_secs: 3600,
+            floor_factor: 0.9,
+        };
+        let fill = Fill {
+            timestamp: 1_000,
+            rate: 0.0004,
+        };
+
+        assert!((high_water_mark.floor(fill, 1_000).unwrap() - 0.000_36).abs() < 1e-12);
+        assert!(high_water_mark.floor(fill, 1_000 + 3599).is_some());
+        assert_eq!(high_water_mark.floor(fill, 1_000 + 3600), None);
+    }
+
+    #[test]
+    fn last_candle_section_rejects_the_features_needing_several_candles() {
+        let nth_highest = RateSelector::NthHighest(3);
+        assert!(validate_candle_section(CandleSection::Hist, nth_highest, true, true).is_ok());
+
+        let last = CandleSection::Last;
+        assert!(validate_candle_section(last, RateSelector::NthHighest(1), false, false).is_ok());
+        assert!(validate_candle_section(last, RateSelector::Percentile(90.), false, false).is_ok());
+        assert!(validate_candle_section(last, nth_highest, false, false).is_err());
+        let ema = RateSelector::Ema {
+            period: 5,
+            multiplier: 1.,
+        };
+        assert!(validate_candle_section(last, ema, false, false).is_err());
+        assert!(validate_candle_section(last, RateSelector::NthHighest(1), true, false).is_err());
+        assert!(validate_candle_section(last, RateSelector::NthHighest(1), false, true).is_err());
+    }
+
+    fn momentum_filter() -> MomentumFilter {
+        // Reprice down once the last 3 highs average 5% below the 3 before.
+        MomentumFilter { k: 3, margin: 0.05 }
+    }
+
+    #[test]
+    fn momentum_filter_confirms_a_falling_series() {
+        let falling: Vec<f64> = (0..10).map(|i| 0.0003 - i as f64 * 0.00001).collect();
+
+        assert!(momentum_filter().confirms_down(&falling));
+    }
+
+    #[test]
+    fn momentum_filter_rejects_a_rising_series() {
+        let rising: Vec<f64> = (0..10).map(|i| 0.0002 + i as f64 * 0.00001).collect();
+
+        assert!(!momentum_filter().confirms_down(&rising));
+    }
+
+    #[test]
+    fn momentum_filter_rejects_an_oscillating_series() {
+        // A single bad candle among steady highs is not a trend.
+        let oscillating = [0.0003, 0.0002, 0.0003, 0.0003, 0.0002, 0.0003];
+        assert!(!momentum_filter().confirms_down(&oscillating));
+
+        // Nor is a series too short to compare two averages.
+        assert!(!momentum_filter().confirms_down(&[0.0003, 0.0002]));
+    }
+
+    #[test]
+    fn balances_without_active_offer() {
+        assert_eq!(compute_balances(1_000., 400., &[]), (400., 1_000.));
+    }
+
+    #[test]
+    fn balances_add_back_the_active_offer() {
+        // The 250 offered are locked in the active offer but can be reallocated.
+        assert_eq!(compute_balances(1_000., 150., &[250.]), (400., 1_000.));
+
+        // Nothing lent: the whole balance is either available or offered.
+        assert_eq!(compute_balances(1_000., 0., &[1_000.]), (1_000., 1_000.));
+    }
+
+    #[test]
+    fn balances_add_back_all_the_active_offers() {
+        assert_eq!(
+            compute_balances(1_000., 100., &[250., 150.]),
+            (500., 1_000.)
+        );
+    }
+
+    #[test]
+    fn zero_balances() {
+        assert_eq!(compute_balances(0., 0., &[]), (0., 0.));
+        assert_eq!(compute_balances(0., 0., &[0.]), (0., 0.));
+    }
+
+    #[test]
+    fn interest_is_checked_per_chunk() {
+        // 4 chunks of 250 earning 0.1 each over 2 days, 0.4 in total.
+        let chunks = offer_chunks(1000., Some(250.), 50.);
+        assert_eq!(
+            check_interest(&chunks, 0.0002, 2, Some(0.1), false),
+            InterestCheck::Enough
+        );
+        assert_eq!(
+            check_interest(&chunks, 0.0002, 2, None, false),
+            InterestCheck::Enough
+        );
+
+        let below = check_interest(&chunks, 0.0002, 2, Some(0.2), false);
+        assert!(matches!(below, InterestCheck::Skip(interest) if (interest - 0.1).abs() < 1e-9));
+    }
+
+    #[test]
+    fn interest_too_low_keeps_the_active_offer() {
+        let chunks = offer_chunks(1000., Some(250.), 50.);
+
+        let below = check_interest(&chunks, 0.0002, 2, Some(0.2), true);
+        assert!(
+            matches!(below, InterestCheck::KeepActive(interest) if (interest - 0.1).abs() < 1e-9)
+        );
+        assert_eq!(
+            check_interest(&chunks, 0.0002, 2, Some(0.1), true),
+            InterestCheck::Enough
+        );
+    }
+
+    #[test]
+    fn request_cap_covers_the_requests_to_come() {
+        assert!(fits_request_cap(100, 10, None));
+        assert!(fits_request_cap(20, 5, Some(25)));
+        // A cancel and a submit after 24 requests would exceed the cap of 25.
+        assert!(!fits_request_cap(24, 2, Some(25)));
+        assert!(!fits_request_cap(25, 1, Some(25)));
+    }
+
+    #[test]
+    fn offer_chunks_are_equal() {
+        assert_eq!(offer_chunks(1000., Some(250.), 50.), vec![250.; 4]);
+        assert_eq!(offer_chunks(1000., Some(300.), 50.), vec![250.; 4]);
+    }
+
+    #[test]
+    fn offer_chunks_are_never_dust() {
+        // 10 chunks of 100 would be below the minimum.
+        let chunks = offer_chunks(1000., Some(100.), 150.);
+        assert_eq!(chunks.len(), 6);
+        assert!(chunks.iter().all(|chunk| *chunk >= 150.));
+        assert!((chunks.iter().sum::<f64>() - 1000.).abs() < 1e-9);
+
+        assert_eq!(offer_chunks(120., Some(100.), 150.), vec![120.]);
+    }
+
+    #[test]
+    fn offer_chunks_without_max_offer_size() {
+        assert_eq!(offer_chunks(1000., None, 50.), vec![1000.]);
+        assert_eq!(offer_chunks(200., Some(250.), 50.), vec![200.]);
+    }
+
+    #[test]
+    fn partial_fill_is_a_decrease_of_the_same_offer() {
+        assert_eq!(partially_filled(Some((1, 1_000.)), 1, 1_000.), None);
+        assert_eq!(partially_filled(Some((1, 1_000.)), 1, 400.), Some(600.));
+        // A new offer or an unknown one was not filled.
+        assert_eq!(partially_filled(Some((1, 1_000.)), 2, 400.), None);
+        assert_eq!(partially_filled(None, 1, 400.), None);
+    }
+
+    #[test]
+    fn depth_check_counts_the_bids_within_the_range() {
+        let depth_check = DepthCheck {
+            rate_range: 0.1,
+            max_depth_fraction: 0.5,
+            action: DepthAction::ScaleDown,
+        };
+        let bids = [
+            BookLevel {
+                rate: 0.0003,
+                period: 2,
+                amount: 10_000.,
+            },
+            BookLevel {
+                rate: 0.000_28,
+                period: 2,
+                amount: 30_000.,
+            },
+            BookLevel {
+                rate: 0.0002,
+                period: 30,
+                amount: 500_000.,
+            },
+        ];
+
+        assert_eq!(depth_check.max_amount(&bids, 0.0003), 20_000.);
+        assert_eq!(depth_check.max_amount(&bids, 0.0004), 0.);
+    }
+
+    #[test]
+    fn period_filter() {
+        let period_filter = PeriodFilter {
+            allowed: Some(vec![2, 7, 30]),
+            blocked: vec![30],
+        };
+
+        assert!(period_filter.allows(2));
+        assert!(!period_filter.allows(30));
+        assert!(!period_filter.allows(120));
+        assert!(PeriodFilter::default().allows(120));
+    }
+
+    #[test]
+    fn primary_offer_is_the_tracked_one() {
+        let offers = [(1, 3_000), (2, 1_000), (3, 2_000)];
+
+        assert_eq!(primary_offer(&offers, Some(2)), Some(1));
+    }
+
+    #[test]
+    fn primary_offer_is_the_newest_one_without_tracked_offer() {
+        let offers = [(1, 1_000), (2, 3_000), (3, 2_000)];
+
+        assert_eq!(primary_offer(&offers, None), Some(1));
+        // The tracked offer was filled or canceled.
+        assert_eq!(primary_offer(&offers, Some(4)), Some(1));
+    }
+
+    #[test]
+    fn no_primary_offer_without_active_offers() {
+        assert_eq!(primary_offer(&[], Some(1)), None);
+    }
+
+    fn volatility_premium(measure: VolatilityMeasure) -> VolatilityPremium {
+        VolatilityPremium {
+            measure,
+            k: 0.5,
+            max_premium: 0.0001,
+        }
+    }
+
+    #[test]
+    fn flat_market_has_no_premium() {
+        let flat = [0.0002; 96];
+
+        assert!(volatility_premium(VolatilityMeasure::StdDev).premium(&flat) < 1e-12);
+        assert_eq!(
+            volatility_premium(VolatilityMeasure::Range).premium(&flat),
+            0.
+        );
+    }
+
+    #[test]
+    fn trending_market_premium() {
+        let trending: Vec<f64> = (0..10).map(|i| 0.0001 + i as f64 * 0.00001).collect();
+
+        let premium = volatility_premium(VolatilityMeasure::Range).premium(&trending);
+        assert!((premium - 0.000045).abs() < 1e-12);
+    }
+
+    #[test]
+    fn spiky_market_premium_is_capped() {
+        let mut spiky = vec![0.0001; 10];
+        spiky[5] = 0.0011;
+
+        assert_eq!(
+            volatility_premium(VolatilityMeasure::Range).premium(&spiky),
+            0.0001
+        );
+        assert_eq!(
+            volatility_premium(VolatilityMeasure::StdDev).premium(&spiky),
+            0.0001
+        );
+    }
+
+    fn replacement() -> Replacement {
+        Replacement {
+            fill_horizon_hours: 24.,
+            min_gain: 0.05,
+            unfillable_drift: 0.05,
+        }
+    }
+
+    #[test]
+    fn replace_when_the_gain_exceeds_the_threshold() {
+        let decision = replacement().decide(0.0002, 1000., 0.0003);
+
+        assert!((decision.gain - 0.1).abs() < 1e-12);
+        assert!(!decision.unfillable);
+        assert!(decision.replace);
+        assert!(!replacement().decide(0.0002, 1000., 0.000_204).replace);
+    }
+
+    #[test]
+    fn keep_the_offer_on_a_tick_up() {
+        let replacement = Replacement::default();
+
+        assert!(!replacement.decide(0.0002, 1000., 0.0002 + 1e-8).replace);
+        assert!(!replacement.decide(0.0002, 1000., 0.000_201).replace);
+        assert!(replacement.decide(0.0002, 1000., 0.000_203).replace);
+    }
+
+    #[test]
+    fn never_replace_to_a_lower_fillable_rate() {
+        let decision = replacement().decide(0.0003, 1000., 0.000_29);
+
+        assert!(decision.gain < 0.);
+        assert!(!decision.unfillable);
+        assert!(!decision.replace);
+    }
+
+    #[test]
+    fn replace_an_unfillable_offer_at_a_lower_rate() {
+        let decision = replacement().decide(0.0004, 1000., 0.0003);
+
+        assert!(decision.unfillable);
+        assert!(decision.replace);
+    }
 }
@@ -0,0 +1,466 @@
+use std::{collections::HashMap, fmt};
+
+use anyhow::{ensure, Context, Ok, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use bitfinex_api::bitfinex::AsyncBitfinex;
+
+use crate::{
+    budget::AccountBudget,
+    config::{self, ConfigFiles},
+    price_feed::PriceFeed,
+};
+
+use super::{
+    common,
+    fixed_rate_strategy::OfferAmount,
+    indicators,
+    owned_offers::OwnedOffers,
+    precision::Precision,
+    rate_display::{self, RateDisplay},
+    rate_guard::{self, RateGuard},
+    timeframe::{CandleSection, CandleTimeFrame},
+    FromConfig, Shared, Strategy,
+};
+
+/// Rate above which the market is spiking.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpikeThreshold {
+    /// A fixed rate, per day.
+    Absolute(f64),
+    /// A multiple of the median of the candle highs.
+    MedianMultiple(f64),
+}
+
+impl SpikeThreshold {
+    /// Return the threshold for the candle `highs`, or `None` if there are no candles to take
+    /// the median of.
+    fn rate(&self, highs: &[f64]) -> Option<f64> {
+        match self {
+            SpikeThreshold::Absolute(rate) => Some(*rate),
+            SpikeThreshold::MedianMultiple(multiple) => {
+                indicators::percentile(highs, 50.).map(|median| median * multiple)
+            }
+        }
+    }
+}
+
+impl fmt::Display for SpikeThreshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpikeThreshold::Absolute(rate) => write!(f, "{:.4}% per day", rate * 100.),
+            SpikeThreshold::MedianMultiple(multiple) => write!(f, "x{multiple} the median"),
+        }
+    }
+}
+
+/// State of the strategy for a cycle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    /// The market is below the threshold: wait for a spike, idle or parked at the threshold.
+    Armed,
+    /// The market is above the threshold: offer at the threshold.
+    Sniping,
+}
+
+/// Return the mode given the `threshold` and the candle `highs`, the market spiking when its
+/// latest high reaches the threshold.
+fn mode(threshold: f64, highs: &[f64]) -> Mode {
+    match highs.last() {
+        Some(latest_high) if *latest_high >= threshold => Mode::Sniping,
+        _ => Mode::Armed,
+    }
+}
+
+/// Only lend during the rate spikes, at the spike threshold, and keep the funds idle or parked at
+/// the threshold the rest of the time.
+pub struct SpikeStrategy {
+    name: String,
+    client: AsyncBitfinex,
+    currency: String,
+    symbol: String,
+    amount: OfferAmount,
+    period: u8,
+    spike_threshold: SpikeThreshold,
+    monitored_window: u64,
+    candle_timeframe: CandleTimeFrame,
+    /// Keep an offer at the threshold while armed instead of leaving the funds idle.
+    park: bool,
+    rate_guard: RateGuard,
+    price_feed: PriceFeed,
+    precision: Precision,
+    hidden: bool,
+    owned_offers: OwnedOffers,
+    rate_display: RateDisplay,
+    budget: AccountBudget,
+}
+
+impl SpikeStrategy {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        name: String,
+        client: AsyncBitfinex,
+        currency: String,
+        symbol: String,
+        amount: OfferAmount,
+        period: u8,
+        spike_threshold: SpikeThreshold,
+        monitored_window: u64,
+        candle_timeframe: CandleTimeFrame,
+        park: bool,
+        rate_guard: RateGuard,
+        price_feed: PriceFeed,
+        precision: Precision,
+        hidden: bool,
+        owned_offers: OwnedOffers,
+        rate_display: RateDisplay,
+        budget: AccountBudget,
+    ) -> Self {
+        Self {
+            name,
+            client,
+            currency,
+            symbol,
+            amount,
+            period,
+            spike_threshold,
+            monitored_window,
+            candle_timeframe,
+            park,
+            rate_guard,
+            price_feed,
+            precision,
+            hidden,
+            owned_offers,
+            rate_display,
+            budget,
+        }
+    }
+}
+
+impl fmt::Display for SpikeStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {}: {} for {} days on the spikes above {} over {}h, {} otherwise",
+            self.name(),
+            self.currency,
+            self.amount,
+            self.period,
+            self.spike_threshold,
+            self.monitored_window,
+            if self.park { "parked" } else { "idle" }
+        )
+    }
+}
+
+impl FromConfig for SpikeStrategy {
+    fn from_config(files: &ConfigFiles, shared: &Shared) -> Result<Vec<Self>> {
+        #[derive(Debug, Deserialize)]
+        struct Strategy {
+            keys: String,
+            currency: String,
+            /// Funding symbol, defaults to `f{currency}`.
+            #[serde(default, alias = "funding_symbol")]
+            symbol: Option<String>,
+            amount: OfferAmount,
+            period: u8,
+            spike_threshold: SpikeThreshold,
+            monitored_window: u64,
+            #[serde(default)]
+            candle_timeframe: CandleTimeFrame,
+            #[serde(default)]
+            park: bool,
+            #[serde(default = "rate_guard::default_max_rate")]
+            max_rate: f64,
+            #[serde(default = "rate_guard::default_sanity_max_apr")]
+            sanity_max_apr: f64,
+            #[serde(default = "common::default_enabled")]
+            enabled: bool,
+            #[serde(default = "common::default_hidden")]
+            hidden: bool,
+            #[serde(default = "rate_display::default_rate_decimals")]
+            rate_display_decimals: usize,
+            #[serde(default = "rate_display::default_apr_decimals")]
+            apr_display_decimals: usize,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[serde(default)]
+            spike_strategies: HashMap<String, Strategy>,
+        }
+
+        let config: Config = config::read(files)?;
+
+        config
+            .spike_strategies
+            .into_iter()
+            .filter(|(name, strategy)| common::is_enabled(name, strategy.enabled))
+            .map(|(name, strategy)| {
+                match strategy.amount {
+                    OfferAmount::Absolute(amount) => {
+                        ensure!(amount > 0., "{name}: amount must be positive")
+                    }
+                    OfferAmount::BalancePercent(percent) => ensure!(
+                        percent > 0. && percent <= 1.,
+                        "{name}: balance_percent amount must be within (0, 1]"
+                    ),
+                }
+                match strategy.spike_threshold {
+                    SpikeThreshold::Absolute(rate) => {
+                        ensure!(rate > 0., "{name}: spike_threshold must be positive")
+                    }
+                    SpikeThreshold::MedianMultiple(multiple) => ensure!(
+                        multiple >= 1.,
+                        "{name}: median_multiple spike_threshold must be at least 1"
+                    ),
+                }
+                ensure!(
+                    strategy.monitored_window > 0,
+                    "{name}: monitored_window must be positive"
+                );
+                common::validate_period(strategy.period)
+                    .with_context(|| format!("{name}: invalid period"))?;
+
+                let rate_display = RateDisplay::new(
+                    strategy.rate_display_decimals,
+                    strategy.apr_display_decimals,
+                );
+                rate_display
+                    .validate()
+                    .with_context(|| format!("{name}: invalid display decimals"))?;
+                let rate_guard = RateGuard::new(
+                    strategy.max_rate,
+                    strategy.sanity_max_apr,
+                    rate_display,
+                    shared.notifier.clone(),
+                );
+                rate_guard
+                    .validate()
+                    .with_context(|| format!("{name}: invalid rate ceilings"))?;
+
+                let account_budget = shared.budget.account(&strategy.keys, &strategy.currency);
+                let precision = shared.precisions.get(&strategy.currency);
+                let symbol = common::funding_symbol(&strategy.currency, strategy.symbol);
+                let owned_offers = OwnedOffers::load(
+                    &name,
+                    &strategy.currency,
+                    shared.state.clone(),
+                    rate_display,
+                );
+
+                Ok(Self::new(
+                    name,
+                    common::client_from_keys(&strategy.keys)?,
+                    strategy.currency,
+                    symbol,
+                    strategy.amount,
+                    strategy.period,
+                    strategy.spike_threshold,
+                    strategy.monitored_window,
+                    strategy.candle_timeframe,
+                    strategy.park,
+                    rate_guard,
+                    shared.price_feed.clone(),
+                    precision,
+                    strategy.hidden,
+                    owned_offers,
+                    rate_display,
+                    account_budget,
+                ))
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid spike_strategies in {files}"))
+    }
+}
+
+#[async_trait]
+impl Strategy for SpikeStrategy {
+    type Output = Result<()>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Cancel the offers submitted by the strategy.
+    async fn cancel_offers(&self) -> Self::Output {
+        self.owned_offers
+            .cancel_all(&self.client, &self.symbol, &self.log_target())
+            .await
+    }
+
+    /// Execute the strategy.
+    async fn execute(&self) -> Self::Output {
+        log::info!(target: &self.log_target(), "Executing {} on {}...", self.name(), self.currency);
+
+        let funding_wallet = common::funding_wallet(&self.client, &self.currency).await?;
+        let active_offer = self
+            .owned_offers
+            .active_offer(&self.client, &self.symbol, &self.log_target())
+            .await?;
+
+        let available_balance = funding_wallet.available_balance
+            + active_offer
+                .as_ref()
+                .map_or(0., |active_offer| active_offer.amount);
+        let total_balance = funding_wallet.balance;
+
+        self.budget
+            .update(total_balance, total_balance - available_balance);
+
+        let candles = common::candles(
+            &self.client,
+            &self.symbol,
+            self.monitored_window,
+            self.period,
+            self.candle_timeframe,
+            CandleSection::Hist,
+        )
+        .await?;
+        let highs: Vec<f64> = candles.iter().map(|candle| candle.high).collect();
+
+        let Some(threshold) = self.spike_threshold.rate(&highs) else {
+            log::info!(
+                target: &self.log_target(),
+                "No candles to derive the spike threshold from, skipping"
+            );
+            return Ok(());
+        };
+
+        let mode = mode(threshold, &highs);
+        match mode {
+            Mode::Sniping => log::info!(
+                target: &self.log_target(),
+                "Sniping: the market {} is above the {} per day threshold",
+                self.rate_display.rate(highs.last().copied().unwrap_or_default()),
+                self.rate_display.rate(threshold)
+            ),
+            Mode::Armed => log::info!(
+                target: &self.log_target(),
+                "Armed: waiting for a spike above {} per day, the funds are {}",
+                self.rate_display.rate(threshold),
+                if self.park { "parked at it" } else { "idle" }
+            ),
+        }
+
+        // Offer what is available, up to the configured amount and the global budget, unless
+        // armed without parking.
+        let loan_amount = if mode == Mode::Sniping || self.park {
+            self.amount
+                .resolve(total_balance)
+                .min(available_balance)
+                .min(self.budget.remaining())
+        } else {
+            0.
+        };
+
+        // Keep the active offer if it already sits at the threshold.
+        if let Some(active_offer) = active_offer {
+            if loan_amount > 0.
+                && active_offer.period == self.period
+                && (active_offer.rate - threshold).abs() / threshold <= 0.01
+                && (active_offer.amount - loan_amount).abs() <= 1.
+            {
+                log::info!(
+                    target: &self.log_target(),
+                    "Active offer is up to date: {:.2} for {} days @ {} per day",
+                    active_offer.amount,
+                    active_offer.period,
+                    self.rate_display.rate(active_offer.rate)
+                );
+                self.budget.deploy(active_offer.amount);
+                return Ok(());
+            }
+
+            self.owned_offers
+                .cancel(&self.client, active_offer.id, &self.log_target())
+                .await?;
+        }
+
+        if loan_amount <= 0. {
+            return Ok(());
+        }
+
+        let Some(params) =
+            self.rate_guard
+                .offer_params(loan_amount, threshold, self.period, &self.log_target())
+        else {
+            return Ok(());
+        };
+
+        let offer = common::submit_offer(
+            &self.client,
+            &self.symbol,
+            params,
+            self.precision,
+            self.hidden,
+            &self.log_target(),
+        )
+        .await?;
+        self.owned_offers.insert(offer.id)?;
+        self.budget.deploy(loan_amount);
+
+        log::info!(
+            target: &self.log_target(),
+            "Offer submitted: {:.2} for {} days @ {} per day ({} APR)",
+            loan_amount,
+            self.period,
+            self.rate_display.rate(threshold),
+            self.rate_display.apr(threshold * 365.)
+        );
+        common::log_projected_interest(
+            &self.client,
+            &self.price_feed,
+            &self.currency,
+            loan_amount,
+            threshold,
+            &self.log_target(),
+        )
+        .await;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Baseline highs at 0.0002 per day with a spike to 0.001 in the middle.
+    fn spike() -> Vec<f64> {
+        let mut highs = vec![0.0002; 24];
+        highs[10] = 0.001;
+        highs[11] = 0.0008;
+        highs
+    }
+
+    #[test]
+    fn median_threshold_ignores_the_spike() {
+        let threshold = SpikeThreshold::MedianMultiple(3.).rate(&spike()).unwrap();
+
+        assert!((threshold - 0.0006).abs() < 1e-12);
+        assert_eq!(SpikeThreshold::MedianMultiple(3.).rate(&[]), None);
+        assert_eq!(SpikeThreshold::Absolute(0.0005).rate(&[]), Some(0.0005));
+    }
+
+    #[test]
+    fn armed_at_the_baseline_and_sniping_during_the_spike() {
+        let highs = spike();
+        let threshold = SpikeThreshold::MedianMultiple(3.).rate(&highs).unwrap();
+
+        let modes: Vec<Mode> = (9..13).map(|i| mode(threshold, &highs[..=i])).collect();
+
+        assert_eq!(
+            modes,
+            [Mode::Armed, Mode::Sniping, Mode::Sniping, Mode::Armed]
+        );
+    }
+
+    #[test]
+    fn armed_without_candles() {
+        assert_eq!(mode(0.0005, &[]), Mode::Armed);
+    }
+}
@@ -0,0 +1,80 @@
+use serde::Deserialize;
+
+use bitfinex_api::api::common::{Section, TimeFrame};
+
+/// Timeframe of the candles fetched from the Bitfinex API, as written in the config.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum CandleTimeFrame {
+    #[serde(rename = "1m")]
+    OneMin,
+    #[serde(rename = "5m")]
+    FiveMins,
+    #[default]
+    #[serde(rename = "15m")]
+    FifteenMins,
+    #[serde(rename = "30m")]
+    ThirtyMins,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "3h")]
+    ThreeHours,
+    #[serde(rename = "6h")]
+    SixHours,
+    #[serde(rename = "12h")]
+    TwelveHours,
+    #[serde(rename = "1D")]
+    OneDay,
+}
+
+impl CandleTimeFrame {
+    pub fn time_frame(&self) -> TimeFrame {
+        match self {
+            CandleTimeFrame::OneMin => TimeFrame::OneMin,
+            CandleTimeFrame::FiveMins => TimeFrame::FiveMins,
+            CandleTimeFrame::FifteenMins => TimeFrame::FifteenMins,
+            CandleTimeFrame::ThirtyMins => TimeFrame::ThirtyMins,
+            CandleTimeFrame::OneHour => TimeFrame::OneHour,
+            CandleTimeFrame::ThreeHours => TimeFrame::ThreeHours,
+            CandleTimeFrame::SixHours => TimeFrame::SixHours,
+            CandleTimeFrame::TwelveHours => TimeFrame::TwelveHours,
+            CandleTimeFrame::OneDay => TimeFrame::OneDay,
+        }
+    }
+
+    /// Return the duration of a candle, in milliseconds.
+    pub fn millis(&self) -> u64 {
+        let minutes = match self {
+            CandleTimeFrame::OneMin => 1,
+            CandleTimeFrame::FiveMins => 5,
+            CandleTimeFrame::FifteenMins => 15,
+            CandleTimeFrame::ThirtyMins => 30,
+            CandleTimeFrame::OneHour => 60,
+            CandleTimeFrame::ThreeHours => 3 * 60,
+            CandleTimeFrame::SixHours => 6 * 60,
+            CandleTimeFrame::TwelveHours => 12 * 60,
+            CandleTimeFrame::OneDay => 24 * 60,
+        };
+
+        minutes * 60 * 1000
+    }
+}
+
+/// Section of the candles fetched from the Bitfinex API, as written in the config.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CandleSection {
+    /// The candles of the window.
+    #[default]
+    Hist,
+    /// Only the latest candle, still forming.
+    Last,
+}
+
+impl CandleSection {
+    pub fn section(&self) -> Section {
+        match self {
+            CandleSection::Hist => Section::Hist,
+            CandleSection::Last => Section::Last,
+        }
+    }
+}
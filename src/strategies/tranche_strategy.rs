@@ -0,0 +1,458 @@
+use std::{collections::HashMap, fmt};
+
+use anyhow::{ensure, Context, Ok, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use bitfinex_api::bitfinex::AsyncBitfinex;
+
+use crate::{
+    budget::AccountBudget,
+    config::{self, ConfigFiles},
+    state::StateStore,
+};
+
+use super::{
+    common,
+    owned_offers::OwnedOffers,
+    precision::Precision,
+    rate_display::{self, RateDisplay},
+    rate_guard::{self, RateGuard},
+    rate_selector::RateSelector,
+    timeframe::CandleTimeFrame,
+    FromConfig, Shared, Strategy,
+};
+
+/// A submitted tranche.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Tranche {
+    rate: f64,
+    amount: f64,
+}
+
+/// Progress of the tranche schedule, persisted across restarts.
+#[derive(Debug, Serialize, Deserialize)]
+struct Schedule {
+    /// UNIX timestamp (in seconds) of the start of the schedule.
+    started_at: u64,
+    /// Total amount and number of tranches of the schedule, to start over when they change.
+    total_amount: f64,
+    tranches: usize,
+    submitted: Vec<Tranche>,
+    /// Whether the schedule was stopped early because the funds were no longer available.
+    stopped: bool,
+}
+
+/// Return the number of tranches due `elapsed` seconds after the start of a schedule spreading
+/// `tranches` tranches over `duration` seconds, the first one being due right away.
+fn due_tranches(elapsed: u64, duration: u64, tranches: usize) -> usize {
+    if duration == 0 {
+        return tranches;
+    }
+
+    let due = elapsed as u128 * tranches as u128 / duration as u128 + 1;
+    (due as usize).min(tranches)
+}
+
+/// Deploy a total amount as tranches spread over time, each priced from the market at submission.
+pub struct TrancheStrategy {
+    name: String,
+    client: AsyncBitfinex,
+    currency: String,
+    symbol: String,
+    total_amount: f64,
+    tranches: usize,
+    /// Duration of the schedule, in seconds.
+    duration: u64,
+    period: u8,
+    min_rate: f64,
+    monitored_window: u64,
+    rate_selector: RateSelector,
+    candle_timeframe: CandleTimeFrame,
+    rate_guard: RateGuard,
+    state: StateStore,
+    precision: Precision,
+    hidden: bool,
+    owned_offers: OwnedOffers,
+    rate_display: RateDisplay,
+    budget: AccountBudget,
+}
+
+impl TrancheStrategy {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        name: String,
+        client: AsyncBitfinex,
+        currency: String,
+        symbol: String,
+        total_amount: f64,
+        tranches: usize,
+        duration: u64,
+        period: u8,
+        min_rate: f64,
+        monitored_window: u64,
+        rate_selector: RateSelector,
+        candle_timeframe: CandleTimeFrame,
+        rate_guard: RateGuard,
+        state: StateStore,
+        precision: Precision,
+        hidden: bool,
+        owned_offers: OwnedOffers,
+        rate_display: RateDisplay,
+        budget: AccountBudget,
+    ) -> Self {
+        Self {
+            name,
+            client,
+            currency,
+            symbol,
+            total_amount,
+            tranches,
+            duration,
+            period,
+            min_rate,
+            monitored_window,
+            rate_selector,
+            candle_timeframe,
+            rate_guard,
+            state,
+            precision,
+            hidden,
+            owned_offers,
+            rate_display,
+            budget,
+        }
+    }
+
+    /// Key of the schedule in the state file.
+    fn state_key(&self) -> String {
+        format!("tranche_strategies.{}", self.name)
+    }
+
+    /// Load the persisted schedule, or start a new one if there is none or the config changed.
+    fn schedule(&self, now: u64) -> Schedule {
+        self.state
+            .get::<Schedule>(&self.state_key())
+            .filter(|schedule| {
+                schedule.total_amount == self.total_amount && schedule.tranches == self.tranches
+            })
+            .unwrap_or_else(|| {
+                log::info!(
+                    target: &self.log_target(),
+                    "Starting a new schedule of {} tranches",
+                    self.tranches
+                );
+                Schedule {
+                    started_at: now,
+                    total_amount: self.total_amount,
+                    tranches: self.tranches,
+                    submitted: vec![],
+                    stopped: false,
+                }
+            })
+    }
+}
+
+impl fmt::Display for TrancheStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {}: {:.2} in {} tranches over {} min, {} days @ min {} per day",
+            self.name(),
+            self.currency,
+            self.total_amount,
+            self.tranches,
+            self.duration / 60,
+            self.period,
+            self.rate_display.rate(self.min_rate)
+        )
+    }
+}
+
+impl FromConfig for TrancheStrategy {
+    fn from_config(files: &ConfigFiles, shared: &Shared) -> Result<Vec<Self>> {
+        #[derive(Debug, Deserialize)]
+        struct Strategy {
+            keys: String,
+            currency: String,
+            /// Funding symbol, defaults to `f{currency}`.
+            #[serde(default, alias = "funding_symbol")]
+            symbol: Option<String>,
+            total_amount: f64,
+            tranches: usize,
+            duration_minutes: u64,
+            period: u8,
+            min_rate: f64,
+            monitored_window: u64,
+            rate_selector: RateSelector,
+            #[serde(default)]
+            candle_timeframe: CandleTimeFrame,
+            #[serde(default = "rate_guard::default_max_rate")]
+            max_rate: f64,
+            #[serde(default = "rate_guard::default_sanity_max_apr")]
+            sanity_max_apr: f64,
+            #[serde(default = "common::default_enabled")]
+            enabled: bool,
+            #[serde(default = "common::default_hidden")]
+            hidden: bool,
+            #[serde(default = "rate_display::default_rate_decimals")]
+            rate_display_decimals: usize,
+            #[serde(default = "rate_display::default_apr_decimals")]
+            apr_display_decimals: usize,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[serde(default)]
+            tranche_strategies: HashMap<String, Strategy>,
+        }
+
+        let config: Config = config::read(files)?;
+
+        config
+            .tranche_strategies
+            .into_iter()
+            .filter(|(name, strategy)| common::is_enabled(name, strategy.enabled))
+            .map(|(name, strategy)| {
+                ensure!(
+                    strategy.total_amount > 0.,
+                    "{name}: total_amount must be positive"
+                );
+                ensure!(strategy.tranches > 0, "{name}: tranches must be positive");
+                ensure!(
+                    strategy.min_rate >= 0.,
+                    "{name}: min_rate must not be negative"
+                );
+                ensure!(
+                    strategy.monitored_window > 0,
+                    "{name}: monitored_window must be positive"
+                );
+                common::validate_period(strategy.period)
+                    .with_context(|| format!("{name}: invalid period"))?;
+                strategy
+                    .rate_selector
+                    .validate()
+                    .with_context(|| format!("{name}: invalid rate_selector"))?;
+
+                let rate_display = RateDisplay::new(
+                    strategy.rate_display_decimals,
+                    strategy.apr_display_decimals,
+                );
+                rate_display
+                    .validate()
+                    .with_context(|| format!("{name}: invalid display decimals"))?;
+                let rate_guard = RateGuard::new(
+                    strategy.max_rate,
+                    strategy.sanity_max_apr,
+                    rate_display,
+                    shared.notifier.clone(),
+                );
+                rate_guard
+                    .validate()
+                    .with_context(|| format!("{name}: invalid rate ceilings"))?;
+
+                let account_budget = shared.budget.account(&strategy.keys, &strategy.currency);
+                let precision = shared.precisions.get(&strategy.currency);
+                let symbol = common::funding_symbol(&strategy.currency, strategy.symbol);
+                let owned_offers = OwnedOffers::load(
+                    &name,
+                    &strategy.currency,
+                    shared.state.clone(),
+                    rate_display,
+                );
+
+                Ok(Self::new(
+                    name,
+                    common::client_from_keys(&strategy.keys)?,
+                    strategy.currency,
+                    symbol,
+                    strategy.total_amount,
+                    strategy.tranches,
+                    strategy.duration_minutes * 60,
+                    strategy.period,
+                    strategy.min_rate,
+                    strategy.monitored_window,
+                    strategy.rate_selector,
+                    strategy.candle_timeframe,
+                    rate_guard,
+                    shared.state.clone(),
+                    precision,
+                    strategy.hidden,
+                    owned_offers,
+                    rate_display,
+                    account_budget,
+                ))
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid tranche_strategies in {files}"))
+    }
+}
+
+#[async_trait]
+impl Strategy for TrancheStrategy {
+    type Output = Result<()>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Cancel the offers submitted by the strategy.
+    async fn cancel_offers(&self) -> Self::Output {
+        self.owned_offers
+            .cancel_all(&self.client, &self.symbol, &self.log_target())
+            .await
+    }
+
+    /// Execute the strategy.
+    async fn execute(&self) -> Self::Output {
+        log::info!(target: &self.log_target(), "Executing {} on {}...", self.name(), self.currency);
+
+        let now = common::unix_time()?;
+        let mut schedule = self.schedule(now);
+
+        let funding_wallet = common::funding_wallet(&self.client, &self.currency).await?;
+        let active_offers = self
+            .owned_offers
+            .active_offers(&self.client, &self.symbol, &self.log_target())
+            .await?;
+
+        // The live tranches stay deployed: only the available balance can fund the next ones.
+        let available_balance = funding_wallet.available_balance;
+        let total_balance = funding_wallet.balance;
+        self.budget
+            .update(total_balance, total_balance - available_balance);
+
+        let live = schedule
+            .submitted
+            .iter()
+            .filter(|tranche| {
+                active_offers.iter().any(|offer| {
+                    offer.period == self.period
+                        && (offer.rate - tranche.rate).abs() < 1e-8
+                        && (offer.amount - tranche.amount).abs() <= 1.
+                })
+            })
+            .count();
+
+        log::info!(
+            target: &self.log_target(),
+            "{}/{} tranches submitted: {live} live, {} filled or canceled",
+            schedule.submitted.len(),
+            self.tranches,
+            schedule.submitted.len() - live
+        );
+
+        if schedule.stopped || schedule.submitted.len() >= self.tranches {
+            log::info!(target: &self.log_target(), "Schedule is over, nothing to do");
+            return Ok(());
+        }
+
+        let due = due_tranches(now - schedule.started_at, self.duration, self.tranches);
+        if schedule.submitted.len() >= due {
+            log::info!(
+                target: &self.log_target(),
+                "Tranche {}/{} is not due yet",
+                due + 1,
+                self.tranches
+            );
+            return Ok(());
+        }
+
+        let index = schedule.submitted.len() + 1;
+        let amount = self.total_amount / self.tranches as f64;
+
+        // Stop early if the funds got lent or moved elsewhere.
+        if available_balance < amount {
+            log::info!(
+                target: &self.log_target(),
+                "Stopping the schedule at tranche {index}/{}: {available_balance:.2} available < \
+                 {amount:.2}",
+                self.tranches
+            );
+            schedule.stopped = true;
+            return self.state.set(&self.state_key(), &schedule);
+        }
+
+        if self.budget.remaining() < amount {
+            log::info!(
+                target: &self.log_target(),
+                "Global deployment budget exhausted for {}, tranche {index}/{} postponed",
+                self.currency,
+                self.tranches
+            );
+            return Ok(());
+        }
+
+        // Take 99% of the fresh candle rate, but never less than the floor.
+        let rate = (common::candle_rate(
+            &self.client,
+            &self.symbol,
+            self.monitored_window,
+            &self.rate_selector,
+            self.period,
+            self.candle_timeframe,
+        )
+        .await?
+            * 0.99)
+            .max(self.min_rate);
+
+        let Some(params) =
+            self.rate_guard
+                .offer_params(amount, rate, self.period, &self.log_target())
+        else {
+            return Ok(());
+        };
+
+        let offer = common::submit_offer(
+            &self.client,
+            &self.symbol,
+            params,
+            self.precision,
+            self.hidden,
+            &self.log_target(),
+        )
+        .await?;
+        self.owned_offers.insert(offer.id)?;
+        self.budget.deploy(offer.amount);
+
+        log::info!(
+            target: &self.log_target(),
+            "Tranche {index}/{} submitted as offer {}: {:.2} for {} days @ {} per day \
+             ({} APR)",
+            self.tranches,
+            offer.id,
+            offer.amount,
+            offer.period,
+            self.rate_display.rate(offer.rate),
+            self.rate_display.apr(offer.rate * 365.)
+        );
+
+        schedule.submitted.push(Tranche {
+            rate: offer.rate,
+            amount: offer.amount,
+        });
+        self.state.set(&self.state_key(), &schedule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tranches_are_spread_over_the_duration() {
+        // 10 tranches over 5 hours: one every 30 minutes.
+        let duration = 5 * 3600;
+
+        assert_eq!(due_tranches(0, duration, 10), 1);
+        assert_eq!(due_tranches(1799, duration, 10), 1);
+        assert_eq!(due_tranches(1800, duration, 10), 2);
+        assert_eq!(due_tranches(4 * 3600 + 1800, duration, 10), 10);
+        assert_eq!(due_tranches(48 * 3600, duration, 10), 10);
+    }
+
+    #[test]
+    fn tranches_without_duration_are_all_due() {
+        assert_eq!(due_tranches(0, 0, 4), 4);
+    }
+}
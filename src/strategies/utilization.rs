@@ -0,0 +1,263 @@
+use std::fmt;
+
+use anyhow::{ensure, Result};
+use serde::Deserialize;
+
+/// A point of the utilization table.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct UtilizationPoint {
+    /// Fraction, within [0, 1], of the funding in use.
+    pub utilization: f64,
+    /// Multiplier applied to the rate at this utilization.
+    pub multiplier: f64,
+}
+
+/// Piecewise linear mapping from the funding utilization to a rate multiplier: hold out for
+/// higher rates when the demand is high, undercut when it is low.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct UtilizationTable {
+    points: Vec<UtilizationPoint>,
+}
+
+impl UtilizationTable {
+    #[cfg(test)]
+    fn new(points: &[(f64, f64)]) -> Self {
+        Self {
+            points: points
+                .iter()
+                .map(|&(utilization, multiplier)| UtilizationPoint {
+                    utilization,
+                    multiplier,
+                })
+                .collect(),
+        }
+    }
+
+    /// Ensure the table is not empty, sorted by utilization and its multipliers are positive.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(!self.points.is_empty(), "table must not be empty");
+
+        for point in &self.points {
+            ensure!(
+                (0. ..=1.).contains(&point.utilization),
+                "utilization {} must be within [0, 1]",
+                point.utilization
+            );
+            ensure!(
+                point.multiplier > 0.,
+                "multiplier of utilization {} must be positive",
+                point.utilization
+            );
+        }
+
+        ensure!(
+            self.points
+                .windows(2)
+                .all(|pair| pair[0].utilization < pair[1].utilization),
+            "utilizations must be strictly increasing"
+        );
+
+        Ok(())
+    }
+
+    /// Return the multiplier at `utilization`, interpolated between the surrounding points and
+    /// clamped to the first and last ones.
+    pub fn multiplier(&self, utilization: f64) -> f64 {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return 1.;
+        };
+
+        if utilization <= first.utilization {
+            return first.multiplier;
+        }
+        if utilization >= last.utilization {
+            return last.multiplier;
+        }
+
+        let upper = self
+            .points
+            .iter()
+            .position(|point| point.utilization >= utilization)
+            .unwrap();
+        let (low, high) = (self.points[upper - 1], self.points[upper]);
+
+        let t = (utilization - low.utilization) / (high.utilization - low.utilization);
+        low.multiplier + t * (high.multiplier - low.multiplier)
+    }
+}
+
+impl fmt::Display for UtilizationTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let points: Vec<String> = self
+            .points
+            .iter()
+            .map(|point| format!("{:.0}%: x{}", point.utilization * 100., point.multiplier))
+            .collect();
+
+        write!(f, "{}", points.join(", "))
+    }
+}
+
+/// A bucket of the sizing table.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct SizingBucket {
+    /// Utilization from which the bucket applies.
+    pub min_utilization: f64,
+    /// Maximum fraction, within (0, 1], of the total balance per loan in this bucket.
+    pub fraction: f64,
+}
+
+/// Mapping from the funding utilization buckets to the maximum fraction of the balance per loan:
+/// deploy everything when the fills are nearly guaranteed, keep powder dry otherwise.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct SizingTable {
+    buckets: Vec<SizingBucket>,
+}
+
+impl SizingTable {
+    #[cfg(test)]
+    fn new(buckets: &[(f64, f64)]) -> Self {
+        Self {
+            buckets: buckets
+                .iter()
+                .map(|&(min_utilization, fraction)| SizingBucket {
+                    min_utilization,
+                    fraction,
+                })
+                .collect(),
+        }
+    }
+
+    /// Ensure the table is not empty, its utilizations strictly increasing, and its fractions
+    /// within (0, 1] and non-decreasing.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(!self.buckets.is_empty(), "table must not be empty");
+
+        for bucket in &self.buckets {
+            ensure!(
+                (0. ..=1.).contains(&bucket.min_utilization),
+                "utilization {} must be within [0, 1]",
+                bucket.min_utilization
+            );
+            ensure!(
+                bucket.fraction > 0. && bucket.fraction <= 1.,
+                "fraction of utilization {} must be within (0, 1]",
+                bucket.min_utilization
+            );
+        }
+
+        for pair in self.buckets.windows(2) {
+            ensure!(
+                pair[0].min_utilization < pair[1].min_utilization,
+                "utilizations must be strictly increasing"
+            );
+            ensure!(
+                pair[0].fraction <= pair[1].fraction,
+                "fractions must not decrease with the utilization"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Return the fraction of the highest bucket reached by `utilization`, or of the first
+    /// bucket if it is below all of them.
+    pub fn fraction(&self, utilization: f64) -> f64 {
+        self.buckets
+            .iter()
+            .rev()
+            .find(|bucket| utilization >= bucket.min_utilization)
+            .or(self.buckets.first())
+            .map_or(1., |bucket| bucket.fraction)
+    }
+}
+
+impl fmt::Display for SizingTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let buckets: Vec<String> = self
+            .buckets
+            .iter()
+            .map(|bucket| {
+                format!(
+                    "{:.0}%+: {:.0}%",
+                    bucket.min_utilization * 100.,
+                    bucket.fraction * 100.
+                )
+            })
+            .collect();
+
+        write!(f, "{}", buckets.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> UtilizationTable {
+        UtilizationTable::new(&[(0.5, 0.9), (0.8, 1.), (0.95, 1.2)])
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn multiplier_is_clamped_outside_the_table() {
+        assert_eq!(table().multiplier(0.), 0.9);
+        assert_eq!(table().multiplier(0.5), 0.9);
+        assert_eq!(table().multiplier(0.99), 1.2);
+    }
+
+    #[test]
+    fn multiplier_is_interpolated_between_points() {
+        assert_close(table().multiplier(0.8), 1.);
+        assert_close(table().multiplier(0.65), 0.95);
+        assert_close(table().multiplier(0.875), 1.1);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(table().validate().is_ok());
+        assert!(UtilizationTable::new(&[]).validate().is_err());
+        assert!(UtilizationTable::new(&[(0.8, 1.), (0.5, 0.9)])
+            .validate()
+            .is_err());
+        assert!(UtilizationTable::new(&[(1.5, 1.)]).validate().is_err());
+        assert!(UtilizationTable::new(&[(0.5, 0.)]).validate().is_err());
+    }
+
+    fn sizing() -> SizingTable {
+        SizingTable::new(&[(0., 0.1), (0.6, 0.3), (0.9, 1.)])
+    }
+
+    #[test]
+    fn sizing_uses_the_highest_bucket_reached() {
+        assert_eq!(sizing().fraction(0.), 0.1);
+        assert_eq!(sizing().fraction(0.59), 0.1);
+        assert_eq!(sizing().fraction(0.6), 0.3);
+        assert_eq!(sizing().fraction(0.95), 1.);
+    }
+
+    #[test]
+    fn sizing_below_the_first_bucket_uses_it() {
+        let sizing = SizingTable::new(&[(0.5, 0.2), (0.8, 0.5)]);
+
+        assert_eq!(sizing.fraction(0.1), 0.2);
+    }
+
+    #[test]
+    fn sizing_validation() {
+        assert!(sizing().validate().is_ok());
+        assert!(SizingTable::new(&[]).validate().is_err());
+        assert!(SizingTable::new(&[(0.5, 0.2), (0.4, 0.5)])
+            .validate()
+            .is_err());
+        assert!(SizingTable::new(&[(0.5, 0.5), (0.8, 0.2)])
+            .validate()
+            .is_err());
+        assert!(SizingTable::new(&[(0.5, 1.5)]).validate().is_err());
+    }
+}